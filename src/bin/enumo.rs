@@ -0,0 +1,359 @@
+/*!
+The `enumo` command-line tool: rule synthesis, derivability, validation and
+conversion from the shell, for the built-in domains.
+!*/
+
+use std::process::exit;
+
+use clap::{ArgEnum, Parser, Subcommand};
+use enumo::{
+    checkpoint,
+    enumo::{Metric, OpMaps, RuleFile, RuleStatus, Ruleset, RustExport, Scheduler},
+    halide::Pred,
+    rational::Math,
+    recipe::Recipe,
+    recipe_utils::{recursive_rules, Lang},
+    DeriveType, Limits, SynthLanguage, ValidationResult,
+};
+
+mod bv4 {
+    ::enumo::impl_bv!(4);
+}
+mod bv8 {
+    ::enumo::impl_bv!(8);
+}
+mod bv16 {
+    ::enumo::impl_bv!(16);
+}
+mod bv32 {
+    ::enumo::impl_bv!(32);
+}
+mod bv64 {
+    ::enumo::impl_bv!(64);
+}
+
+#[derive(Parser)]
+#[clap(
+    name = "enumo",
+    about = "Rewrite rule inference with equality saturation"
+)]
+struct Cli {
+    /// The domain the rules are over.
+    #[clap(long, short, arg_enum)]
+    domain: Domain,
+    /// Checkpoint synthesis steps in this directory, resuming from the
+    /// steps already there.
+    #[clap(long)]
+    checkpoint: Option<String>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// The registry of built-in domains. Other domains, such as those in the
+/// tests, are only available as a library.
+#[derive(Clone, Copy, ArgEnum)]
+enum Domain {
+    Bv4,
+    Bv8,
+    Bv16,
+    Bv32,
+    Bv64,
+    Rational,
+    Halide,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Learn rules by running a recipe file, or over the domain's operators
+    /// up to a term size.
+    Synth {
+        /// A recipe file, see `enumo::recipe`.
+        #[clap(long)]
+        recipe: Option<String>,
+        /// The term size, without a recipe.
+        #[clap(long, default_value = "5")]
+        atoms: usize,
+        /// Rules to start from.
+        #[clap(long)]
+        prior: Option<String>,
+        /// Where to write the rules, instead of standard output.
+        #[clap(long, short)]
+        output: Option<String>,
+    },
+    /// Check which rules of one file the rules of another can derive.
+    Derive {
+        /// The rules used for deriving.
+        using: String,
+        /// The rules to derive.
+        target: String,
+        #[clap(long, arg_enum, default_value = "lhs-and-rhs")]
+        derive_type: Derivation,
+    },
+    /// Check the soundness of each rule in a file.
+    Validate {
+        file: String,
+        /// Record the results in the file's metadata.
+        #[clap(long)]
+        update: bool,
+    },
+    /// Compare two rule files modulo renaming, direction and derivability.
+    Diff {
+        old: String,
+        new: String,
+        #[clap(long, arg_enum, default_value = "lhs-and-rhs")]
+        derive_type: Derivation,
+        #[clap(long)]
+        json: bool,
+    },
+    /// Write a rule file in another format.
+    Convert {
+        input: String,
+        /// A file, or a directory for `smtlib`.
+        output: String,
+        #[clap(long, arg_enum)]
+        to: Format,
+    },
+    /// Remove the rules of a file that follow from the others and a prior.
+    Minimize {
+        candidates: String,
+        #[clap(long)]
+        prior: Option<String>,
+        #[clap(long, short)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum Derivation {
+    Lhs,
+    LhsAndRhs,
+}
+
+impl From<Derivation> for DeriveType {
+    fn from(derivation: Derivation) -> Self {
+        match derivation {
+            Derivation::Lhs => DeriveType::Lhs,
+            Derivation::LhsAndRhs => DeriveType::LhsAndRhs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum Format {
+    /// The rule file format, see `RuleFile`.
+    Rules,
+    Rust,
+    Egglog,
+    Smtlib,
+    Lean,
+}
+
+/// What the tool needs to know about a domain besides its language.
+struct DomainInfo {
+    /// What `synth` enumerates.
+    lang: Lang,
+    /// Path of the language type, for Rust export.
+    rust_lang: &'static str,
+}
+
+fn bv_info() -> DomainInfo {
+    DomainInfo {
+        lang: Lang::new(
+            &["0", "1"],
+            &["a", "b", "c"],
+            &[&["~", "-"], &["&", "|", "*", "--", "+", "<<", ">>"]],
+        ),
+        rust_lang: "Bv",
+    }
+}
+
+fn rational_info() -> DomainInfo {
+    DomainInfo {
+        lang: Lang::new(
+            &["-1", "0", "1"],
+            &["a", "b", "c"],
+            &[&["~", "fabs"], &["+", "-", "*", "/"]],
+        ),
+        rust_lang: "Math",
+    }
+}
+
+fn halide_info() -> DomainInfo {
+    DomainInfo {
+        lang: Lang::new(
+            &["-1", "0", "1"],
+            &["a", "b", "c"],
+            &[
+                &["-", "!"],
+                &[
+                    "<", "<=", "==", "!=", "&&", "||", "+", "-", "*", "min", "max",
+                ],
+                &["select"],
+            ],
+        ),
+        rust_lang: "Pred",
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    // Progress is logged to stderr; set RUST_LOG=debug to also list the rules
+    // each step learns.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    if let Some(dir) = &cli.checkpoint {
+        checkpoint::enable(dir).unwrap_or_else(|e| {
+            eprintln!("{}: {}", dir, e);
+            exit(2)
+        });
+    }
+    match cli.domain {
+        Domain::Bv4 => run::<bv4::Bv>(cli.command, bv_info()),
+        Domain::Bv8 => run::<bv8::Bv>(cli.command, bv_info()),
+        Domain::Bv16 => run::<bv16::Bv>(cli.command, bv_info()),
+        Domain::Bv32 => run::<bv32::Bv>(cli.command, bv_info()),
+        Domain::Bv64 => run::<bv64::Bv>(cli.command, bv_info()),
+        Domain::Rational => run::<Math>(cli.command, rational_info()),
+        Domain::Halide => run::<Pred>(cli.command, halide_info()),
+    }
+}
+
+/// Read a rule file, exiting with the I/O or parse error.
+fn read<L: SynthLanguage>(filename: &str) -> RuleFile<L> {
+    let contents = std::fs::read_to_string(filename).unwrap_or_else(|e| {
+        eprintln!("{}: {}", filename, e);
+        exit(2)
+    });
+    RuleFile::parse(&contents).unwrap_or_else(|e| {
+        eprintln!("{}:{}", filename, e);
+        exit(2)
+    })
+}
+
+fn write<L: SynthLanguage>(rules: &Ruleset<L>, output: Option<String>) {
+    let file = RuleFile::from(rules);
+    match output {
+        Some(filename) => write_string(&filename, file.to_string()),
+        None => print!("{}", file),
+    }
+}
+
+fn run<L: SynthLanguage + OpMaps>(command: Command, info: DomainInfo) {
+    match command {
+        Command::Synth {
+            recipe,
+            atoms,
+            prior,
+            output,
+        } => {
+            let mut rules = prior.map_or_else(Ruleset::default, |p| read::<L>(&p).ruleset());
+            match recipe {
+                Some(filename) => {
+                    let recipe = Recipe::from_file(&filename).unwrap_or_else(|e| {
+                        eprintln!("{}: {}", filename, e);
+                        exit(2)
+                    });
+                    rules.extend(recipe.run(rules.clone()));
+                }
+                None => rules = recursive_rules(Metric::Atoms, atoms, info.lang, rules),
+            }
+            write(&rules, output);
+        }
+        Command::Derive {
+            using,
+            target,
+            derive_type,
+        } => {
+            let (r1, r2) = (read::<L>(&using).ruleset(), read::<L>(&target).ruleset());
+            let (can, cannot) = r1.derive_batched(derive_type.into(), &r2, Limits::deriving());
+            println!(
+                "Using {} ({}) to derive {} ({}).\nCan derive {}, cannot derive {}. Missing:",
+                using,
+                r1.len(),
+                target,
+                r2.len(),
+                can.len(),
+                cannot.len()
+            );
+            print!("{}", cannot);
+        }
+        Command::Validate { file, update } => {
+            let mut rules = read::<L>(&file);
+            let mut invalid = 0;
+            for entry in &mut rules.entries {
+                let status = match entry.rule.validate() {
+                    ValidationResult::Valid => RuleStatus::Valid,
+                    ValidationResult::Invalid(_) => RuleStatus::Invalid,
+                    ValidationResult::Unknown => RuleStatus::Unknown,
+                };
+                if status == RuleStatus::Invalid {
+                    invalid += 1;
+                }
+                println!("{:?}\t{}", status, entry.rule);
+                entry.meta.status = Some(status);
+            }
+            if update {
+                write_string(&file, rules.to_string());
+            }
+            if invalid > 0 {
+                eprintln!("{} invalid rules", invalid);
+                exit(1);
+            }
+        }
+        Command::Diff {
+            old,
+            new,
+            derive_type,
+            json,
+        } => {
+            let (old, new) = (read::<L>(&old).ruleset(), read::<L>(&new).ruleset());
+            let diff = old.diff(&new, derive_type.into(), Limits::deriving());
+            if json {
+                println!("{}", diff.to_json());
+            } else {
+                print!("{}", diff);
+            }
+        }
+        Command::Convert { input, output, to } => {
+            let file = read::<L>(&input);
+            let rules = file.ruleset();
+            match to {
+                Format::Rules => write_string(&output, file.to_string()),
+                Format::Rust => {
+                    let rust = rules
+                        .to_rust(&RustExport::new(info.rust_lang))
+                        .unwrap_or_else(|e| {
+                            eprintln!("{}: {}", input, e);
+                            exit(2)
+                        });
+                    write_string(&output, rust)
+                }
+                Format::Egglog => write_string(&output, rules.to_egglog(&L::egglog())),
+                Format::Smtlib => rules
+                    .to_smtlib_dir(&output, &L::smtlib())
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}: {}", output, e);
+                        exit(2)
+                    }),
+                Format::Lean => write_string(&output, rules.to_lean(&L::lean(), "rule")),
+            }
+        }
+        Command::Minimize {
+            candidates,
+            prior,
+            output,
+        } => {
+            let mut candidates = read::<L>(&candidates).ruleset();
+            let prior = prior.map_or_else(Ruleset::default, |p| read::<L>(&p).ruleset());
+            let minimized = candidates.minimize(prior, Scheduler::Compress(Limits::minimize()));
+            write(&minimized.chosen, output);
+        }
+    }
+}
+
+/// Write a file, exiting with the I/O error.
+fn write_string(filename: &str, s: String) {
+    std::fs::write(filename, s).unwrap_or_else(|e| {
+        eprintln!("{}: {}", filename, e);
+        exit(2)
+    });
+}
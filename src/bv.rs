@@ -126,15 +126,42 @@ impl<const N: Inner> From<Inner> for BV<N> {
     }
 }
 
+/// Bitvectors up to this width are validated exhaustively instead of with z3.
+pub const EXHAUSTIVE_BV_WIDTH: Inner = 4;
+
+/// Widest bitvectors whose values `FiniteDomain` lists.
+pub const MAX_FINITE_BV_WIDTH: Inner = 16;
+
+/// Lists the `2^N` values of bitvectors up to `MAX_FINITE_BV_WIDTH` bits.
+impl<const N: Inner> crate::FiniteDomain for BV<N> {
+    fn all_values() -> Option<Vec<Self>> {
+        if N > MAX_FINITE_BV_WIDTH {
+            return None;
+        }
+        Some((0..(1 as Inner) << N).map(Self::new).collect())
+    }
+}
+
+/// The 4-bit domain used by the unit tests.
+#[cfg(test)]
+pub(crate) mod bv4 {
+    crate::impl_bv!(4);
+}
+
 // Macro for specializing BV to different sized bitvectors
 #[macro_export]
 macro_rules! impl_bv {
     ($n:literal) => {
         use $crate::*;
 
+        // Not all used here, but in scope for the crates invoking the macro
+        #[allow(unused_imports)]
         use rand::prelude::*;
+        #[allow(unused_imports)]
         use rand_pcg::Pcg64;
+        #[allow(unused_imports)]
         use serde::{Deserialize, Serialize};
+        #[allow(unused_imports)]
         use std::fmt;
         use std::ops::*;
 
@@ -159,6 +186,7 @@ macro_rules! impl_bv {
 
         impl SynthLanguage for Bv {
             type Constant = BV;
+            const NAME: &'static str = concat!("bv", stringify!($n));
 
             fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
             where
@@ -184,7 +212,7 @@ macro_rules! impl_bv {
                 }
             }
 
-            fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
+            fn mk_interval<'a, F>(&'a self, _get_interval: F) -> Interval<Self::Constant>
             where
                 F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
             {
@@ -229,7 +257,7 @@ macro_rules! impl_bv {
                 consts.sort();
                 consts.dedup();
 
-                let mut cvecs = self_product(&consts, vars.len());
+                let cvecs = self_product(&consts, vars.len());
 
                 egraph.analysis.cvec_len = cvecs[0].len();
 
@@ -242,9 +270,13 @@ macro_rules! impl_bv {
             fn validate(
                 lhs: &Pattern<Self>,
                 rhs: &Pattern<Self>,
-            ) -> ValidationResult {
+            ) -> ValidationResult<Self> {
                 use z3::{*, ast::Ast};
 
+                if $n <= $crate::EXHAUSTIVE_BV_WIDTH {
+                    return $crate::exhaustive_validate(lhs, rhs);
+                }
+
                 fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Bv]) -> z3::ast::BV<'a> {
                     let mut buf: Vec<z3::ast::BV> = vec![];
                     for node in expr.as_ref().iter() {
@@ -266,18 +298,102 @@ macro_rules! impl_bv {
                     buf.pop().unwrap()
                 }
 
-                let mut cfg = z3::Config::new();
-                cfg.set_timeout_msec(1000);
-                let ctx = z3::Context::new(&cfg);
-                let solver = z3::Solver::new(&ctx);
-                let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-                let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-                solver.assert(&lexpr._eq(&rexpr).not());
-                match solver.check() {
-                    SatResult::Sat => ValidationResult::Invalid,
-                    SatResult::Unsat => ValidationResult::Valid,
-                    SatResult::Unknown => ValidationResult::Unknown
-                }
+                $crate::with_z3_context(|ctx| {
+                    let solver = $crate::z3_solver(ctx);
+                    let l_inst = Self::instantiate(lhs);
+                    let r_inst = Self::instantiate(rhs);
+                    let lexpr = egg_to_z3(ctx, l_inst.as_ref());
+                    let rexpr = egg_to_z3(ctx, r_inst.as_ref());
+                    solver.assert(&lexpr._eq(&rexpr).not());
+                    match solver.check() {
+                        SatResult::Sat => {
+                            let mut vars: Vec<$crate::Symbol> = vec![];
+                            for node in l_inst.as_ref().iter().chain(r_inst.as_ref()) {
+                                if let Bv::Var(v) = node {
+                                    if !vars.contains(v) {
+                                        vars.push(*v);
+                                    }
+                                }
+                            }
+                            let cex = solver.get_model().and_then(|model| {
+                                let assignment = vars
+                                    .into_iter()
+                                    .map(|v| {
+                                        let var = z3::ast::BV::new_const(ctx, v.to_string(), $n);
+                                        let val = model.eval(&var)?.as_u64()?;
+                                        Some((v, BV::from(val as u128)))
+                                    })
+                                    .collect::<Option<Vec<_>>>()?;
+                                Some($crate::Counterexample::new(lhs, rhs, assignment))
+                            });
+                            ValidationResult::Invalid(cex)
+                        }
+                        SatResult::Unsat => ValidationResult::Valid,
+                        SatResult::Unknown => ValidationResult::Unknown
+                    }
+                })
+            }
+        }
+
+        impl $crate::enumo::OpMaps for Bv {
+            fn smtlib() -> $crate::enumo::OpMap {
+                let mut map = $crate::enumo::OpMap::new(
+                    &format!("(_ BitVec {})", $n),
+                    &[
+                        ("+", "bvadd"),
+                        ("--", "bvsub"),
+                        ("*", "bvmul"),
+                        ("-", "bvneg"),
+                        ("~", "bvnot"),
+                        ("<<", "bvshl"),
+                        (">>", "bvlshr"),
+                        ("&", "bvand"),
+                        ("|", "bvor"),
+                        ("^", "bvxor"),
+                    ],
+                );
+                map.literal = |c| format!("(_ bv{} {})", c, $n);
+                map
+            }
+
+            fn egglog() -> $crate::enumo::OpMap {
+                let mut map = $crate::enumo::OpMap::new(
+                    "Bv",
+                    &[
+                        ("+", "Add"),
+                        ("--", "Sub"),
+                        ("*", "Mul"),
+                        ("-", "Neg"),
+                        ("~", "Not"),
+                        ("<<", "Shl"),
+                        (">>", "Shr"),
+                        ("&", "And"),
+                        ("|", "Or"),
+                        ("^", "Xor"),
+                    ],
+                );
+                map.literal = |c| format!("(Lit {})", c);
+                map
+            }
+
+            fn lean() -> $crate::enumo::OpMap {
+                let mut map = $crate::enumo::OpMap::new(
+                    &format!("BitVec {}", $n),
+                    &[
+                        ("+", "({0} + {1})"),
+                        ("--", "({0} - {1})"),
+                        ("*", "({0} * {1})"),
+                        ("-", "(-{0})"),
+                        ("~", "(~~~{0})"),
+                        ("<<", "({0} <<< {1})"),
+                        (">>", "({0} >>> {1})"),
+                        ("&", "({0} &&& {1})"),
+                        ("|", "({0} ||| {1})"),
+                        ("^", "({0} ^^^ {1})"),
+                    ],
+                );
+                map.literal = |c| format!("({} : BitVec {})", c, $n);
+                map
             }
         }
     };
@@ -301,4 +417,13 @@ pub mod tests {
         assert_eq!(BV4::MIN.wrapping_mul(BV::NEG_ONE), BV::MIN);
         assert_eq!(BV4::MIN.wrapping_neg(), BV::MIN);
     }
+
+    #[test]
+    fn wide_bvs_are_not_listed() {
+        use crate::FiniteDomain;
+
+        assert_eq!(BV4::all_values().unwrap().len(), 16);
+        assert!(BV::<64>::all_values().is_none());
+        assert!(BV::<128>::all_values().is_none());
+    }
 }
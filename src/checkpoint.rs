@@ -0,0 +1,261 @@
+//! Process-wide checkpointing of rule inference.
+//! Once enabled, every `run_workload` step, including each size step of
+//! `recursive_rules`, writes the rules it learns to the checkpoint directory.
+//! A step whose checkpoint exists is skipped and its rules are read back, so
+//! rerunning a recipe that crashed or timed out resumes after the last
+//! completed step.
+//!
+//! Steps are keyed by a hash of the domain's name, the workload's terms, the
+//! prior rules and the limits. Steps minimized with a custom `MinimizeConfig`
+//! (see `run_workload_with`) are not checkpointed, since the scorer cannot be
+//! hashed.
+//!
+//! A checkpoint holds the prior rules along with the ones the step learned,
+//! and is only resumed from if it has all of the step's prior rules.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    enumo::{RuleFile, Ruleset, Workload},
+    Limits, SynthLanguage,
+};
+
+static DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Checkpoint steps in `dir`, creating it if needed.
+pub fn enable(dir: impl Into<PathBuf>) -> io::Result<()> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir)?;
+    *DIR.lock().unwrap() = Some(dir);
+    Ok(())
+}
+
+pub fn disable() {
+    *DIR.lock().unwrap() = None;
+}
+
+/// The checkpoint directory, if checkpointing is enabled.
+pub fn dir() -> Option<PathBuf> {
+    DIR.lock().unwrap().clone()
+}
+
+/// The inputs that determine the rules a step learns.
+pub(crate) struct Step<'a, L: SynthLanguage> {
+    /// Which algorithm runs the step, e.g. `run_workload`.
+    pub kind: &'a str,
+    pub workload: &'a Workload,
+    pub prior: &'a Ruleset<L>,
+    pub prior_limits: Limits,
+    pub minimize_limits: Limits,
+    pub fast_match: bool,
+}
+
+impl<L: SynthLanguage> Step<'_, L> {
+    /// A hex digest of the step's inputs. The domain is hashed by its
+    /// `SynthLanguage::NAME`, the workload by its terms, and prior rules by
+    /// canonical name, in sorted order, so the key does not depend on where
+    /// the language is defined or how the workload and rules were built. The
+    /// digest is FNV-1a, which is the same on every platform and toolchain.
+    pub(crate) fn key(&self) -> String {
+        let mut prior: Vec<&str> = self.prior.0.keys().map(|name| name.as_ref()).collect();
+        prior.sort_unstable();
+        let terms: Vec<String> = self
+            .workload
+            .force()
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        let mut hash = FNV_OFFSET;
+        for part in [
+            L::NAME,
+            self.kind,
+            &terms.join("\n"),
+            &prior.join("\n"),
+            &canonical_limits(&self.prior_limits),
+            &canonical_limits(&self.minimize_limits),
+            &self.fast_match.to_string(),
+        ] {
+            for byte in part.bytes().chain([0]) {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn canonical_limits(limits: &Limits) -> String {
+    let time = limits
+        .time
+        .map_or("none".to_string(), |t| t.as_nanos().to_string());
+    let max_egraph_nodes = limits
+        .max_egraph_nodes
+        .map_or("none".to_string(), |n| n.to_string());
+    format!(
+        "iter={} node={} match={} time={} max_egraph_nodes={}",
+        limits.iter, limits.node, limits.match_, time, max_egraph_nodes
+    )
+}
+
+fn rules_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.rules", key))
+}
+
+/// The rules learned by a completed step, if checkpointing is enabled and the
+/// step has a readable checkpoint with all of its `prior` rules.
+pub(crate) fn load<L: SynthLanguage>(key: &str, prior: &Ruleset<L>) -> Option<Ruleset<L>> {
+    load_from(&dir()?, key, prior)
+}
+
+fn load_from<L: SynthLanguage>(dir: &Path, key: &str, prior: &Ruleset<L>) -> Option<Ruleset<L>> {
+    let contents = fs::read_to_string(rules_path(dir, key)).ok()?;
+    let rules = RuleFile::parse(&contents).ok()?.ruleset();
+    if !prior.iter().all(|rule| rules.contains(rule)) {
+        log::warn!("Ignoring checkpoint {}, which lacks prior rules", key);
+        return None;
+    }
+    let (_, learned) = rules.partition(|rule| prior.contains(rule));
+    Some(learned)
+}
+
+/// Record the rules a step learned from `prior`, if checkpointing is enabled.
+///
+/// The file starts with comments describing the step, followed by the prior
+/// and the learned rules. It is written under a temporary name and then
+/// renamed, so a crash while writing never leaves a partial checkpoint behind.
+pub(crate) fn save<L: SynthLanguage>(key: &str, prior: &Ruleset<L>, rules: &Ruleset<L>, secs: f64) {
+    let Some(dir) = dir() else { return };
+    if let Err(e) = save_to(&dir, key, prior, rules, secs) {
+        log::warn!("Failed to write checkpoint {}: {}", key, e);
+    }
+}
+
+fn save_to<L: SynthLanguage>(
+    dir: &Path,
+    key: &str,
+    prior: &Ruleset<L>,
+    rules: &Ruleset<L>,
+    secs: f64,
+) -> io::Result<()> {
+    let contents = format!(
+        "# step {}\n# learned {} rules in {:.3}s using {} prior rules\n{}",
+        key,
+        rules.len(),
+        secs,
+        prior.len(),
+        RuleFile::from(&prior.union(rules))
+    );
+    let tmp = dir.join(format!("{}.tmp", key));
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, rules_path(dir, key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn keys_depend_on_inputs() {
+        let workload = Workload::new(["(+ a b)", "(+ b a)"]);
+        let prior: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a 0) ==> ?a", "(* ?a 1) ==> ?a"]);
+        let step = Step {
+            kind: "run_workload",
+            workload: &workload,
+            prior: &prior,
+            prior_limits: Limits::synthesis(),
+            minimize_limits: Limits::minimize(),
+            fast_match: true,
+        };
+        let key = step.key();
+
+        let reordered: Ruleset<SymbolLang> = Ruleset::new(["(* ?x 1) ==> ?x", "(+ ?a 0) ==> ?a"]);
+        assert_eq!(
+            Step {
+                prior: &reordered,
+                ..step
+            }
+            .key(),
+            key
+        );
+
+        let fewer = Limits {
+            iter: 1,
+            ..Limits::synthesis()
+        };
+        assert_ne!(
+            Step {
+                prior_limits: fewer,
+                ..step
+            }
+            .key(),
+            key
+        );
+        let other = Workload::new(["(+ a b)"]);
+        assert_ne!(
+            Step {
+                workload: &other,
+                ..step
+            }
+            .key(),
+            key
+        );
+        assert_ne!(
+            Step {
+                prior: &Ruleset::default(),
+                ..step
+            }
+            .key(),
+            key
+        );
+    }
+
+    #[test]
+    fn keys_are_stable() {
+        let workload = Workload::new(["(+ a b)"]);
+        let step = Step {
+            kind: "run_workload",
+            workload: &workload,
+            prior: &Ruleset::<SymbolLang>::default(),
+            prior_limits: Limits::synthesis(),
+            minimize_limits: Limits::minimize(),
+            fast_match: false,
+        };
+        // Checkpoints written by earlier builds must still be found
+        assert_eq!(step.key(), "6d6e638f817ef75a");
+
+        let plugged = Workload::new(["(+ a x)"]).plug("x", &Workload::new(["b"]));
+        assert_eq!(
+            Step {
+                workload: &plugged,
+                ..step
+            }
+            .key(),
+            step.key()
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("enumo_checkpoint_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let prior: Ruleset<SymbolLang> = Ruleset::new(["(* ?a 1) ==> ?a"]);
+        let rules: Ruleset<SymbolLang> =
+            Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)", "(+ ?a 0) <=> ?a"]);
+        assert!(load_from::<SymbolLang>(&dir, "missing", &prior).is_none());
+        save_to(&dir, "0123", &prior, &rules, 1.5).unwrap();
+        assert_eq!(load_from(&dir, "0123", &prior), Some(rules.clone()));
+        assert!(!dir.join("0123.tmp").exists());
+
+        // A checkpoint of a step with other prior rules is not resumed from
+        let other: Ruleset<SymbolLang> = Ruleset::new(["(* ?a 0) ==> 0"]);
+        assert!(load_from(&dir, "0123", &other).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,597 @@
+use std::sync::Arc;
+
+use egg::{ENodeOrVar, Id, PatternAst, Var};
+
+use crate::{HashMap, HashSet, Pattern, SynthLanguage, ValidationResult};
+
+use super::{contains_either_way, Oriented, Rule, Ruleset, TermOrder};
+
+/// Limits for `Ruleset::complete`.
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionConfig {
+    /// Rounds of critical pair computation before giving up.
+    pub max_rounds: usize,
+    /// Stop adding rules once the ruleset has this many.
+    pub max_rules: usize,
+    /// Rewrites allowed when normalizing a single term.
+    pub max_steps: usize,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            max_rounds: 10,
+            max_rules: 200,
+            max_steps: 10_000,
+        }
+    }
+}
+
+/// A critical pair: the two results of rewriting the most general term on
+/// which the lhs of one rule overlaps with the lhs of another.
+#[derive(Debug, Clone)]
+pub struct CriticalPair<L: SynthLanguage> {
+    pub lhs: Pattern<L>,
+    pub rhs: Pattern<L>,
+    /// The overlapping rules: the one rewriting at the root first.
+    pub rules: (Arc<str>, Arc<str>),
+}
+
+/// Why a critical pair could not be turned into a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unjoinable {
+    /// The sides are equivalent but incomparable in the reduction order.
+    Unorientable,
+    /// The validator found the sides not to be equivalent.
+    Invalid,
+    /// The validator could not decide.
+    Unknown,
+    /// The ruleset already had `max_rules` rules.
+    RuleLimit,
+}
+
+/// The outcome of `Ruleset::complete`.
+#[derive(Debug, Clone)]
+pub struct Completion<L: SynthLanguage> {
+    /// The completed, oriented ruleset.
+    pub rules: Ruleset<L>,
+    /// Rules of `rules` that are not in the input, in either direction.
+    pub added: Ruleset<L>,
+    /// Rules of the input that are not in `rules`, in either direction.
+    /// This includes the input rules that could not be oriented.
+    pub removed: Ruleset<L>,
+    /// Input rules that could not be oriented, see `Ruleset::orient`.
+    pub unorientable: Ruleset<L>,
+    /// Critical pairs that neither joined nor became rules, normalized.
+    pub failed: Vec<(CriticalPair<L>, Unjoinable)>,
+    /// Whether every critical pair of `rules` joins, i.e., `rules` is
+    /// confluent as well as terminating.
+    pub complete: bool,
+}
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// Knuth-Bendix completion.
+    ///
+    /// Orients the ruleset with `order`, then repeatedly computes the critical
+    /// pairs of the rules and normalizes both sides of each. Pairs that do not
+    /// join are oriented and checked with the domain's validator, and added
+    /// as rules if they are valid. After each added rule, rules whose lhs the
+    /// new rule rewrites are removed and their rhs are normalized, so the
+    /// result stays small.
+    ///
+    /// Conditional rules do not take part, and are kept as they are.
+    pub fn complete(&self, order: &impl TermOrder<L>, config: &CompletionConfig) -> Completion<L> {
+        let (conditional, unconditional) = self.partition(|rule| rule.is_conditional());
+        let Oriented {
+            rules: mut trs,
+            unorientable,
+        } = unconditional.orient(order);
+
+        let mut failed = vec![];
+        let mut checked: HashSet<(Arc<str>, Arc<str>)> = HashSet::default();
+        let mut complete = false;
+        for _ in 0..config.max_rounds {
+            let mut pairs = vec![];
+            for r1 in trs.iter() {
+                for r2 in trs.iter() {
+                    if checked.insert((r1.name.clone(), r2.name.clone())) {
+                        pairs.extend(critical_pairs(r1, r2));
+                    }
+                }
+            }
+            if pairs.is_empty() {
+                complete = true;
+                break;
+            }
+            for pair in pairs {
+                let system = System::new(trs.iter(), config.max_steps);
+                let (lhs, rhs) = (
+                    system.normalize(&Term::from_pattern(&pair.lhs)),
+                    system.normalize(&Term::from_pattern(&pair.rhs)),
+                );
+                if lhs == rhs {
+                    continue;
+                }
+                let pair = CriticalPair {
+                    lhs: lhs.to_pattern(),
+                    rhs: rhs.to_pattern(),
+                    rules: pair.rules,
+                };
+                if trs.len() >= config.max_rules {
+                    failed.push((pair, Unjoinable::RuleLimit));
+                    continue;
+                }
+                let rule = match orient_pair(&pair.lhs, &pair.rhs, order) {
+                    Some(rule) => rule,
+                    None => {
+                        failed.push((pair, Unjoinable::Unorientable));
+                        continue;
+                    }
+                };
+                match rule.validate() {
+                    ValidationResult::Valid => interreduce(&mut trs, rule, order, config),
+                    ValidationResult::Invalid(_) => failed.push((pair, Unjoinable::Invalid)),
+                    ValidationResult::Unknown => failed.push((pair, Unjoinable::Unknown)),
+                }
+            }
+        }
+
+        // Later rules may join pairs that failed earlier
+        let system = System::new(trs.iter(), config.max_steps);
+        failed.retain(|(pair, _)| {
+            let lhs = system.normalize(&Term::from_pattern(&pair.lhs));
+            lhs != system.normalize(&Term::from_pattern(&pair.rhs))
+        });
+        let complete = complete && failed.is_empty();
+
+        trs.extend(conditional);
+        let (added, _) = trs.partition(|rule| !contains_either_way(self, rule));
+        let (removed, _) = self.partition(|rule| !contains_either_way(&trs, rule));
+        Completion {
+            rules: trs,
+            added,
+            removed,
+            unorientable,
+            failed,
+            complete,
+        }
+    }
+}
+
+/// The rule from the greater side to the smaller one, if the sides compare.
+fn orient_pair<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    order: &impl TermOrder<L>,
+) -> Option<Rule<L>> {
+    let rule = if order.greater(lhs, rhs) {
+        Rule::new(lhs, rhs)
+    } else if order.greater(rhs, lhs) {
+        Rule::new(rhs, lhs)
+    } else {
+        None
+    };
+    rule.map(|rule| rule.alpha_normalize().0)
+}
+
+/// Add `rule` to `trs`, then simplify the other rules with it. Rules whose lhs
+/// becomes reducible are removed, and added back oriented if their normalized
+/// sides still differ. Those rules are consequences of valid rules, so they
+/// are not validated again.
+fn interreduce<L: SynthLanguage>(
+    trs: &mut Ruleset<L>,
+    rule: Rule<L>,
+    order: &impl TermOrder<L>,
+    config: &CompletionConfig,
+) {
+    let mut pending = vec![rule];
+    while let Some(rule) = pending.pop() {
+        if trs.contains(&rule) {
+            continue;
+        }
+        let new = System::new([&rule], config.max_steps);
+        let (collapsed, kept) = trs.partition(|r| new.reduces(&Term::from_pattern(&r.lhs)));
+        *trs = kept;
+        trs.add(rule);
+
+        let system = System::new(trs.iter(), config.max_steps);
+        let mut simplified = Ruleset::default();
+        for r in trs.iter() {
+            let rhs = system.normalize(&Term::from_pattern(&r.rhs)).to_pattern();
+            match Rule::new(&r.lhs, &rhs) {
+                Some(r) => simplified.add(r.alpha_normalize().0),
+                None => simplified.add(r.clone()),
+            }
+        }
+        *trs = simplified;
+
+        for r in collapsed.iter() {
+            let lhs = system.normalize(&Term::from_pattern(&r.lhs));
+            let rhs = system.normalize(&Term::from_pattern(&r.rhs));
+            if lhs != rhs {
+                pending.extend(orient_pair(&lhs.to_pattern(), &rhs.to_pattern(), order));
+            }
+        }
+    }
+}
+
+/// All critical pairs of `r2` into `r1`: overlaps of the lhs of `r2` with a
+/// non-variable subterm of the lhs of `r1`. A rule trivially overlaps with
+/// itself at the root, which is skipped.
+fn critical_pairs<L: SynthLanguage>(r1: &Rule<L>, r2: &Rule<L>) -> Vec<CriticalPair<L>> {
+    let (l1, rhs1) = (Term::from_pattern(&r1.lhs), Term::from_pattern(&r1.rhs));
+    let (l2, rhs2) = (Term::renamed_apart(&r2.lhs), Term::renamed_apart(&r2.rhs));
+    let mut pairs = vec![];
+    for path in l1.positions() {
+        if path.is_empty() && r1.name == r2.name {
+            continue;
+        }
+        let mut subst = HashMap::default();
+        if let Term::App(..) = l1.at(&path) {
+            if unify(l1.at(&path), &l2, &mut subst) {
+                let lhs = rhs1.apply_unifier(&subst);
+                let rhs = l1.replace(&path, &rhs2).apply_unifier(&subst);
+                pairs.push(CriticalPair {
+                    lhs: lhs.to_pattern(),
+                    rhs: rhs.to_pattern(),
+                    rules: (r1.name.clone(), r2.name.clone()),
+                });
+            }
+        }
+    }
+    pairs
+}
+
+/// A pattern as a tree, for rewriting and unification outside of an e-graph.
+/// The children of the stored nodes are meaningless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term<L> {
+    Var(Var),
+    App(L, Vec<Term<L>>),
+}
+
+type Subst<L> = HashMap<Var, Term<L>>;
+
+impl<L: SynthLanguage> Term<L> {
+    fn from_pattern(pat: &Pattern<L>) -> Self {
+        Self::from_ast(pat.ast.as_ref(), pat.ast.as_ref().len() - 1)
+    }
+
+    fn from_ast(nodes: &[ENodeOrVar<L>], id: usize) -> Self {
+        match &nodes[id] {
+            ENodeOrVar::Var(v) => Term::Var(*v),
+            ENodeOrVar::ENode(n) => {
+                let args = n
+                    .children()
+                    .iter()
+                    .map(|c| Self::from_ast(nodes, usize::from(*c)))
+                    .collect();
+                Term::App(n.clone().map_children(|_| Id::from(0)), args)
+            }
+        }
+    }
+
+    /// The pattern with its variables renamed so they do not clash with those
+    /// of another rule.
+    fn renamed_apart(pat: &Pattern<L>) -> Self {
+        Self::from_pattern(pat).map_vars(&|v| format!("{}_", v).parse().unwrap())
+    }
+
+    fn map_vars(&self, f: &impl Fn(&Var) -> Var) -> Self {
+        match self {
+            Term::Var(v) => Term::Var(f(v)),
+            Term::App(op, args) => {
+                Term::App(op.clone(), args.iter().map(|a| a.map_vars(f)).collect())
+            }
+        }
+    }
+
+    fn to_pattern(&self) -> Pattern<L> {
+        let mut ast = PatternAst::default();
+        self.add_to(&mut ast);
+        ast.into()
+    }
+
+    fn add_to(&self, ast: &mut PatternAst<L>) -> Id {
+        match self {
+            Term::Var(v) => ast.add(ENodeOrVar::Var(*v)),
+            Term::App(op, args) => {
+                let mut ids = args
+                    .iter()
+                    .map(|a| a.add_to(ast))
+                    .collect::<Vec<_>>()
+                    .into_iter();
+                ast.add(ENodeOrVar::ENode(
+                    op.clone().map_children(|_| ids.next().unwrap()),
+                ))
+            }
+        }
+    }
+
+    /// Paths to every subterm, in pre-order.
+    fn positions(&self) -> Vec<Vec<usize>> {
+        let mut positions = vec![vec![]];
+        if let Term::App(_, args) = self {
+            for (i, arg) in args.iter().enumerate() {
+                for mut path in arg.positions() {
+                    path.insert(0, i);
+                    positions.push(path);
+                }
+            }
+        }
+        positions
+    }
+
+    fn at(&self, path: &[usize]) -> &Self {
+        match (self, path) {
+            (_, []) => self,
+            (Term::App(_, args), [i, rest @ ..]) => args[*i].at(rest),
+            (Term::Var(_), _) => panic!("no subterm at {:?}", path),
+        }
+    }
+
+    fn replace(&self, path: &[usize], new: &Self) -> Self {
+        match (self, path) {
+            (_, []) => new.clone(),
+            (Term::App(op, args), [i, rest @ ..]) => {
+                let mut args = args.clone();
+                args[*i] = args[*i].replace(rest, new);
+                Term::App(op.clone(), args)
+            }
+            (Term::Var(_), _) => panic!("no subterm at {:?}", path),
+        }
+    }
+
+    /// Substitute the variables bound by a match.
+    fn apply(&self, subst: &Subst<L>) -> Self {
+        self.substitute(subst, false)
+    }
+
+    /// Substitute the variables bound by a unifier, whose bindings may refer
+    /// to other bound variables.
+    fn apply_unifier(&self, subst: &Subst<L>) -> Self {
+        self.substitute(subst, true)
+    }
+
+    fn substitute(&self, subst: &Subst<L>, resolve: bool) -> Self {
+        match self {
+            Term::Var(v) => match subst.get(v) {
+                Some(t) if resolve => t.apply_unifier(subst),
+                Some(t) => t.clone(),
+                None => self.clone(),
+            },
+            Term::App(op, args) => Term::App(
+                op.clone(),
+                args.iter().map(|a| a.substitute(subst, resolve)).collect(),
+            ),
+        }
+    }
+
+    fn occurs(&self, v: &Var, subst: &Subst<L>) -> bool {
+        match self {
+            Term::Var(w) => w == v || subst.get(w).is_some_and(|t| t.occurs(v, subst)),
+            Term::App(_, args) => args.iter().any(|a| a.occurs(v, subst)),
+        }
+    }
+
+    /// Match `self` as a pattern against `term`, whose variables are treated
+    /// as constants.
+    fn matches(&self, term: &Self, subst: &mut Subst<L>) -> bool {
+        match (self, term) {
+            (Term::Var(v), _) => match subst.get(v) {
+                Some(bound) => bound == term,
+                None => {
+                    subst.insert(*v, term.clone());
+                    true
+                }
+            },
+            (Term::App(f, fs), Term::App(g, gs)) => {
+                f == g && fs.iter().zip(gs).all(|(a, b)| a.matches(b, subst))
+            }
+            (Term::App(..), Term::Var(_)) => false,
+        }
+    }
+}
+
+/// Follow the bindings of a variable.
+fn resolve<'a, L>(mut t: &'a Term<L>, subst: &'a Subst<L>) -> &'a Term<L> {
+    while let Term::Var(v) = t {
+        match subst.get(v) {
+            Some(bound) => t = bound,
+            None => break,
+        }
+    }
+    t
+}
+
+/// Extend `subst` to a most general unifier of `a` and `b`.
+fn unify<L: SynthLanguage>(a: &Term<L>, b: &Term<L>, subst: &mut Subst<L>) -> bool {
+    match (resolve(a, subst).clone(), resolve(b, subst).clone()) {
+        (Term::Var(x), Term::Var(y)) if x == y => true,
+        (Term::Var(x), t) | (t, Term::Var(x)) => {
+            if t.occurs(&x, subst) {
+                return false;
+            }
+            subst.insert(x, t);
+            true
+        }
+        (Term::App(f, fs), Term::App(g, gs)) => {
+            f == g && fs.iter().zip(&gs).all(|(a, b)| unify(a, b, subst))
+        }
+    }
+}
+
+/// A term rewriting system made of the unconditional rules of a ruleset.
+struct System<L> {
+    rules: Vec<(Term<L>, Term<L>)>,
+    max_steps: usize,
+}
+
+impl<L: SynthLanguage> System<L> {
+    fn new<'a>(rules: impl IntoIterator<Item = &'a Rule<L>>, max_steps: usize) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter(|rule| !rule.is_conditional())
+            .map(|rule| (Term::from_pattern(&rule.lhs), Term::from_pattern(&rule.rhs)))
+            .collect();
+        Self { rules, max_steps }
+    }
+
+    /// The normal form of `term`, rewriting innermost first. Gives up after
+    /// `max_steps` rewrites and returns the term reached.
+    fn normalize(&self, term: &Term<L>) -> Term<L> {
+        let mut steps = self.max_steps;
+        self.normalize_with(term, &mut steps)
+    }
+
+    fn normalize_with(&self, term: &Term<L>, steps: &mut usize) -> Term<L> {
+        let term = match term {
+            Term::Var(_) => return term.clone(),
+            Term::App(op, args) => Term::App(
+                op.clone(),
+                args.iter().map(|a| self.normalize_with(a, steps)).collect(),
+            ),
+        };
+        if *steps == 0 {
+            return term;
+        }
+        for (lhs, rhs) in &self.rules {
+            let mut subst = HashMap::default();
+            if lhs.matches(&term, &mut subst) {
+                *steps -= 1;
+                return self.normalize_with(&rhs.apply(&subst), steps);
+            }
+        }
+        term
+    }
+
+    /// Whether some rule rewrites `term` or one of its subterms.
+    fn reduces(&self, term: &Term<L>) -> bool {
+        let at_root = self
+            .rules
+            .iter()
+            .any(|(lhs, _)| lhs.matches(term, &mut HashMap::default()));
+        at_root
+            || match term {
+                Term::Var(_) => false,
+                Term::App(_, args) => args.iter().any(|a| self.reduces(a)),
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bv4,
+        enumo::{Lpo, Precedence},
+    };
+    use egg::SymbolLang;
+
+    #[test]
+    fn critical_pairs_of_associativity() {
+        let rules: Ruleset<SymbolLang> =
+            Ruleset::new(["(+ (+ ?a ?b) ?c) ==> (+ ?a (+ ?b ?c))", "(+ ?a 0) ==> ?a"]);
+        let assoc = rules.iter().next().unwrap();
+        let zero = rules.iter().nth(1).unwrap();
+        // Associativity overlaps with itself below the root
+        assert_eq!(critical_pairs(assoc, assoc).len(), 1);
+        let pairs = critical_pairs(zero, assoc);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].lhs.to_string(), "(+ ?a_ ?b_)");
+        assert_eq!(pairs[0].rhs.to_string(), "(+ ?a_ (+ ?b_ 0))");
+
+        let system = System::new(rules.iter(), 100);
+        let (lhs, rhs) = (
+            Term::from_pattern(&pairs[0].lhs),
+            Term::from_pattern(&pairs[0].rhs),
+        );
+        assert_eq!(system.normalize(&lhs), system.normalize(&rhs));
+    }
+
+    #[test]
+    fn joinable_rules_are_complete() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new([
+            "(+ (+ ?a ?b) ?c) <=> (+ ?a (+ ?b ?c))",
+            "(+ ?a 0) <=> ?a",
+            "(+ 0 ?a) <=> ?a",
+            "(+ ?a ?b) <=> (+ ?b ?a)",
+        ]);
+        let completion = rules.complete(
+            &Lpo::new(Precedence::new(["0", "+"])),
+            &CompletionConfig::default(),
+        );
+        assert!(completion.complete);
+        assert_eq!(completion.rules.len(), 3);
+        assert_eq!(completion.unorientable.len(), 1);
+        assert!(completion.added.is_empty());
+        // Commutativity is its own reverse
+        assert_eq!(completion.removed.len(), 1);
+    }
+
+    #[test]
+    fn invalid_pairs_are_reported() {
+        // SymbolLang rejects every rule
+        let rules: Ruleset<SymbolLang> =
+            Ruleset::new(["(+ (+ ?a ?b) ?c) ==> (+ ?a (+ ?b ?c))", "(+ ?a 0) ==> ?a"]);
+        let completion = rules.complete(
+            &Lpo::new(Precedence::new(["0", "+"])),
+            &CompletionConfig::default(),
+        );
+        assert!(!completion.complete);
+        assert_eq!(completion.rules.len(), 2);
+        let (pair, reason) = &completion.failed[0];
+        assert_eq!(*reason, Unjoinable::Invalid);
+        assert_eq!(pair.lhs.to_string(), "(+ ?a_ (+ 0 ?c))");
+        assert_eq!(pair.rhs.to_string(), "(+ ?a_ ?c)");
+    }
+
+    #[test]
+    fn complete_group_axioms() {
+        use bv4::Bv;
+
+        let axioms: Ruleset<Bv> = Ruleset::new([
+            "(+ 0 ?a) ==> ?a",
+            "(+ (- ?a) ?a) ==> 0",
+            "(+ (+ ?a ?b) ?c) ==> (+ ?a (+ ?b ?c))",
+        ]);
+        let lpo = Lpo::new(Precedence::new(["0", "+", "-"]));
+        let completion = axioms.complete(&lpo, &CompletionConfig::default());
+        assert!(completion.complete, "{:?}", completion.failed);
+        // The ten rules of the complete system for groups
+        assert_eq!(
+            completion.rules.len(),
+            10,
+            "{:?}",
+            completion.rules.to_str_vec()
+        );
+        assert!(completion.rules.contains(
+            &Rule::from_string("(- (+ ?a ?b)) ==> (+ (- ?b) (- ?a))")
+                .unwrap()
+                .0
+        ));
+    }
+
+    #[test]
+    fn rule_limit_is_not_complete() {
+        use bv4::Bv;
+
+        let axioms: Ruleset<Bv> = Ruleset::new([
+            "(+ 0 ?a) ==> ?a",
+            "(+ (- ?a) ?a) ==> 0",
+            "(+ (+ ?a ?b) ?c) ==> (+ ?a (+ ?b ?c))",
+        ]);
+        let lpo = Lpo::new(Precedence::new(["0", "+", "-"]));
+        let config = CompletionConfig {
+            max_rules: 4,
+            ..Default::default()
+        };
+        let completion = axioms.complete(&lpo, &config);
+        assert!(!completion.complete);
+        assert!(completion.rules.len() <= 4);
+        assert!(completion
+            .failed
+            .iter()
+            .any(|(_, reason)| *reason == Unjoinable::RuleLimit));
+    }
+}
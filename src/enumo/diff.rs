@@ -0,0 +1,153 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde_json::{json, Value};
+
+use crate::{DeriveType, Limits, SynthLanguage};
+
+use super::{Rule, Ruleset};
+
+/// The semantic difference between two rulesets, see `Ruleset::diff`.
+#[derive(Debug, Clone)]
+pub struct RulesetDiff<L: SynthLanguage> {
+    /// Rules of the new ruleset that are not in the old one.
+    pub added: Ruleset<L>,
+    /// Rules of the old ruleset that are not in the new one.
+    pub removed: Ruleset<L>,
+    /// Added rules that the old ruleset can derive.
+    pub added_derivable: Ruleset<L>,
+    /// Removed rules that the new ruleset can still derive.
+    pub removed_derivable: Ruleset<L>,
+}
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// Compare `self` (the old ruleset) with `new`.
+    ///
+    /// Rules are compared modulo alpha-renaming and direction, so a rule
+    /// only counts as added or removed if neither it nor its reverse is in
+    /// the other ruleset. Each added and removed rule is then checked for
+    /// derivability from the other ruleset with `can_derive`.
+    pub fn diff(&self, new: &Self, derive_type: DeriveType, limits: Limits) -> RulesetDiff<L> {
+        let missing_from = |rules: &Self, other: &Self| {
+            let (missing, _) = rules.partition(|rule| !contains_either_way(other, rule));
+            missing
+        };
+        let added = missing_from(new, self);
+        let removed = missing_from(self, new);
+        let (added_derivable, _) = self.derive_batched(derive_type, &added, limits);
+        let (removed_derivable, _) = new.derive_batched(derive_type, &removed, limits);
+        RulesetDiff {
+            added,
+            removed,
+            added_derivable,
+            removed_derivable,
+        }
+    }
+}
+
+pub(super) fn contains_either_way<L: SynthLanguage>(rules: &Ruleset<L>, rule: &Rule<L>) -> bool {
+    rules.contains(rule)
+        || rule
+            .reverse()
+            .is_some_and(|reverse| rules.contains(&reverse))
+}
+
+impl<L: SynthLanguage> RulesetDiff<L> {
+    /// Whether the two rulesets have the same rules.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let entries = |rules: &Ruleset<L>, derivable: &Ruleset<L>| -> Vec<Value> {
+            rules
+                .iter()
+                .map(|rule| {
+                    json!({
+                        "rule": rule.name.to_string(),
+                        "derivable": derivable.contains(rule),
+                    })
+                })
+                .collect()
+        };
+        json!({
+            "added": entries(&self.added, &self.added_derivable),
+            "removed": entries(&self.removed, &self.removed_derivable),
+        })
+    }
+}
+
+impl<L: SynthLanguage> Display for RulesetDiff<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} added ({} derivable from the old rules), {} removed ({} derivable from the new rules)",
+            self.added.len(),
+            self.added_derivable.len(),
+            self.removed.len(),
+            self.removed_derivable.len()
+        )?;
+        let sections = [
+            ('+', &self.added, &self.added_derivable),
+            ('-', &self.removed, &self.removed_derivable),
+        ];
+        for (sign, rules, derivable) in sections {
+            for rule in rules.iter() {
+                if derivable.contains(rule) {
+                    writeln!(f, "{} {}  (derivable)", sign, rule)?;
+                } else {
+                    writeln!(f, "{} {}", sign, rule)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn diff_modulo_alpha_and_direction() {
+        let old: Ruleset<SymbolLang> = Ruleset::new([
+            "(+ ?a ?b) ==> (+ ?b ?a)",
+            "(* ?a 1) ==> ?a",
+            "(+ ?a (+ ?b ?c)) ==> (+ (+ ?a ?b) ?c)",
+        ]);
+        let new: Ruleset<SymbolLang> = Ruleset::new([
+            "(+ ?y ?x) ==> (+ ?x ?y)",
+            "(+ (+ ?a ?b) ?c) ==> (+ ?a (+ ?b ?c))",
+            "(+ ?a 0) ==> ?a",
+            "(+ 0 ?a) ==> ?a",
+        ]);
+        let diff = old.diff(&new, DeriveType::LhsAndRhs, Limits::deriving());
+        assert_eq!(
+            diff.added.to_str_vec(),
+            vec!["(+ ?a 0) ==> ?a", "(+ 0 ?a) ==> ?a"]
+        );
+        assert_eq!(diff.removed.to_str_vec(), vec!["(* ?a 1) ==> ?a"]);
+        assert!(diff.added_derivable.is_empty());
+        assert!(diff.removed_derivable.is_empty());
+        assert!(!diff.is_empty());
+
+        let json = diff.to_json();
+        assert_eq!(json["added"].as_array().unwrap().len(), 2);
+        assert_eq!(json["removed"][0]["rule"], "(* ?a 1) ==> ?a");
+        assert!(diff.to_string().contains("- (* ?a 1) ==> ?a\n"));
+    }
+
+    #[test]
+    fn diff_reports_derivable_rules() {
+        let old: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)", "(+ ?a 0) ==> ?a"]);
+        let new: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)", "(+ 0 ?a) ==> ?a"]);
+        let diff = old.diff(&new, DeriveType::LhsAndRhs, Limits::deriving());
+        assert!(diff
+            .added_derivable
+            .contains(diff.added.iter().next().unwrap()));
+        assert!(diff
+            .removed_derivable
+            .contains(diff.removed.iter().next().unwrap()));
+        assert!(diff.to_string().contains("(derivable)"));
+    }
+}
@@ -0,0 +1,445 @@
+use std::{fmt::Write, io, sync::Arc};
+
+use egg::ENodeOrVar;
+
+use crate::{HashMap, HashSet, Pattern, SynthLanguage};
+
+use super::{Rule, Ruleset};
+
+/// Options for `Ruleset::to_rust`.
+#[derive(Debug, Clone)]
+pub struct RustExport {
+    /// Path of the egg language in the generated module, e.g. `crate::Math`.
+    pub lang: String,
+    /// Path of the e-graph analysis, `()` for none.
+    pub analysis: String,
+    /// Name of the generated function.
+    pub fn_name: String,
+    /// A term that a rule's guard must be equal to for the rule to fire,
+    /// e.g. `true`. Rulesets with conditional rules cannot be exported
+    /// without it.
+    pub truth: Option<String>,
+}
+
+impl RustExport {
+    pub fn new(lang: &str) -> Self {
+        Self {
+            lang: lang.to_string(),
+            analysis: "()".to_string(),
+            fn_name: "rules".to_string(),
+            truth: None,
+        }
+    }
+}
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// A Rust module with a function returning the ruleset as egg rewrites.
+    ///
+    /// Each direction of a bidirectional rule becomes its own rewrite, and
+    /// rewrites are named after their rule, so names stay the same across
+    /// runs. Guards become `ConditionEqual` conditions against
+    /// `export.truth`, which needs a language that implements `FromOp`, as
+    /// those from `define_language!` do. Without `export.truth`, a ruleset
+    /// with a conditional rule is an error, since leaving out the guard would
+    /// make the rewrite unsound.
+    pub fn to_rust(&self, export: &RustExport) -> Result<String, String> {
+        if export.truth.is_none() {
+            if let Some(rule) = self.iter().find(|rule| rule.is_conditional()) {
+                return Err(format!(
+                    "{}: exporting a conditional rule needs a truth term",
+                    rule.name
+                ));
+            }
+        }
+        let mut out = String::new();
+        writeln!(out, "// Generated by enumo.").unwrap();
+        writeln!(out).unwrap();
+        let imports = if self.iter().any(|rule| rule.is_conditional()) {
+            "{rewrite, ConditionEqual, Rewrite}"
+        } else {
+            "{rewrite, Rewrite}"
+        };
+        writeln!(out, "use egg::{};", imports).unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "pub fn {}() -> Vec<Rewrite<{}, {}>> {{",
+            export.fn_name, export.lang, export.analysis
+        )
+        .unwrap();
+        writeln!(out, "    vec![").unwrap();
+        for rule in self.iter() {
+            let (lhs, rhs) = (rule.lhs.to_string(), rule.rhs.to_string());
+            match (&rule.cond, &export.truth) {
+                (Some(cond), Some(truth)) => writeln!(
+                    out,
+                    "        rewrite!({:?}; {:?} => {:?} if ConditionEqual::parse({:?}, {:?})),",
+                    rule.name,
+                    lhs,
+                    rhs,
+                    cond.to_string(),
+                    truth
+                ),
+                _ => writeln!(
+                    out,
+                    "        rewrite!({:?}; {:?} => {:?}),",
+                    rule.name, lhs, rhs
+                ),
+            }
+            .unwrap();
+        }
+        writeln!(out, "    ]").unwrap();
+        writeln!(out, "}}").unwrap();
+        Ok(out)
+    }
+
+    /// Write `to_rust` to a file. A ruleset that cannot be exported is an
+    /// `InvalidInput` error.
+    pub fn to_rust_file(&self, filename: &str, export: &RustExport) -> io::Result<()> {
+        let rust = self
+            .to_rust(export)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        std::fs::write(filename, rust)
+    }
+}
+
+/// How a domain's terms are written in a target language, for the egglog,
+/// SMT-LIB2 and Lean exporters.
+#[derive(Debug, Clone)]
+pub struct OpMap {
+    /// Target name of each operator. An entry for `op/arity`, e.g. `-/1`,
+    /// takes precedence over one for `op`, for domains that overload an
+    /// operator. A name containing `{0}`, `{1}`, ... is a template for the
+    /// whole application, e.g. `({0} + {1})`. Operators without an entry
+    /// keep their name.
+    pub ops: HashMap<String, String>,
+    /// How to write a constant of the domain.
+    pub literal: fn(&str) -> String,
+    /// The sort or type of variables, e.g. `(_ BitVec 4)` or `Int`.
+    pub sort: String,
+    /// A term that a rule's guard must be equal to for the rule to hold.
+    /// Without it, guards are used as propositions.
+    pub truth: Option<String>,
+    /// A term that a rule's guard must differ from for the rule to hold, for
+    /// domains where any other value is true. Takes precedence over `truth`.
+    pub falsity: Option<String>,
+}
+
+/// How a domain's terms are written in each target of the exporters.
+pub trait OpMaps {
+    fn smtlib() -> OpMap;
+    fn egglog() -> OpMap;
+    fn lean() -> OpMap;
+}
+
+impl OpMap {
+    pub fn new(sort: &str, ops: &[(&str, &str)]) -> Self {
+        Self {
+            ops: ops
+                .iter()
+                .map(|(op, target)| (op.to_string(), target.to_string()))
+                .collect(),
+            literal: |c| c.to_string(),
+            sort: sort.to_string(),
+            truth: None,
+            falsity: None,
+        }
+    }
+
+    /// Write `pat` in the target language. Variables lose their `?`, and
+    /// nullary operators are wrapped in parentheses if `nullary_parens`.
+    fn term<L: SynthLanguage>(&self, pat: &Pattern<L>, nullary_parens: bool) -> String {
+        let nodes = pat.ast.as_ref();
+        self.subterm(nodes, nodes.len() - 1, nullary_parens)
+    }
+
+    fn subterm<L: SynthLanguage>(
+        &self,
+        nodes: &[ENodeOrVar<L>],
+        id: usize,
+        nullary_parens: bool,
+    ) -> String {
+        let node = match &nodes[id] {
+            ENodeOrVar::Var(v) => return var_name(v),
+            ENodeOrVar::ENode(node) => node,
+        };
+        if node.is_constant() {
+            return (self.literal)(&node.to_string());
+        }
+        let args: Vec<String> = node
+            .children()
+            .iter()
+            .map(|c| self.subterm(nodes, usize::from(*c), nullary_parens))
+            .collect();
+        let op = node.to_string();
+        let target = self
+            .ops
+            .get(&format!("{}/{}", op, args.len()))
+            .or_else(|| self.ops.get(&op))
+            .unwrap_or(&op);
+        if target.contains("{0}") {
+            let mut term = target.clone();
+            for (i, arg) in args.iter().enumerate() {
+                term = term.replace(&format!("{{{}}}", i), arg);
+            }
+            term
+        } else if args.is_empty() && !nullary_parens {
+            target.clone()
+        } else {
+            let mut term = format!("({}", target);
+            for arg in &args {
+                write!(term, " {}", arg).unwrap();
+            }
+            term + ")"
+        }
+    }
+
+    /// The guard of `rule` as a proposition. `eq` and `ne` are the target's
+    /// templates for `{0}` being equal to and differing from `{1}`.
+    fn guard<L: SynthLanguage>(
+        &self,
+        cond: &Pattern<L>,
+        nullary_parens: bool,
+        eq: &str,
+        ne: &str,
+    ) -> String {
+        let cond = self.term(cond, nullary_parens);
+        match (&self.falsity, &self.truth) {
+            (Some(falsity), _) => ne.replace("{0}", &cond).replace("{1}", falsity),
+            (None, Some(truth)) => eq.replace("{0}", &cond).replace("{1}", truth),
+            (None, None) => cond,
+        }
+    }
+}
+
+fn var_name(v: &egg::Var) -> String {
+    v.to_string()[1..].to_string()
+}
+
+/// The variables of a rule, in order of appearance.
+fn rule_vars<L: SynthLanguage>(rule: &Rule<L>) -> Vec<String> {
+    let mut vars = rule.lhs.vars();
+    for v in rule
+        .rhs
+        .vars()
+        .into_iter()
+        .chain(rule.cond.iter().flat_map(|c| c.vars()))
+    {
+        if !vars.contains(&v) {
+            vars.push(v);
+        }
+    }
+    vars.iter().map(var_name).collect()
+}
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// Each rule once, paired with whether its reverse is in the ruleset too.
+    /// Of a bidirectional pair, the rule that comes first is kept.
+    pub(super) fn one_direction(&self) -> Vec<(&Rule<L>, bool)> {
+        let mut skip: HashSet<Arc<str>> = HashSet::default();
+        let mut rules = vec![];
+        for rule in self.iter() {
+            if skip.contains(&rule.canonical_name()) {
+                continue;
+            }
+            let reverse = rule
+                .reverse()
+                .map(|reverse| reverse.canonical_name())
+                .filter(|reverse| {
+                    *reverse != rule.canonical_name() && self.0.contains_key(reverse)
+                });
+            let bidirectional = reverse.is_some();
+            skip.extend(reverse);
+            rules.push((rule, bidirectional));
+        }
+        rules
+    }
+
+    /// An egglog program with a `rewrite` for each rule, or a `birewrite`
+    /// for each bidirectional pair. Guards become `:when` facts.
+    ///
+    /// Only the rules are written; the program must be combined with a
+    /// declaration of the language's datatype.
+    pub fn to_egglog(&self, map: &OpMap) -> String {
+        let mut out = String::new();
+        for (rule, bidirectional) in self.one_direction() {
+            let command = if bidirectional {
+                "birewrite"
+            } else {
+                "rewrite"
+            };
+            write!(
+                out,
+                "({} {} {}",
+                command,
+                map.term(&rule.lhs, true),
+                map.term(&rule.rhs, true)
+            )
+            .unwrap();
+            if let Some(cond) = &rule.cond {
+                let fact = map.guard(cond, true, "(= {0} {1})", "(!= {0} {1})");
+                write!(out, " :when ({})", fact).unwrap();
+            }
+            writeln!(out, ")").unwrap();
+        }
+        out
+    }
+
+    /// An SMT-LIB2 script for each rule, or each bidirectional pair, that is
+    /// `unsat` exactly when the rule is valid.
+    pub fn to_smtlib(&self, map: &OpMap) -> Vec<String> {
+        self.one_direction()
+            .into_iter()
+            .map(|(rule, _)| {
+                let mut eq = format!(
+                    "(= {} {})",
+                    map.term(&rule.lhs, false),
+                    map.term(&rule.rhs, false)
+                );
+                if let Some(cond) = &rule.cond {
+                    let guard = map.guard(cond, false, "(= {0} {1})", "(not (= {0} {1}))");
+                    eq = format!("(=> {} {})", guard, eq);
+                }
+                let vars = rule_vars(rule);
+                let claim = if vars.is_empty() {
+                    eq
+                } else {
+                    let binders: Vec<String> = vars
+                        .iter()
+                        .map(|v| format!("({} {})", v, map.sort))
+                        .collect();
+                    format!("(forall ({}) {})", binders.join(" "), eq)
+                };
+                format!("; {}\n(assert (not {}))\n(check-sat)\n", rule.name, claim)
+            })
+            .collect()
+    }
+
+    /// Write the scripts of `to_smtlib` to `dir`, as `0.smt2`, `1.smt2`, ...
+    pub fn to_smtlib_dir(&self, dir: &str, map: &OpMap) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (i, script) in self.to_smtlib(map).iter().enumerate() {
+            std::fs::write(format!("{}/{}.smt2", dir, i), script)?;
+        }
+        Ok(())
+    }
+
+    /// A Lean 4 theorem for each rule, or each bidirectional pair, proved by
+    /// `sorry`. Theorems are named `{prefix}_0`, `{prefix}_1`, ...
+    pub fn to_lean(&self, map: &OpMap, prefix: &str) -> String {
+        let mut out = String::new();
+        for (i, (rule, _)) in self.one_direction().into_iter().enumerate() {
+            writeln!(out, "-- {}", rule.name).unwrap();
+            write!(out, "theorem {}_{}", prefix, i).unwrap();
+            let vars = rule_vars(rule);
+            if !vars.is_empty() {
+                write!(out, " ({} : {})", vars.join(" "), map.sort).unwrap();
+            }
+            if let Some(cond) = &rule.cond {
+                let hyp = map.guard(cond, false, "{0} = {1}", "{0} ≠ {1}");
+                write!(out, " (h : {})", hyp).unwrap();
+            }
+            writeln!(
+                out,
+                " :\n    {} = {} := by\n  sorry\n",
+                map.term(&rule.lhs, false),
+                map.term(&rule.rhs, false)
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn rust_expands_bidirectional_rules() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a 0) <=> ?a"]);
+        let rust = rules.to_rust(&RustExport::new("egg::SymbolLang")).unwrap();
+        assert!(rust.contains("pub fn rules() -> Vec<Rewrite<egg::SymbolLang, ()>> {"));
+        assert!(rust.contains(r#"rewrite!("(+ ?a 0) ==> ?a"; "(+ ?a 0)" => "?a"),"#));
+        assert!(rust.contains(r#"rewrite!("?a ==> (+ ?a 0)"; "?a" => "(+ ?a 0)"),"#));
+    }
+
+    #[test]
+    fn rust_guards_need_a_truth_term() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new(["(/ ?a ?a) ==> 1 if (!= ?a 0)"]);
+        let mut export = RustExport::new("egg::SymbolLang");
+        assert!(rules.to_rust(&export).is_err());
+
+        export.truth = Some("true".into());
+        let rust = rules.to_rust(&export).unwrap();
+        assert!(rust.contains("use egg::{rewrite, ConditionEqual, Rewrite};"));
+        assert!(rust.contains(r#"if ConditionEqual::parse("(!= ?a 0)", "true")),"#));
+    }
+
+    fn halide_smt() -> OpMap {
+        let mut map = OpMap::new("Int", &[("-/1", "({0} * -1)"), ("!=", "distinct")]);
+        map.truth = Some("1".into());
+        map
+    }
+
+    #[test]
+    fn egglog_merges_bidirectional_rules() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new([
+            "(+ ?a 0) <=> ?a",
+            "(* ?a 0) ==> 0",
+            "(/ ?a ?a) ==> 1 if (!= ?a 0)",
+        ]);
+        let map = OpMap::new(
+            "Math",
+            &[("+", "Add"), ("*", "Mul"), ("/", "Div"), ("!=", "Ne")],
+        );
+        assert_eq!(
+            rules.to_egglog(&map),
+            "(birewrite (Add a (0)) a)\n\
+             (rewrite (Mul a (0)) (0))\n\
+             (rewrite (Div a a) (1) :when ((Ne a (0))))\n"
+        );
+    }
+
+    #[test]
+    fn smtlib_checks_each_rule() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new([
+            "(- (- ?a)) <=> ?a",
+            "(/ ?a ?a) ==> 1 if (!= ?a 0)",
+            "(+ 1 1) ==> 2",
+        ]);
+        let scripts = rules.to_smtlib(&halide_smt());
+        assert_eq!(scripts.len(), 3);
+        assert!(scripts[0].contains("(assert (not (forall ((a Int)) (= ((a * -1) * -1) a))))"));
+        assert!(scripts[1].contains("(=> (= (distinct a 0) 1) (= (/ a a) 1))"));
+        assert!(scripts[2].contains("(assert (not (= (+ 1 1) 2)))"));
+    }
+
+    #[test]
+    fn bv_op_maps() {
+        use crate::bv4::Bv;
+
+        let rules: Ruleset<Bv> = Ruleset::new(["(+ ?a 0) ==> ?a"]);
+        let scripts = rules.to_smtlib(&Bv::smtlib());
+        assert!(scripts[0].contains("(forall ((a (_ BitVec 4))) (= (bvadd a (_ bv0 4)) a))"));
+        assert_eq!(
+            rules.to_egglog(&Bv::egglog()),
+            "(rewrite (Add a (Lit 0)) a)\n"
+        );
+        assert!(rules
+            .to_smtlib_dir("/dev/null/smtlib", &Bv::smtlib())
+            .is_err());
+    }
+
+    #[test]
+    fn lean_theorem_stubs() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)"]);
+        let map = OpMap::new("BitVec 4", &[("+", "({0} + {1})")]);
+        assert_eq!(
+            rules.to_lean(&map, "comm"),
+            "-- (+ ?a ?b) ==> (+ ?b ?a)\n\
+             theorem comm_0 (a b : BitVec 4) :\n    (a + b) = (b + a) := by\n  sorry\n\n"
+        );
+    }
+}
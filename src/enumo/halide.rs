@@ -0,0 +1,348 @@
+use std::io;
+
+use crate::{Pattern, SynthLanguage};
+
+use super::{Rule, Ruleset};
+
+/// The outcome of `Ruleset::from_halide`.
+#[derive(Debug, Clone)]
+pub struct HalideImport<L: SynthLanguage> {
+    pub rules: Ruleset<L>,
+    /// The arguments of each `rewrite(...)` that could not be translated,
+    /// with the reason.
+    pub untranslatable: Vec<(String, String)>,
+}
+
+/// Functions with a counterpart in the halide domain.
+const FUNCTIONS: [&str; 3] = ["max", "min", "select"];
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// Import the rules of Halide's simplifier from its C++ source.
+    ///
+    /// Each `rewrite(lhs, rhs)` or `rewrite(lhs, rhs, predicate)` call becomes
+    /// a rule, conditional on the predicate if there is one. Expressions use
+    /// the operator names of the halide domain: `a > b` becomes `(< b a)`,
+    /// `true` and `false` become `1` and `0`, and variables, including the
+    /// constant wildcards `c0`, `c1`, ..., become pattern variables. A
+    /// wildcard then matches any term, not only constants.
+    ///
+    /// Calls using operators or functions the domain lacks, e.g. `%`,
+    /// `broadcast` or `fold`, are reported in `untranslatable`, as are those
+    /// that do not parse as patterns of `L`.
+    pub fn from_halide(source: &str) -> HalideImport<L> {
+        let mut rules = Ruleset::default();
+        let mut untranslatable = vec![];
+        for call in rewrite_calls(&strip_comments(source)) {
+            match translate(&call) {
+                Ok(rule) => rules.add(rule),
+                Err(reason) => untranslatable.push((call.join(", "), reason)),
+            }
+        }
+        HalideImport {
+            rules,
+            untranslatable,
+        }
+    }
+
+    /// `from_halide` on the contents of a file.
+    pub fn from_halide_file(filename: &str) -> io::Result<HalideImport<L>> {
+        let source = std::fs::read_to_string(filename)?;
+        Ok(Self::from_halide(&source))
+    }
+}
+
+fn translate<L: SynthLanguage>(args: &[String]) -> Result<Rule<L>, String> {
+    let pattern = |arg: &String| -> Result<Pattern<L>, String> {
+        let sexp = Parser::new(arg)?.parse()?;
+        sexp.parse()
+            .map_err(|_| format!("{} is not a pattern of the domain", sexp))
+    };
+    let (lhs, rhs, cond) = match args {
+        [lhs, rhs] => (pattern(lhs)?, pattern(rhs)?, None),
+        [lhs, rhs, cond] => (pattern(lhs)?, pattern(rhs)?, Some(pattern(cond)?)),
+        _ => return Err(format!("expected 2 or 3 arguments, found {}", args.len())),
+    };
+    Rule::new_conditional(&lhs, &rhs, cond.as_ref())
+        .ok_or_else(|| "rhs or predicate has variables the lhs does not".to_string())
+}
+
+/// Remove `//` and `/* */` comments.
+fn strip_comments(source: &str) -> String {
+    let mut out = String::new();
+    let mut rest = source;
+    while let Some(start) = rest.find('/') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if tail.starts_with("//") {
+            rest = tail.find('\n').map_or("", |end| &tail[end..]);
+        } else if tail.starts_with("/*") {
+            rest = tail.find("*/").map_or("", |end| &tail[end + 2..]);
+        } else {
+            out.push('/');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The top-level arguments of every call to `rewrite`.
+fn rewrite_calls(source: &str) -> Vec<Vec<String>> {
+    let mut calls = vec![];
+    let mut rest = source;
+    while let Some(start) = rest.find("rewrite(") {
+        let is_call = !rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        rest = &rest[start + "rewrite(".len()..];
+        if !is_call {
+            continue;
+        }
+        let mut args = vec![String::new()];
+        let mut depth = 0;
+        let mut end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    end = i + 1;
+                    break;
+                }
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    args.push(String::new());
+                    continue;
+                }
+                _ => (),
+            }
+            args.last_mut().unwrap().push(c);
+        }
+        rest = &rest[end..];
+        calls.push(args.iter().map(|arg| arg.trim().to_string()).collect());
+    }
+    calls
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+/// Longest first, so that e.g. `<=` is not read as `<`.
+const OPS: [&str; 22] = [
+    "<=", ">=", "==", "!=", "&&", "||", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "^",
+    "&", "|", "~", "(", ")",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let n = rest[..len]
+                .parse()
+                .map_err(|_| format!("bad literal {}", &rest[..len]))?;
+            tokens.push(Token::Num(n));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else if c == ',' {
+            tokens.push(Token::Op(","));
+            1
+        } else {
+            return Err(format!("unsupported syntax `{}`", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Binding power of the binary operators, as in C++.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+/// A precedence-climbing parser from C++ expressions to s-expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(s: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(s)?,
+            pos: 0,
+        })
+    }
+
+    fn parse(mut self) -> Result<String, String> {
+        let sexp = self.expr(0)?;
+        match self.tokens.get(self.pos) {
+            None => Ok(sexp),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            found => Err(format!("expected `{}`, found {:?}", op, found)),
+        }
+    }
+
+    fn expr(&mut self, min_prec: u8) -> Result<String, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            let op = *op;
+            let prec = match precedence(op) {
+                Some(prec) if prec > min_prec => prec,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.expr(prec)?;
+            lhs = match op {
+                ">" => format!("(< {} {})", rhs, lhs),
+                ">=" => format!("(<= {} {})", rhs, lhs),
+                "+" | "-" | "*" | "/" | "<" | "<=" | "==" | "!=" | "&&" | "||" | "^" => {
+                    format!("({} {} {})", op, lhs, rhs)
+                }
+                _ => return Err(format!("unsupported operator `{}`", op)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Op("!")) => Ok(format!("(! {})", self.unary()?)),
+            Some(Token::Op("-")) => match self.tokens.get(self.pos) {
+                Some(Token::Num(n)) => {
+                    let n = -n;
+                    self.pos += 1;
+                    Ok(n.to_string())
+                }
+                _ => Ok(format!("(- {})", self.unary()?)),
+            },
+            Some(Token::Op("(")) => {
+                let inner = self.expr(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(Token::Num(n)) => Ok(n.to_string()),
+            Some(Token::Ident(name)) if self.tokens.get(self.pos) == Some(&Token::Op("(")) => {
+                if !FUNCTIONS.contains(&name.as_str()) {
+                    return Err(format!("unsupported function `{}`", name));
+                }
+                self.pos += 1;
+                let mut args = vec![self.expr(0)?];
+                while self.tokens.get(self.pos) == Some(&Token::Op(",")) {
+                    self.pos += 1;
+                    args.push(self.expr(0)?);
+                }
+                self.expect(")")?;
+                Ok(format!("({} {})", name, args.join(" ")))
+            }
+            Some(Token::Ident(name)) => Ok(match name.as_str() {
+                "true" => "1".to_string(),
+                "false" => "0".to_string(),
+                _ => format!("?{}", name),
+            }),
+            token => Err(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn parse_cpp_expressions() {
+        let sexp = |s: &str| Parser::new(s).unwrap().parse().unwrap();
+        assert_eq!(sexp("x + y * z - 1"), "(- (+ ?x (* ?y ?z)) 1)");
+        assert_eq!(
+            sexp("!(x < y) || c0 >= -2"),
+            "(|| (! (< ?x ?y)) (<= -2 ?c0))"
+        );
+        assert_eq!(
+            sexp("select(x == y, min(x, c0), true)"),
+            "(select (== ?x ?y) (min ?x ?c0) 1)"
+        );
+        assert_eq!(sexp("-(x / 2)"), "(- (/ ?x 2))");
+    }
+
+    #[test]
+    fn import_rewrite_calls() {
+        let source = r#"
+            // rewrite(x, y) is commented out
+            return rewrite(x + 0, x) ||
+                   rewrite((x - y) + y, x) ||
+                   rewrite(max(x, c0) + c1, max(x + c1, c0 + c1), c1 > 0) ||
+                   rewrite(x % 2, 0, /* never */ false) ||
+                   rewrite(broadcast(x, c0) + 1, x) ||
+                   can_rewrite(x, x);
+        "#;
+        let import: HalideImport<SymbolLang> = Ruleset::from_halide(source);
+        assert_eq!(
+            import.rules.to_str_vec(),
+            vec![
+                "(+ ?a 0) ==> ?a",
+                "(+ (- ?a ?b) ?b) ==> ?a",
+                "(+ (max ?a ?b) ?c) ==> (max (+ ?a ?c) (+ ?b ?c)) if (< 0 ?c)",
+            ]
+        );
+        assert_eq!(
+            import.untranslatable,
+            vec![
+                (
+                    "x % 2, 0, false".to_string(),
+                    "unsupported operator `%`".to_string()
+                ),
+                (
+                    "broadcast(x, c0) + 1, x".to_string(),
+                    "unsupported function `broadcast`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let missing = std::env::temp_dir().join("enumo-missing.cpp");
+        let e = Ruleset::<SymbolLang>::from_halide_file(missing.to_str().unwrap()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    }
+}
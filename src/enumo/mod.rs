@@ -1,19 +1,35 @@
 use crate::HashMap;
 
+pub use complete::*;
+pub use diff::*;
+pub use export::*;
 pub use filter::*;
+pub use halide::*;
 pub use metric::*;
+pub use orient::*;
 pub use pattern::*;
+pub use proof::*;
 pub use rule::*;
+pub use rulefile::*;
 pub use ruleset::*;
 pub use scheduler::*;
+pub use scorer::*;
 pub use sexp::*;
 pub use workload::*;
 
+mod complete;
+mod diff;
+mod export;
 mod filter;
+mod halide;
 mod metric;
+mod orient;
 mod pattern;
+mod proof;
 mod rule;
+mod rulefile;
 mod ruleset;
 mod scheduler;
+mod scorer;
 mod sexp;
 mod workload;
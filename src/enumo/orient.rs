@@ -0,0 +1,463 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
+
+use egg::ENodeOrVar;
+
+use crate::{HashMap, HashSet, Pattern, SynthLanguage};
+
+use super::{Rule, Ruleset};
+
+/// A reduction order on patterns: a well-founded order that is closed under
+/// substitution and contexts. A rule system whose rules all decrease in such
+/// an order terminates.
+pub trait TermOrder<L: SynthLanguage> {
+    /// Whether `s` is strictly greater than `t`.
+    fn greater(&self, s: &Pattern<L>, t: &Pattern<L>) -> bool;
+}
+
+/// A precedence on operators, given from smallest to largest. Operators that
+/// are not listed are only comparable to themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Precedence(pub Vec<String>);
+
+impl Precedence {
+    pub fn new<I>(ops: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        Self(ops.into_iter().map(|op| op.to_string()).collect())
+    }
+
+    fn cmp(&self, f: &str, g: &str) -> Option<Ordering> {
+        if f == g {
+            return Some(Ordering::Equal);
+        }
+        let f = self.0.iter().position(|op| op == f)?;
+        let g = self.0.iter().position(|op| op == g)?;
+        Some(f.cmp(&g))
+    }
+}
+
+/// The lexicographic path order for a precedence.
+#[derive(Debug, Clone, Default)]
+pub struct Lpo {
+    pub precedence: Precedence,
+}
+
+impl Lpo {
+    pub fn new(precedence: Precedence) -> Self {
+        Self { precedence }
+    }
+
+    fn gt<L: SynthLanguage>(&self, s: Term<L>, t: Term<L>) -> bool {
+        let f = match s.node() {
+            ENodeOrVar::Var(_) => return false,
+            ENodeOrVar::ENode(f) => f,
+        };
+        let g = match t.node() {
+            ENodeOrVar::Var(x) => return s.contains(x) && !s.equals(t),
+            ENodeOrVar::ENode(g) => g,
+        };
+        // Some argument of s is at least t
+        if s.children()
+            .into_iter()
+            .any(|si| si.equals(t) || self.gt(si, t))
+        {
+            return true;
+        }
+        let dominates = || t.children().into_iter().all(|tj| self.gt(s, tj));
+        if f.matches(g) {
+            lex_gt(&s.children(), &t.children(), |a, b| self.gt(a, b)) && dominates()
+        } else {
+            let ordering = self.precedence.cmp(&f.to_string(), &g.to_string());
+            ordering == Some(Ordering::Greater) && dominates()
+        }
+    }
+}
+
+impl<L: SynthLanguage> TermOrder<L> for Lpo {
+    fn greater(&self, s: &Pattern<L>, t: &Pattern<L>) -> bool {
+        self.gt(Term::root(s), Term::root(t))
+    }
+}
+
+/// The Knuth-Bendix order for a precedence and operator weights.
+///
+/// Operators without a weight weigh `default_weight`, and variables weigh
+/// `var_weight`. The weights are admissible, so the order is a reduction
+/// order: see `Kbo::with_weights`.
+#[derive(Debug, Clone)]
+pub struct Kbo {
+    precedence: Precedence,
+    weights: HashMap<String, usize>,
+    default_weight: usize,
+    var_weight: usize,
+}
+
+impl Default for Kbo {
+    fn default() -> Self {
+        Self {
+            precedence: Default::default(),
+            weights: Default::default(),
+            default_weight: 1,
+            var_weight: 1,
+        }
+    }
+}
+
+/// Why weights are not admissible for `Kbo::with_weights`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KboError {
+    /// Variables weigh nothing.
+    ZeroVarWeight,
+    /// The default weight is less than the weight of variables, so constants
+    /// without a weight would be too.
+    LightDefault,
+    /// The constant weighs less than variables.
+    LightConstant(String),
+    /// The unary operator weighs nothing, but is not the largest operator in
+    /// the precedence.
+    WeightlessUnary(String),
+}
+
+impl Display for KboError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KboError::ZeroVarWeight => write!(f, "variables must have a positive weight"),
+            KboError::LightDefault => {
+                write!(f, "the default weight is less than the variable weight")
+            }
+            KboError::LightConstant(op) => {
+                write!(f, "constant {} weighs less than a variable", op)
+            }
+            KboError::WeightlessUnary(op) => write!(
+                f,
+                "unary operator {} has weight 0 but is not the largest in the precedence",
+                op
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KboError {}
+
+impl Kbo {
+    /// The order in which every operator and variable weighs 1.
+    pub fn new(precedence: Precedence) -> Self {
+        Self {
+            precedence,
+            ..Default::default()
+        }
+    }
+
+    /// The order with the given `(operator, arity, weight)`s, in which other
+    /// operators weigh `default_weight` and variables weigh `var_weight`.
+    ///
+    /// Fails unless the weights are admissible: variables must weigh something,
+    /// every constant at least as much as a variable, and a unary operator may
+    /// only weigh nothing if it is the largest operator in the precedence.
+    pub fn with_weights<I, S>(
+        precedence: Precedence,
+        weights: I,
+        default_weight: usize,
+        var_weight: usize,
+    ) -> Result<Self, KboError>
+    where
+        I: IntoIterator<Item = (S, usize, usize)>,
+        S: ToString,
+    {
+        if var_weight == 0 {
+            return Err(KboError::ZeroVarWeight);
+        }
+        if default_weight < var_weight {
+            return Err(KboError::LightDefault);
+        }
+        let mut kbo = Self {
+            precedence,
+            weights: Default::default(),
+            default_weight,
+            var_weight,
+        };
+        for (op, arity, weight) in weights {
+            let op = op.to_string();
+            if arity == 0 && weight < var_weight {
+                return Err(KboError::LightConstant(op));
+            }
+            if arity == 1 && weight == 0 && kbo.precedence.0.last() != Some(&op) {
+                return Err(KboError::WeightlessUnary(op));
+            }
+            kbo.weights.insert(op, weight);
+        }
+        Ok(kbo)
+    }
+
+    fn weight<L: SynthLanguage>(&self, t: Term<L>) -> usize {
+        match t.node() {
+            ENodeOrVar::Var(_) => self.var_weight,
+            ENodeOrVar::ENode(f) => {
+                let own = *self
+                    .weights
+                    .get(&f.to_string())
+                    .unwrap_or(&self.default_weight);
+                own + t
+                    .children()
+                    .into_iter()
+                    .map(|c| self.weight(c))
+                    .sum::<usize>()
+            }
+        }
+    }
+
+    fn gt<L: SynthLanguage>(&self, s: Term<L>, t: Term<L>) -> bool {
+        // Every variable must occur at least as often in s as in t
+        let (s_vars, t_vars) = (s.var_counts(), t.var_counts());
+        if t_vars.iter().any(|(x, n)| s_vars.get(x).unwrap_or(&0) < n) {
+            return false;
+        }
+        let (ws, wt) = (self.weight(s), self.weight(t));
+        if ws != wt {
+            return ws > wt;
+        }
+        match (s.node(), t.node()) {
+            (ENodeOrVar::ENode(_), ENodeOrVar::Var(x)) => s.contains(x),
+            (ENodeOrVar::ENode(f), ENodeOrVar::ENode(g)) => {
+                if f.matches(g) {
+                    lex_gt(&s.children(), &t.children(), |a, b| self.gt(a, b))
+                } else {
+                    let ordering = self.precedence.cmp(&f.to_string(), &g.to_string());
+                    ordering == Some(Ordering::Greater)
+                }
+            }
+            (ENodeOrVar::Var(_), _) => false,
+        }
+    }
+}
+
+impl<L: SynthLanguage> TermOrder<L> for Kbo {
+    fn greater(&self, s: &Pattern<L>, t: &Pattern<L>) -> bool {
+        self.gt(Term::root(s), Term::root(t))
+    }
+}
+
+/// Whether `ss` is lexicographically greater than `ts`, given that they have
+/// the same length.
+fn lex_gt<L, F>(ss: &[Term<L>], ts: &[Term<L>], gt: F) -> bool
+where
+    L: SynthLanguage,
+    F: Fn(Term<L>, Term<L>) -> bool,
+{
+    match ss.iter().zip(ts).find(|(s, t)| !s.equals(**t)) {
+        Some((s, t)) => gt(*s, *t),
+        None => false,
+    }
+}
+
+/// A subterm of a pattern.
+struct Term<'a, L: SynthLanguage> {
+    nodes: &'a [ENodeOrVar<L>],
+    id: usize,
+}
+
+impl<L: SynthLanguage> Clone for Term<'_, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L: SynthLanguage> Copy for Term<'_, L> {}
+
+impl<'a, L: SynthLanguage> Term<'a, L> {
+    fn root(pat: &'a Pattern<L>) -> Self {
+        let nodes = pat.ast.as_ref();
+        Self {
+            nodes,
+            id: nodes.len() - 1,
+        }
+    }
+
+    fn node(&self) -> &'a ENodeOrVar<L> {
+        &self.nodes[self.id]
+    }
+
+    fn children(&self) -> Vec<Self> {
+        match self.node() {
+            ENodeOrVar::Var(_) => vec![],
+            ENodeOrVar::ENode(n) => n
+                .children()
+                .iter()
+                .map(|id| Self {
+                    nodes: self.nodes,
+                    id: usize::from(*id),
+                })
+                .collect(),
+        }
+    }
+
+    fn equals(&self, other: Self) -> bool {
+        match (self.node(), other.node()) {
+            (ENodeOrVar::Var(x), ENodeOrVar::Var(y)) => x == y,
+            (ENodeOrVar::ENode(f), ENodeOrVar::ENode(g)) => {
+                f.matches(g)
+                    && self
+                        .children()
+                        .into_iter()
+                        .zip(other.children())
+                        .all(|(a, b)| a.equals(b))
+            }
+            _ => false,
+        }
+    }
+
+    fn contains(&self, x: &egg::Var) -> bool {
+        match self.node() {
+            ENodeOrVar::Var(y) => x == y,
+            ENodeOrVar::ENode(_) => self.children().into_iter().any(|c| c.contains(x)),
+        }
+    }
+
+    fn var_counts(&self) -> HashMap<egg::Var, usize> {
+        let mut counts = HashMap::default();
+        self.count_vars(&mut counts);
+        counts
+    }
+
+    fn count_vars(&self, counts: &mut HashMap<egg::Var, usize>) {
+        match self.node() {
+            ENodeOrVar::Var(x) => *counts.entry(*x).or_default() += 1,
+            ENodeOrVar::ENode(_) => {
+                for c in self.children() {
+                    c.count_vars(counts);
+                }
+            }
+        }
+    }
+}
+
+/// The outcome of `Ruleset::orient`.
+#[derive(Debug, Clone)]
+pub struct Oriented<L: SynthLanguage> {
+    /// Rules that decrease in the order, one per bidirectional pair.
+    pub rules: Ruleset<L>,
+    /// Rules that decrease in neither direction, e.g. commutativity. Pairs
+    /// are listed once.
+    pub unorientable: Ruleset<L>,
+}
+
+impl<L: SynthLanguage> Ruleset<L> {
+    /// Orient every rule so that its lhs is greater than its rhs in `order`.
+    ///
+    /// A rule whose reverse is also in the ruleset is kept in one direction
+    /// only. Rules may be flipped, since learned rules are equalities. Rules
+    /// that cannot be oriented are left out of `rules` and reported in
+    /// `unorientable`, so `rules` is a terminating rewrite system.
+    pub fn orient(&self, order: &impl TermOrder<L>) -> Oriented<L> {
+        let mut rules = Ruleset::default();
+        let mut unorientable = Ruleset::default();
+        let mut seen: HashSet<Arc<str>> = HashSet::default();
+        for rule in self.iter() {
+            let reverse = rule.reverse();
+            if let Some(reverse) = &reverse {
+                if !seen.insert(reverse.canonical_name()) {
+                    continue;
+                }
+            }
+            seen.insert(rule.canonical_name());
+
+            let oriented: Option<Rule<L>> = if order.greater(&rule.lhs, &rule.rhs) {
+                Some(rule.clone())
+            } else {
+                reverse.filter(|reverse| order.greater(&reverse.lhs, &reverse.rhs))
+            };
+            match oriented {
+                Some(oriented) => rules.add(oriented),
+                None => unorientable.add(rule.clone()),
+            }
+        }
+        Oriented {
+            rules,
+            unorientable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    fn pat(s: &str) -> Pattern<SymbolLang> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn lpo_orders_by_precedence() {
+        let lpo = Lpo::new(Precedence::new(["0", "+", "*"]));
+        let distrib = (pat("(* ?a (+ ?b ?c))"), pat("(+ (* ?a ?b) (* ?a ?c))"));
+        assert!(lpo.greater(&distrib.0, &distrib.1));
+        assert!(!lpo.greater(&distrib.1, &distrib.0));
+        assert!(lpo.greater(&pat("(+ (+ ?a ?b) ?c)"), &pat("(+ ?a (+ ?b ?c))")));
+        assert!(!lpo.greater(&pat("(+ ?a ?b)"), &pat("(+ ?b ?a)")));
+        assert!(!lpo.greater(&pat("?a"), &pat("(+ ?a 0)")));
+        // Not in the precedence
+        assert!(!lpo.greater(&pat("(f ?a)"), &pat("(g ?a)")));
+    }
+
+    #[test]
+    fn kbo_orders_by_weight() {
+        let kbo = Kbo::new(Precedence::new(["f", "g"]));
+        assert!(kbo.greater(&pat("(+ ?a 0)"), &pat("?a")));
+        assert!(kbo.greater(&pat("(g ?a)"), &pat("(f ?a)")));
+        // Duplicates a variable
+        assert!(!kbo.greater(&pat("(f ?a)"), &pat("(+ ?a ?a)")));
+        assert!(!kbo.greater(&pat("(+ ?a ?b)"), &pat("(+ ?b ?a)")));
+    }
+
+    #[test]
+    fn kbo_rejects_inadmissible_weights() {
+        let prec = || Precedence::new(["0", "-", "+"]);
+        let kbo = |weights: Vec<(&str, usize, usize)>, var_weight| {
+            Kbo::with_weights(prec(), weights, 1, var_weight).map(|_| ())
+        };
+        assert_eq!(kbo(vec![("+", 2, 0)], 0), Err(KboError::ZeroVarWeight));
+        assert_eq!(kbo(vec![], 2), Err(KboError::LightDefault));
+        assert_eq!(kbo(vec![("0", 0, 1)], 1), Ok(()));
+        assert_eq!(
+            kbo(vec![("0", 0, 0)], 1),
+            Err(KboError::LightConstant("0".into()))
+        );
+        assert_eq!(
+            kbo(vec![("-", 1, 0)], 1),
+            Err(KboError::WeightlessUnary("-".into()))
+        );
+        assert_eq!(kbo(vec![("+", 2, 0)], 1), Ok(()));
+
+        // A weightless largest unary operator is admissible
+        let kbo = Kbo::with_weights(Precedence::new(["f", "g"]), [("g", 1, 0)], 1, 1).unwrap();
+        assert!(kbo.greater(&pat("(g (f ?a))"), &pat("(f (g ?a))")));
+    }
+
+    #[test]
+    fn orient_pairs() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new([
+            "(+ ?a 0) <=> ?a",
+            "(+ ?a ?b) <=> (+ ?b ?a)",
+            "(+ (* ?a ?b) (* ?a ?c)) <=> (* ?a (+ ?b ?c))",
+        ]);
+        let oriented = rules.orient(&Lpo::new(Precedence::new(["0", "+", "*"])));
+        assert_eq!(
+            oriented.rules.to_str_vec(),
+            vec![
+                "(+ ?a 0) ==> ?a",
+                "(* ?a (+ ?b ?c)) ==> (+ (* ?a ?b) (* ?a ?c))"
+            ]
+        );
+        assert_eq!(
+            oriented.unorientable.to_str_vec(),
+            vec!["(+ ?a ?b) ==> (+ ?b ?a)"]
+        );
+    }
+}
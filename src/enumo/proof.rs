@@ -0,0 +1,124 @@
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+use egg::{FlatTerm, RecExpr};
+use serde_json::{json, Value};
+
+use crate::SynthLanguage;
+
+use super::Rule;
+
+/// A derivation of a rule: the sequence of rewrites that takes its lhs to its
+/// rhs, see `Ruleset::prove`.
+#[derive(Debug, Clone)]
+pub struct Proof<L: SynthLanguage> {
+    /// The derived rule.
+    pub rule: Rule<L>,
+    /// The instantiated lhs of the rule.
+    pub start: RecExpr<L>,
+    /// The rewrites applied to `start`, in order. The term of the last step
+    /// is the instantiated rhs.
+    pub steps: Vec<ProofStep<L>>,
+}
+
+/// A single rewrite in a `Proof`.
+#[derive(Debug, Clone)]
+pub struct ProofStep<L: SynthLanguage> {
+    /// The name of the applied rule, as in the deriving ruleset.
+    /// Unions made by the e-graph analysis are named by their reason instead,
+    /// e.g. "constant-fold".
+    pub rule: Arc<str>,
+    /// Whether the rule was applied from right to left.
+    pub backward: bool,
+    /// The term after the rewrite.
+    pub term: RecExpr<L>,
+}
+
+impl<L: SynthLanguage> Proof<L> {
+    /// The proof of `rule` given by a flat explanation. Terms of the
+    /// explanation that record no rewrite add nothing to the proof and are
+    /// skipped.
+    pub(crate) fn from_flat(rule: Rule<L>, flat: &[FlatTerm<L>]) -> Self {
+        let start = flat[0].get_recexpr();
+        let steps = flat[1..]
+            .iter()
+            .filter_map(|term| {
+                let (rule, backward) = applied_rule(term)?;
+                Some(ProofStep {
+                    rule: rule.into(),
+                    backward,
+                    term: term.get_recexpr(),
+                })
+            })
+            .collect();
+        Self { rule, start, steps }
+    }
+
+    /// Names of the applied rules, in order.
+    pub fn rules(&self) -> Vec<Arc<str>> {
+        self.steps.iter().map(|step| step.rule.clone()).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let steps: Vec<Value> = self
+            .steps
+            .iter()
+            .map(|step| {
+                json!({
+                    "rule": step.rule.to_string(),
+                    "backward": step.backward,
+                    "term": step.term.to_string(),
+                })
+            })
+            .collect();
+        json!({
+            "rule": self.rule.name.to_string(),
+            "start": self.start.to_string(),
+            "steps": steps,
+        })
+    }
+}
+
+/// The rewrite recorded in a term of a flat explanation, on the term itself or
+/// on one of its subterms. Terms after the first usually have exactly one.
+fn applied_rule<L: SynthLanguage>(term: &FlatTerm<L>) -> Option<(String, bool)> {
+    if let Some(rule) = term.forward_rule {
+        return Some((rule.to_string(), false));
+    }
+    if let Some(rule) = term.backward_rule {
+        return Some((rule.to_string(), true));
+    }
+    term.children.iter().find_map(applied_rule)
+}
+
+impl<L: SynthLanguage> Display for Proof<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.rule)?;
+        writeln!(f, "  {}", self.start)?;
+        for step in &self.steps {
+            let arrow = if step.backward { "<=" } else { "=>" };
+            writeln!(f, "  {} {}  by {}", arrow, step.term, step.rule)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn steps_without_a_rewrite_are_skipped() {
+        let (rule, _) = Rule::<SymbolLang>::from_string("(+ ?a 0) ==> ?a").unwrap();
+        let leaf = |op: &str| FlatTerm::new(SymbolLang::leaf(op), vec![]);
+        let add = SymbolLang::new("+", vec![0.into(); 2]);
+        let start = FlatTerm::new(add, vec![leaf("a"), leaf("0")]);
+        let mut rewritten = leaf("a");
+        rewritten.forward_rule = Some("add-zero".into());
+        let proof = Proof::from_flat(rule, &[start.clone(), start, rewritten]);
+        assert_eq!(proof.start.to_string(), "(+ a 0)");
+        assert_eq!(proof.rules(), vec![Arc::from("add-zero")]);
+        assert_eq!(proof.steps[0].term.to_string(), "a");
+    }
+}
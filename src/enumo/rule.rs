@@ -13,46 +13,49 @@ pub struct Rule<L: SynthLanguage> {
     pub lhs: Pattern<L>,
     /// The pattern to merge
     pub rhs: Pattern<L>,
+    /// An optional guard. The rule only applies where the guard is known to be
+    /// true, see `SynthLanguage::is_true`: in practice, where it evaluates to
+    /// a true constant.
+    pub cond: Option<Pattern<L>>,
     /// egg::Rewrite
     pub rewrite: Rewrite<L, SynthAnalysis>,
 }
 
 impl<L: SynthLanguage> Display for Rule<L> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ==> {}", self.lhs, self.rhs)
+        write!(f, "{} ==> {}", self.lhs, self.rhs)?;
+        if let Some(cond) = &self.cond {
+            write!(f, " if {}", cond)?;
+        }
+        Ok(())
     }
 }
 
 impl<L: SynthLanguage> Rule<L> {
+    /// Parse a rule of the form `lhs => rhs`, `lhs ==> rhs` or `lhs <=> rhs`,
+    /// optionally followed by a guard: `lhs ==> rhs if cond`.
     pub fn from_string(s: &str) -> Result<(Self, Option<Self>), String> {
         if let Some((l, r)) = s.split_once("=>") {
-            let l_pat: Pattern<L> = l.parse().unwrap();
-            let r_pat: Pattern<L> = r.parse().unwrap();
-
-            let forwards = Self {
-                name: format!("{} ==> {}", l_pat, r_pat).into(),
-                lhs: l_pat.clone(),
-                rhs: r_pat.clone(),
-                rewrite: Rewrite::new(
-                    format!("{} ==> {}", l_pat, r_pat),
-                    l_pat.clone(),
-                    Rhs { rhs: r_pat.clone() },
-                )
-                .unwrap(),
+            let l = l
+                .strip_suffix("==")
+                .or_else(|| l.strip_suffix('<'))
+                .unwrap_or(l);
+            let (rhs_end, cond_start) = split_guard(r).map_err(|(_, e)| e)?;
+            let (r, cond) = (&r[..rhs_end], cond_start.map(|i| &r[i..]));
+            let l_pat: Pattern<L> = l.parse().map_err(|_| format!("Failed to parse {}", l))?;
+            let r_pat: Pattern<L> = r.parse().map_err(|_| format!("Failed to parse {}", r))?;
+            let cond_pat: Option<Pattern<L>> = match cond {
+                Some(c) => Some(c.parse().map_err(|_| format!("Failed to parse {}", c))?),
+                None => None,
             };
 
+            let forwards = Self::new_conditional(&l_pat, &r_pat, cond_pat.as_ref())
+                .ok_or_else(|| format!("Failed to create rule from {}", s))?;
+
             if s.contains("<=>") {
-                let backwards = Self {
-                    name: format!("{} ==> {}", r_pat, l_pat).into(),
-                    lhs: r_pat.clone(),
-                    rhs: l_pat.clone(),
-                    rewrite: Rewrite::new(
-                        format!("{} ==> {}", r_pat, l_pat),
-                        r_pat,
-                        Rhs { rhs: l_pat },
-                    )
-                    .unwrap(),
-                };
+                let backwards = forwards
+                    .reverse()
+                    .ok_or_else(|| format!("Failed to create rule from {}", s))?;
                 Ok((forwards, Some(backwards)))
             } else {
                 Ok((forwards, None))
@@ -63,14 +66,73 @@ impl<L: SynthLanguage> Rule<L> {
     }
 }
 
+/// Split the text after a rule's arrow into the rhs and the guard.
+///
+/// The rhs is the first s-expression, so an operator named `if` inside it is
+/// not mistaken for a guard. Returns the offset at which the rhs ends and,
+/// if `if` follows it, the offset at which the guard starts. Errors carry the
+/// offset they refer to.
+pub(crate) fn split_guard(s: &str) -> Result<(usize, Option<usize>), (usize, String)> {
+    let start = s.len() - s.trim_start().len();
+    let mut depth = 0;
+    let mut end = None;
+    for (i, c) in s[start..].char_indices() {
+        let i = start + i;
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err((i, "unbalanced `)`".into())),
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                end = Some(i);
+                break;
+            }
+            _ => (),
+        }
+    }
+    let end = match end {
+        Some(end) => end,
+        None if depth > 0 => return Err((start, "unclosed `(`".into())),
+        None => s.len(),
+    };
+    if end == start {
+        return Err((start, "expected a pattern".into()));
+    }
+    let rest = s[end..].trim_start();
+    let rest_start = s.len() - rest.len();
+    if rest.is_empty() {
+        return Ok((end, None));
+    }
+    match rest.strip_prefix("if") {
+        Some(cond) if cond.starts_with(char::is_whitespace) => {
+            Ok((end, Some(rest_start + "if".len())))
+        }
+        _ => Err((rest_start, format!("expected `if` or the end of the rule, found `{}`", rest))),
+    }
+}
+
 /// Default Applier for rewrite rules
 struct Rhs<L: SynthLanguage> {
     rhs: Pattern<L>,
+    cond: Option<Pattern<L>>,
 }
 
 impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
     fn vars(&self) -> Vec<Var> {
-        self.rhs.vars()
+        let mut vars = self.rhs.vars();
+        if let Some(cond) = &self.cond {
+            for v in cond.vars() {
+                if !vars.contains(&v) {
+                    vars.push(v);
+                }
+            }
+        }
+        vars
     }
 
     fn apply_one(
@@ -78,13 +140,19 @@ impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
         egraph: &mut EGraph<L, SynthAnalysis>,
         matched_id: Id,
         subst: &Subst,
-        _ast: Option<&PatternAst<L>>,
-        _sym: Symbol,
+        ast: Option<&PatternAst<L>>,
+        sym: Symbol,
     ) -> Vec<Id> {
         if !egraph[matched_id].data.is_defined() {
             return vec![];
         }
 
+        if let Some(cond) = &self.cond {
+            if !cond_holds(egraph, cond, subst) {
+                return vec![];
+            }
+        }
+
         let id = apply_pat(self.rhs.ast.as_ref(), egraph, subst);
         if id == matched_id {
             return vec![];
@@ -94,25 +162,57 @@ impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
             return vec![];
         }
 
-        egraph.union(id, matched_id);
+        // With explanations enabled, egg passes the matched lhs so the union
+        // can be justified by this rule
+        match ast {
+            Some(ast) => {
+                egraph.union_instantiations(ast, &self.rhs.ast, subst, sym);
+            }
+            None => {
+                egraph.union(id, matched_id);
+            }
+        }
         vec![id]
     }
 }
 
 impl<L: SynthLanguage> Rule<L> {
     pub fn new(l_pat: &Pattern<L>, r_pat: &Pattern<L>) -> Option<Self> {
-        let name = format!("{} ==> {}", l_pat, r_pat);
-        let rhs = Rhs { rhs: r_pat.clone() };
+        Self::new_conditional(l_pat, r_pat, None)
+    }
+
+    /// Construct a rule that only applies where `cond` holds.
+    /// Passing `None` for the condition gives an ordinary rule.
+    pub fn new_conditional(
+        l_pat: &Pattern<L>,
+        r_pat: &Pattern<L>,
+        cond: Option<&Pattern<L>>,
+    ) -> Option<Self> {
+        let name = rule_name(l_pat, r_pat, cond);
+        let rhs = Rhs {
+            rhs: r_pat.clone(),
+            cond: cond.cloned(),
+        };
         let rewrite = Rewrite::new(name.clone(), l_pat.clone(), rhs).ok();
 
         rewrite.map(|rw| Rule {
             name: name.into(),
             lhs: l_pat.clone(),
             rhs: r_pat.clone(),
+            cond: cond.cloned(),
             rewrite: rw,
         })
     }
 
+    /// The rule in the opposite direction, keeping the same condition.
+    pub fn reverse(&self) -> Option<Self> {
+        Self::new_conditional(&self.rhs, &self.lhs, self.cond.as_ref())
+    }
+
+    pub fn is_conditional(&self) -> bool {
+        self.cond.is_some()
+    }
+
     /// A rule is saturating if applying it is guaranteed not to add any
     /// e-classes to the e-graph.
     pub fn is_saturating(&self) -> bool {
@@ -133,12 +233,274 @@ impl<L: SynthLanguage> Rule<L> {
         L::score(&self.lhs, &self.rhs)
     }
 
+    /// The rule with its variables renamed to `?a`, `?b`, ... in order of
+    /// first occurrence in the lhs, rhs and guard.
+    ///
+    /// Alpha-equivalent rules have the same canonical form, so
+    /// `(+ ?x ?y) ==> (+ ?y ?x)` and `(+ ?b ?a) ==> (+ ?a ?b)` are both
+    /// `(+ ?a ?b) ==> (+ ?b ?a)`. `Ruleset`s store rules in canonical form.
+    pub fn canonical(&self) -> Self {
+        self.alpha_normalize().0
+    }
+
+    /// The name of the canonical form, without building its rewrite.
+    pub fn canonical_name(&self) -> Arc<str> {
+        let renaming = self.canonical_renaming();
+        if renaming.iter().all(|(new, old)| new == old) {
+            return self.name.clone();
+        }
+        let cond = self.cond.as_ref().map(|c| rename_vars(c, &renaming));
+        rule_name(
+            &rename_vars(&self.lhs, &renaming),
+            &rename_vars(&self.rhs, &renaming),
+            cond.as_ref(),
+        )
+        .into()
+    }
+
+    /// The canonical name of each variable, paired with its current name.
+    fn canonical_renaming(&self) -> Vec<(Var, Var)> {
+        let mut renaming: Vec<(Var, Var)> = vec![];
+        let pats = std::iter::once(&self.lhs)
+            .chain(std::iter::once(&self.rhs))
+            .chain(self.cond.as_ref());
+        for pat in pats {
+            for var in printed_vars(pat) {
+                if !renaming.iter().any(|(_, old)| *old == var) {
+                    let new = format!("?{}", letter(renaming.len())).parse().unwrap();
+                    renaming.push((new, var));
+                }
+            }
+        }
+        renaming
+    }
+
+    /// The canonical form of the rule, together with the original name of
+    /// each of its variables.
+    pub(crate) fn alpha_normalize(&self) -> (Self, Vec<(Symbol, Symbol)>) {
+        let renaming = self.canonical_renaming();
+        let symbols = renaming
+            .iter()
+            .map(|(new, old)| {
+                let sym = |v: &Var| Symbol::from(&v.to_string()[1..]);
+                (sym(new), sym(old))
+            })
+            .collect();
+        if renaming.iter().all(|(new, old)| new == old) {
+            return (self.clone(), symbols);
+        }
+        let cond = self.cond.as_ref().map(|c| rename_vars(c, &renaming));
+        let normalized = Self::new_conditional(
+            &rename_vars(&self.lhs, &renaming),
+            &rename_vars(&self.rhs, &renaming),
+            cond.as_ref(),
+        )
+        .expect("renaming variables preserves well-formedness");
+        (normalized, symbols)
+    }
+
+    /// Check the soundness of the rule. Conditional rules are checked under
+    /// their guard.
+    ///
+    /// Results are cached by the alpha-normalized rule, so alpha-equivalent
+    /// rules are only checked once per process, see `validation_cache`.
+    pub fn validate(&self) -> ValidationResult<L> {
+        self.validate_normalized(|rule| {
+            validation_cache::get_or_validate(&rule.name, || rule.check())
+        })
+    }
+
+    /// Check a rule whose validation was `Unknown` again, trying each timeout
+    /// of the escalation in turn and then falling back to random testing.
+    /// Stops at the first definitive result and updates the cache with the
+    /// final result.
+    pub fn validate_escalating(&self, escalation: &Escalation<L>) -> ValidationResult<L> {
+        self.validate_normalized(|rule| {
+            let mut result = ValidationResult::Unknown;
+            for ms in &escalation.timeouts_ms {
+                result = with_z3_timeout(*ms, || rule.check());
+                if !matches!(result, ValidationResult::Unknown) {
+                    break;
+                }
+            }
+            if let (ValidationResult::Unknown, Some(fuzz), None) =
+                (&result, &escalation.fuzz, &rule.cond)
+            {
+                if let ValidationResult::Invalid(cex) = fuzz_validate(&rule.lhs, &rule.rhs, fuzz) {
+                    result = ValidationResult::Invalid(cex);
+                }
+            }
+            validation_cache::insert(&rule.name, result.clone());
+            result
+        })
+    }
+
+    /// Run `validate` on the alpha-normalized rule and rename the variables
+    /// of its counterexample back.
+    fn validate_normalized(
+        &self,
+        validate: impl FnOnce(&Self) -> ValidationResult<L>,
+    ) -> ValidationResult<L> {
+        let (normalized, renaming) = self.alpha_normalize();
+        match validate(&normalized) {
+            ValidationResult::Invalid(Some(mut cex)) => {
+                for (var, _) in &mut cex.assignment {
+                    if let Some((_, old)) = renaming.iter().find(|(new, _)| new == var) {
+                        *var = *old;
+                    }
+                }
+                ValidationResult::Invalid(Some(cex))
+            }
+            result => result,
+        }
+    }
+
+    /// Run the domain's validator, bypassing the cache.
+    fn check(&self) -> ValidationResult<L> {
+        match &self.cond {
+            Some(cond) => L::validate_with_cond(&self.lhs, &self.rhs, cond),
+            None => L::validate(&self.lhs, &self.rhs),
+        }
+    }
+
     /// Whether the rule is sound
     pub fn is_valid(&self) -> bool {
-        matches!(L::validate(&self.lhs, &self.rhs), ValidationResult::Valid)
+        matches!(self.validate(), ValidationResult::Valid)
     }
 }
 
+fn rule_name<L: SynthLanguage>(
+    l_pat: &Pattern<L>,
+    r_pat: &Pattern<L>,
+    cond: Option<&Pattern<L>>,
+) -> String {
+    match cond {
+        Some(c) => format!("{} ==> {} if {}", l_pat, r_pat, c),
+        None => format!("{} ==> {}", l_pat, r_pat),
+    }
+}
+
+/// The variables of a pattern in printed order, not AST node order.
+fn printed_vars<L: SynthLanguage>(pat: &Pattern<L>) -> Vec<Var> {
+    fn walk<L: SynthLanguage>(ast: &PatternAst<L>, id: Id, vars: &mut Vec<Var>) {
+        match &ast[id] {
+            ENodeOrVar::Var(v) => {
+                if !vars.contains(v) {
+                    vars.push(*v);
+                }
+            }
+            ENodeOrVar::ENode(n) => n.for_each(|child| walk(ast, child, vars)),
+        }
+    }
+    let mut vars = vec![];
+    let root = Id::from(pat.ast.as_ref().len() - 1);
+    walk(&pat.ast, root, &mut vars);
+    vars
+}
+
+/// Rename the variables of a pattern, given pairs of new and old names.
+pub(crate) fn rename_vars<L: SynthLanguage>(
+    pat: &Pattern<L>,
+    renaming: &[(Var, Var)],
+) -> Pattern<L> {
+    let nodes: Vec<ENodeOrVar<L>> = pat
+        .ast
+        .as_ref()
+        .iter()
+        .map(|node| match node {
+            ENodeOrVar::Var(v) => {
+                let (new, _) = renaming.iter().find(|(_, old)| old == v).unwrap();
+                ENodeOrVar::Var(*new)
+            }
+            ENodeOrVar::ENode(n) => ENodeOrVar::ENode(n.clone()),
+        })
+        .collect();
+    PatternAst::from(nodes).into()
+}
+
+/// Whether a guard is known to be true where a rule matched.
+///
+/// The guard is decided by its value: that of its e-class if the e-graph
+/// already has it and the interval analysis or constant folding gave it one,
+/// or else that of the guard evaluated on the constants its variables are
+/// bound to. A guard whose variables are not all bound to constants is not
+/// known to hold. The cvec is only used to rule the guard out early: a
+/// sample that makes it false proves nothing about the matched terms.
+///
+/// The guard is looked up, not added, so a rule that does not fire leaves the
+/// e-graph unchanged.
+fn cond_holds<L: SynthLanguage>(
+    egraph: &EGraph<L, SynthAnalysis>,
+    cond: &Pattern<L>,
+    subst: &Subst,
+) -> bool {
+    if let Some(id) = lookup_pat(cond.ast.as_ref(), egraph, subst) {
+        if egraph[id].data.cvec.iter().flatten().any(|c| !L::is_true(c)) {
+            return false;
+        }
+        if let Some(c) = class_constant(egraph, id) {
+            return L::is_true(&c);
+        }
+    }
+    let mut assignment = vec![];
+    for var in cond.vars() {
+        match class_constant(egraph, subst[var]) {
+            Some(c) => assignment.push((Symbol::from(&var.to_string()[1..]), c)),
+            None => return false,
+        }
+    }
+    let guard = Counterexample::<L> {
+        assignment,
+        lhs: None,
+        rhs: None,
+    };
+    guard
+        .eval(&L::instantiate(cond))
+        .is_some_and(|c| L::is_true(&c))
+}
+
+/// The value of an e-class, if it is known to be a constant.
+fn class_constant<L: SynthLanguage>(
+    egraph: &EGraph<L, SynthAnalysis>,
+    id: Id,
+) -> Option<L::Constant> {
+    let class = &egraph[id];
+    if let Interval {
+        low: Some(low),
+        high: Some(high),
+    } = &class.data.interval
+    {
+        if low == high {
+            return Some(low.clone());
+        }
+    }
+    class
+        .nodes
+        .iter()
+        .find(|node| node.is_constant())
+        .and_then(|node| node.eval(1, |_| unreachable!("constants have no children")).pop())
+        .flatten()
+}
+
+/// The e-class of a pattern instance, if the e-graph has it.
+fn lookup_pat<L: Language, A: Analysis<L>>(
+    pat: &[ENodeOrVar<L>],
+    egraph: &EGraph<L, A>,
+    subst: &Subst,
+) -> Option<Id> {
+    let mut ids: Vec<Id> = Vec::with_capacity(pat.len());
+    for pat_node in pat {
+        let id = match pat_node {
+            ENodeOrVar::Var(w) => subst[*w],
+            ENodeOrVar::ENode(e) => {
+                egraph.lookup(e.clone().map_children(|child| ids[usize::from(child)]))?
+            }
+        };
+        ids.push(id);
+    }
+    ids.last().copied()
+}
+
 fn apply_pat<L: Language, A: Analysis<L>>(
     pat: &[ENodeOrVar<L>],
     egraph: &mut EGraph<L, A>,
@@ -162,7 +524,47 @@ fn apply_pat<L: Language, A: Analysis<L>>(
 
 #[cfg(test)]
 mod test {
+    use crate::bv4;
     use crate::enumo::Rule;
+    use crate::Symbol;
+
+    #[test]
+    fn alpha_normalize() {
+        let (rule, _) =
+            Rule::<egg::SymbolLang>::from_string("(* ?x (+ ?y ?z)) ==> (- ?y ?x) if ?z")
+                .ok()
+                .unwrap();
+        let (normalized, renaming) = rule.alpha_normalize();
+        assert_eq!(
+            normalized.name.to_string(),
+            "(* ?a (+ ?b ?c)) ==> (- ?b ?a) if ?c"
+        );
+        let sym = Symbol::from;
+        assert_eq!(
+            renaming,
+            vec![
+                (sym("a"), sym("x")),
+                (sym("b"), sym("y")),
+                (sym("c"), sym("z"))
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_name_follows_printed_order() {
+        use egg::{ENodeOrVar, PatternAst, SymbolLang};
+        // Prints as `(+ ?y ?x)`, but `?x` is the first node of the AST.
+        let nodes: Vec<ENodeOrVar<SymbolLang>> = vec![
+            ENodeOrVar::Var("?x".parse().unwrap()),
+            ENodeOrVar::Var("?y".parse().unwrap()),
+            ENodeOrVar::ENode(SymbolLang::new("+", vec![1.into(), 0.into()])),
+        ];
+        let lhs: egg::Pattern<SymbolLang> = PatternAst::from(nodes).into();
+        let rule = Rule::new(&lhs, &"(+ ?x ?y)".parse().unwrap()).unwrap();
+        let (parsed, _) = Rule::<SymbolLang>::from_string("(+ ?b ?a) ==> (+ ?a ?b)").unwrap();
+        assert_eq!(rule.canonical_name(), parsed.canonical_name());
+        assert_eq!(rule.canonical_name().to_string(), "(+ ?a ?b) ==> (+ ?b ?a)");
+    }
 
     #[test]
     fn parse() {
@@ -188,4 +590,60 @@ mod test {
         assert_eq!(backwards.unwrap().name.to_string(), "(* c d) ==> (* a b)");
         assert_eq!(forwards.name.to_string(), "(* a b) ==> (* c d)");
     }
+
+    #[test]
+    fn parse_conditional() {
+        let (forwards, backwards) = Rule::<egg::SymbolLang>::from_string("(/ ?a ?a) ==> 1 if ?a")
+            .ok()
+            .unwrap();
+        assert!(backwards.is_none());
+        assert!(forwards.is_conditional());
+        assert_eq!(forwards.cond.unwrap().to_string(), "?a");
+        assert_eq!(forwards.name.to_string(), "(/ ?a ?a) ==> 1 if ?a");
+
+        // The guard is kept in both directions
+        let (forwards, backwards) =
+            Rule::<egg::SymbolLang>::from_string("(* (/ ?a ?b) ?b) <=> (/ (* ?a ?b) ?b) if ?b")
+                .ok()
+                .unwrap();
+        assert_eq!(
+            forwards.to_string(),
+            "(* (/ ?a ?b) ?b) ==> (/ (* ?a ?b) ?b) if ?b"
+        );
+        assert_eq!(
+            backwards.unwrap().name.to_string(),
+            "(/ (* ?a ?b) ?b) ==> (* (/ ?a ?b) ?b) if ?b"
+        );
+
+        // Operators named `if` are not guards
+        let (forwards, _) = Rule::<egg::SymbolLang>::from_string("(if ?a ?b ?b) ==> ?b")
+            .ok()
+            .unwrap();
+        assert!(!forwards.is_conditional());
+        let (forwards, _) =
+            Rule::<egg::SymbolLang>::from_string("(f ?a ?b) ==> (g ?b) if (if ?a ?b 0)")
+                .ok()
+                .unwrap();
+        assert_eq!(forwards.rhs.to_string(), "(g ?b)");
+        assert_eq!(forwards.cond.unwrap().to_string(), "(if ?a ?b 0)");
+
+        assert!(Rule::<egg::SymbolLang>::from_string("(f ?a) ==> ?a ?a").is_err());
+        assert!(Rule::<egg::SymbolLang>::from_string("(f ?a) ==> (g ?a").is_err());
+    }
+
+    #[test]
+    fn cached_counterexample_keeps_names() {
+        use crate::ValidationResult;
+        use bv4::Bv;
+
+        for var in ["x", "y"] {
+            let lhs = format!("(* ?{} ?{})", var, var).parse().unwrap();
+            let rhs = format!("?{}", var).parse().unwrap();
+            let rule: Rule<Bv> = Rule::new(&lhs, &rhs).unwrap();
+            match rule.validate() {
+                ValidationResult::Invalid(Some(cex)) => assert!(cex.get(var.into()).is_some()),
+                res => panic!("expected a counterexample, got {:?}", res),
+            }
+        }
+    }
 }
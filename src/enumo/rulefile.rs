@@ -0,0 +1,377 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+};
+
+use crate::{Pattern, SynthLanguage};
+
+use super::{split_guard, Rule, Ruleset};
+
+/// A ruleset with names, metadata and comments, as read from or written to
+/// a rule file.
+///
+/// Each line of a rule file is blank, a comment starting with `#`, or a rule
+///
+/// ```text
+/// [name] lhs <=> rhs if cond ; status=valid from=bv4 tags=arith,comm
+/// ```
+///
+/// where the name, guard and metadata are optional, and `==>` or `=>` give a
+/// rule in one direction. A file with one plain rule per line is a rule file
+/// too.
+#[derive(Debug, Clone)]
+pub struct RuleFile<L: SynthLanguage> {
+    pub entries: Vec<RuleEntry<L>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleEntry<L: SynthLanguage> {
+    pub name: Option<String>,
+    pub rule: Rule<L>,
+    /// Whether the rule holds in both directions, written `<=>`.
+    pub bidirectional: bool,
+    pub meta: RuleMeta,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleMeta {
+    /// The last known validation result.
+    pub status: Option<RuleStatus>,
+    /// Where the rule comes from, e.g. the recipe that learned it.
+    pub provenance: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleStatus {
+    Valid,
+    Invalid,
+    Unknown,
+}
+
+impl RuleStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RuleStatus::Valid => "valid",
+            RuleStatus::Invalid => "invalid",
+            RuleStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// An error in a rule file. Lines and columns start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An error reading a rule file.
+#[derive(Debug)]
+pub enum RuleFileError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl Display for RuleFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleFileError::Io(e) => write!(f, "{}", e),
+            RuleFileError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuleFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuleFileError::Io(e) => Some(e),
+            RuleFileError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for RuleFileError {
+    fn from(e: io::Error) -> Self {
+        RuleFileError::Io(e)
+    }
+}
+
+impl From<ParseError> for RuleFileError {
+    fn from(e: ParseError) -> Self {
+        RuleFileError::Parse(e)
+    }
+}
+
+impl<L: SynthLanguage> RuleFile<L> {
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        let mut entries = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let parsed = parse_line(line).map_err(|(column, message)| ParseError {
+                line: i + 1,
+                column: column + 1,
+                message,
+            })?;
+            entries.extend(parsed);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self, RuleFileError> {
+        let s = std::fs::read_to_string(filename)?;
+        Ok(Self::parse(&s)?)
+    }
+
+    pub fn to_file(&self, filename: &str) -> io::Result<()> {
+        std::fs::write(filename, self.to_string())
+    }
+
+    /// The rules of the file, with both directions of bidirectional rules.
+    pub fn ruleset(&self) -> Ruleset<L> {
+        let mut rules = Ruleset::default();
+        for entry in &self.entries {
+            rules.add(entry.rule.clone());
+            if entry.bidirectional {
+                rules.add(entry.rule.reverse().expect("bidirectional rule"));
+            }
+        }
+        rules
+    }
+}
+
+impl<L: SynthLanguage> From<&Ruleset<L>> for RuleFile<L> {
+    /// Unnamed entries without metadata, with each pair of a rule and its
+    /// reverse merged into one `<=>` entry.
+    fn from(rules: &Ruleset<L>) -> Self {
+        let entries = rules
+            .one_direction()
+            .into_iter()
+            .map(|(rule, bidirectional)| RuleEntry {
+                name: None,
+                rule: rule.clone(),
+                bidirectional,
+                meta: RuleMeta::default(),
+            })
+            .collect();
+        Self { entries }
+    }
+}
+
+impl<L: SynthLanguage> Display for RuleEntry<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "[{}] ", name)?;
+        }
+        let arrow = if self.bidirectional { "<=>" } else { "==>" };
+        write!(f, "{} {} {}", self.rule.lhs, arrow, self.rule.rhs)?;
+        if let Some(cond) = &self.rule.cond {
+            write!(f, " if {}", cond)?;
+        }
+        let meta = &self.meta;
+        if meta == &RuleMeta::default() {
+            return Ok(());
+        }
+        write!(f, " ;")?;
+        if let Some(status) = meta.status {
+            write!(f, " status={}", status.as_str())?;
+        }
+        if let Some(provenance) = &meta.provenance {
+            write!(f, " from={}", provenance)?;
+        }
+        if !meta.tags.is_empty() {
+            write!(f, " tags={}", meta.tags.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl<L: SynthLanguage> Display for RuleFile<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors carry the 0-based column they refer to.
+type LineResult<T> = Result<T, (usize, String)>;
+
+/// The part of a line between two byte offsets, with surrounding whitespace
+/// removed, and the offset at which the trimmed part starts.
+fn span(line: &str, start: usize, end: usize) -> (&str, usize) {
+    let s = &line[start..end];
+    let trimmed = s.trim_start();
+    (trimmed.trim_end(), start + s.len() - trimmed.len())
+}
+
+fn parse_line<L: SynthLanguage>(line: &str) -> LineResult<Option<RuleEntry<L>>> {
+    let (text, mut start) = span(line, 0, line.len());
+    if text.is_empty() || text.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut name = None;
+    if text.starts_with('[') {
+        let close = line[start..]
+            .find(']')
+            .ok_or((start, "unclosed `[`".to_string()))?;
+        let (n, n_start) = span(line, start + 1, start + close);
+        if n.is_empty() {
+            return Err((n_start, "empty rule name".into()));
+        }
+        name = Some(n.to_string());
+        start += close + 1;
+    }
+
+    let (rule_end, meta) = match line[start..].find(';') {
+        Some(semi) => (start + semi, parse_meta(line, start + semi + 1)?),
+        None => (line.len(), RuleMeta::default()),
+    };
+
+    let (arrow, arrow_len, bidirectional) = ["<=>", "==>", "=>"]
+        .iter()
+        .find_map(|a| {
+            line[start..rule_end]
+                .find(a)
+                .map(|i| (start + i, a.len(), *a == "<=>"))
+        })
+        .ok_or_else(|| {
+            (
+                span(line, start, rule_end).1,
+                "expected `==>`, `=>` or `<=>`".to_string(),
+            )
+        })?;
+    let rhs_start = arrow + arrow_len;
+    let (rhs_end, cond) =
+        split_guard(&line[rhs_start..rule_end]).map_err(|(i, e)| (rhs_start + i, e))?;
+    let rhs_end = rhs_start + rhs_end;
+    let cond = cond.map(|i| span(line, rhs_start + i, rule_end));
+    let lhs = parse_pattern(span(line, start, arrow))?;
+    let rhs_span = span(line, rhs_start, rhs_end);
+    let rhs = parse_pattern(rhs_span)?;
+    let cond = cond.map(parse_pattern).transpose()?;
+
+    let rule = Rule::new_conditional(&lhs, &rhs, cond.as_ref()).ok_or((
+        rhs_span.1,
+        "rhs or guard has variables the lhs does not".to_string(),
+    ))?;
+    if bidirectional && rule.reverse().is_none() {
+        return Err((
+            span(line, start, arrow).1,
+            "lhs has variables the rhs does not, so the rule is not bidirectional".into(),
+        ));
+    }
+    Ok(Some(RuleEntry {
+        name,
+        rule,
+        bidirectional,
+        meta,
+    }))
+}
+
+fn parse_pattern<L: SynthLanguage>((s, column): (&str, usize)) -> LineResult<Pattern<L>> {
+    if s.is_empty() {
+        return Err((column, "expected a pattern".into()));
+    }
+    s.parse()
+        .map_err(|_| (column, format!("bad pattern `{}`", s)))
+}
+
+fn parse_meta(line: &str, start: usize) -> LineResult<RuleMeta> {
+    let mut meta = RuleMeta::default();
+    let mut offset = start;
+    for field in line[start..].split(' ') {
+        let column = offset;
+        offset += field.len() + 1;
+        if field.is_empty() {
+            continue;
+        }
+        let (key, value) = field
+            .split_once('=')
+            .ok_or((column, format!("expected `key=value`, found `{}`", field)))?;
+        match key {
+            "status" => {
+                meta.status = Some(match value {
+                    "valid" => RuleStatus::Valid,
+                    "invalid" => RuleStatus::Invalid,
+                    "unknown" => RuleStatus::Unknown,
+                    _ => return Err((column, format!("unknown status `{}`", value))),
+                })
+            }
+            "from" => meta.provenance = Some(value.to_string()),
+            "tags" => meta.tags = value.split(',').map(|t| t.to_string()).collect(),
+            _ => return Err((column, format!("unknown metadata `{}`", key))),
+        }
+    }
+    Ok(meta)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    const FILE: &str = "\
+# Learned from bv4
+[add-zero] (+ ?a 0) <=> ?a ; status=valid from=bv4 tags=arith,identity
+(* ?a ?b) ==> (* ?b ?a)
+[div-self] (/ ?a ?a) ==> 1 if (!= ?a 0) ; status=unknown
+";
+
+    #[test]
+    fn round_trip() {
+        let file: RuleFile<SymbolLang> = RuleFile::parse(FILE).unwrap();
+        assert_eq!(file.entries.len(), 3);
+        let add_zero = &file.entries[0];
+        assert_eq!(add_zero.name.as_deref(), Some("add-zero"));
+        assert!(add_zero.bidirectional);
+        assert_eq!(add_zero.meta.status, Some(RuleStatus::Valid));
+        assert_eq!(add_zero.meta.provenance.as_deref(), Some("bv4"));
+        assert_eq!(add_zero.meta.tags, vec!["arith", "identity"]);
+        assert!(file.entries[2].rule.is_conditional());
+        assert_eq!(file.ruleset().len(), 4);
+
+        let comment_free: String = FILE.lines().skip(1).flat_map(|l| [l, "\n"]).collect();
+        assert_eq!(file.to_string(), comment_free);
+    }
+
+    #[test]
+    fn merge_directions() {
+        let rules: Ruleset<SymbolLang> = Ruleset::new(["(+ ?a 0) <=> ?a", "(* ?a 0) ==> 0"]);
+        let file = RuleFile::from(&rules);
+        assert_eq!(file.to_string(), "(+ ?a 0) <=> ?a\n(* ?a 0) ==> 0\n");
+        assert_eq!(file.ruleset(), rules);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let error = |s: &str| {
+            let e = RuleFile::<SymbolLang>::parse(s).unwrap_err();
+            (e.line, e.column)
+        };
+        assert_eq!(error("(+ ?a 0) ==> ?a\n(+ ?a 0) ?a"), (2, 1));
+        assert_eq!(error("  [x (+ ?a 0) ==> ?a"), (1, 3));
+        assert_eq!(error("(+ ?a 0) ==> (+ ?a"), (1, 14));
+        assert_eq!(error("(+ ?a 0) ==> ?b"), (1, 14));
+        assert_eq!(error("(+ ?a ?b) <=> ?a"), (1, 1));
+        assert_eq!(error("?a ==> ?a ; status=maybe"), (1, 13));
+        assert_eq!(error("(f ?a) ==> ?a ?a"), (1, 15));
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let missing = std::env::temp_dir().join("enumo-missing.rules");
+        let e = RuleFile::<SymbolLang>::from_file(missing.to_str().unwrap()).unwrap_err();
+        assert!(matches!(e, RuleFileError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+    }
+}
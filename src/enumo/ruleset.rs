@@ -1,22 +1,236 @@
 use egg::{AstSize, EClass, Extractor, RecExpr};
 use indexmap::map::{IntoIter, Iter, IterMut, Values, ValuesMut};
 use itertools::Itertools;
-use log::{debug, info, warn};
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
-use std::{io::Write, sync::Arc};
-use z3::ast;
+use log::{debug, info};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator, ParallelSlice};
+use std::{collections::BinaryHeap, fmt, io, sync::Arc};
 
 use crate::{
-  CVec, DeriveType, EGraph, ExtractableAstSize, HashMap, Id, IndexMap, Limits,
-  Signature, SynthAnalysis, SynthLanguage,
+  CVec, Counterexample, DeriveType, EGraph, Escalation, ExtractableAstSize,
+  HashMap, HashSet, Id, IndexMap, Limits, Signature, SynthAnalysis,
+  SynthLanguage, ValidationResult, Var,
 };
 
-use super::{Rule, Scheduler};
+use super::{
+  Budget, DomainScorer, Proof, Rule, RuleFile, RuleFileError, RuleScorer,
+  Scheduler, Score, rename_vars,
+};
+
+/// Number of rules checked together by `Ruleset::derive_batched`.
+pub const DERIVE_BATCH_SIZE: usize = 32;
 
 /// A set of rewrite rules
 #[derive(Clone, Debug)]
 pub struct Ruleset<L: SynthLanguage>(pub IndexMap<Arc<str>, Rule<L>>);
 
+/// The outcome of `Ruleset::minimize`.
+#[derive(Debug, Clone)]
+pub struct Minimized<L: SynthLanguage> {
+  /// The selected rules, not including the prior rules.
+  pub chosen: Ruleset<L>,
+  /// Candidates that failed validation.
+  pub invalid: Ruleset<L>,
+  /// Models for the invalid candidates whose validator produced one, keyed by
+  /// rule name.
+  pub counterexamples: IndexMap<Arc<str>, Counterexample<L>>,
+  /// Candidates whose soundness is still unknown after escalation, and that
+  /// are not derivable from the chosen rules. They are not included in
+  /// `chosen`.
+  pub unknown: Ruleset<L>,
+  /// Reverses of picked candidates that were no longer candidates
+  /// themselves, e.g. because the chosen rules derive them. They are not
+  /// validated.
+  pub skipped_reverses: Ruleset<L>,
+  /// Whether the budget of the scheduler's `Limits` ran out, so `chosen` holds
+  /// only the rules chosen by then. The candidates that were not considered
+  /// are left in the ruleset being minimized.
+  pub partial: bool,
+}
+
+impl<L: SynthLanguage> Default for Minimized<L> {
+  fn default() -> Self {
+    Self {
+      chosen: Default::default(),
+      invalid: Default::default(),
+      counterexamples: Default::default(),
+      unknown: Default::default(),
+      skipped_reverses: Default::default(),
+      partial: false,
+    }
+  }
+}
+
+/// Options for `Ruleset::minimize_with`.
+pub struct MinimizeConfig<L: SynthLanguage> {
+  /// Number of candidates chosen per round, before the remaining candidates
+  /// are shrunk. Larger steps need fewer rounds, but may choose rules that
+  /// an earlier pick of the same round makes redundant.
+  pub step_size: usize,
+  /// Validate the top `step_size` candidates of a round in parallel, instead
+  /// of one at a time until `step_size` valid ones are found. A round then
+  /// chooses fewer rules when some of its candidates are not valid.
+  pub parallel_validation: bool,
+  /// How to retry candidates whose validation was `Unknown`.
+  pub escalation: Escalation<L>,
+  /// Orders the candidates; the best ones are chosen first.
+  pub scorer: Arc<dyn RuleScorer<L>>,
+}
+
+impl<L: SynthLanguage> Default for MinimizeConfig<L> {
+  fn default() -> Self {
+    Self {
+      step_size: 1,
+      parallel_validation: false,
+      escalation: Escalation::default(),
+      scorer: Arc::new(DomainScorer),
+    }
+  }
+}
+
+impl<L: SynthLanguage> Clone for MinimizeConfig<L> {
+  fn clone(&self) -> Self {
+    Self {
+      step_size: self.step_size,
+      parallel_validation: self.parallel_validation,
+      escalation: self.escalation.clone(),
+      scorer: self.scorer.clone(),
+    }
+  }
+}
+
+/// The candidates of `minimize`, best score first. Ties go to the candidate
+/// that was added last. Entries for candidates that have since been removed
+/// are skipped when popped.
+struct CandidateQueue<L: SynthLanguage> {
+  heap: BinaryHeap<(Score, usize, Arc<str>)>,
+  added: usize,
+  scorer: Arc<dyn RuleScorer<L>>,
+}
+
+impl<L: SynthLanguage> CandidateQueue<L> {
+  fn new(candidates: &Ruleset<L>, scorer: Arc<dyn RuleScorer<L>>) -> Self {
+    let mut queue = Self {
+      heap: BinaryHeap::new(),
+      added: 0,
+      scorer,
+    };
+    for rule in candidates.iter() {
+      queue.push(rule);
+    }
+    queue
+  }
+
+  fn push(&mut self, rule: &Rule<L>) {
+    let score = self.scorer.score(rule);
+    self.heap.push((score, self.added, rule.name.clone()));
+    self.added += 1;
+  }
+
+  /// Remove the best remaining candidate from `candidates`.
+  fn pop(&mut self, candidates: &mut Ruleset<L>) -> Option<Rule<L>> {
+    while let Some((_, _, name)) = self.heap.pop() {
+      if let Some(rule) = candidates.0.shift_remove(&name) {
+        return Some(rule);
+      }
+    }
+    None
+  }
+}
+
+/// The e-graph `minimize` checks derivability in, kept across its rounds.
+/// It holds the sides of every candidate; each round runs the newly chosen
+/// rules on it, and candidates whose sides merge are derivable.
+struct ShrinkGraph<L: SynthLanguage> {
+  egraph: EGraph<L, SynthAnalysis>,
+  /// The e-classes of the sides of each candidate
+  sides: HashMap<Arc<str>, (Id, Id)>,
+}
+
+impl<L: SynthLanguage> ShrinkGraph<L> {
+  fn new(candidates: &Ruleset<L>) -> Self {
+    let mut egraph = EGraph::default();
+    let mut sides = HashMap::default();
+    for rule in candidates.iter() {
+      let lhs = egraph.add_expr(&L::instantiate(&rule.lhs));
+      let rhs = egraph.add_expr(&L::instantiate(&rule.rhs));
+      sides.insert(rule.name.clone(), (lhs, rhs));
+    }
+    egraph.rebuild();
+    Self { egraph, sides }
+  }
+
+  /// Run the `chosen` rules, of which `new` were chosen this round. Rules
+  /// chosen earlier may fire on terms that only the new rules create, which
+  /// `Scheduler::Compress` does not keep, so all `chosen` rules are run
+  /// together, until they merge nothing more.
+  ///
+  /// Unlike rebuilding the e-graph from the remaining candidates every round,
+  /// this keeps the terms of discarded candidates, and rules accumulate
+  /// iterations across rounds. Both can only merge more, so `minimize` may
+  /// choose fewer rules than it would from scratch.
+  fn run(
+    &mut self,
+    new: &Ruleset<L>,
+    chosen: &Ruleset<L>,
+    scheduler: Scheduler,
+    budget: &mut Budget,
+  ) {
+    if new.is_empty() {
+      return;
+    }
+    let mut classes = self.egraph.number_of_classes();
+    let mut merged = self.num_merged();
+    loop {
+      self.egraph = scheduler.run_budgeted(&self.egraph, chosen, budget);
+      let (now_classes, now_merged) =
+        (self.egraph.number_of_classes(), self.num_merged());
+      if budget.exhausted() || (now_classes >= classes && now_merged <= merged)
+      {
+        break;
+      }
+      (classes, merged) = (now_classes, now_merged);
+    }
+  }
+
+  fn num_merged(&self) -> usize {
+    self
+      .sides
+      .values()
+      .filter(|(lhs, rhs)| self.egraph.find(*lhs) == self.egraph.find(*rhs))
+      .count()
+  }
+
+  /// Whether the sides of a candidate are in the same e-class.
+  fn merged(&self, rule: &Rule<L>) -> bool {
+    self.sides.get(&rule.name).is_some_and(|(lhs, rhs)| {
+      self.egraph.find(*lhs) == self.egraph.find(*rhs)
+    })
+  }
+}
+
+impl<L: SynthLanguage> Minimized<L> {
+  /// Validate a candidate, recording it if it is not valid.
+  fn check(&mut self, rule: &Rule<L>) -> bool {
+    self.record(rule, rule.validate())
+  }
+
+  /// Put a rule in the bucket for its validation result. Returns whether it
+  /// is valid.
+  fn record(&mut self, rule: &Rule<L>, result: ValidationResult<L>) -> bool {
+    match result {
+      ValidationResult::Valid => return true,
+      ValidationResult::Unknown => self.unknown.add(rule.clone()),
+      ValidationResult::Invalid(cex) => {
+        if let Some(cex) = cex {
+          self.counterexamples.insert(rule.name.clone(), cex);
+        }
+        self.invalid.add(rule.clone());
+      }
+    }
+    false
+  }
+}
+
 impl<L: SynthLanguage> PartialEq for Ruleset<L> {
   fn eq(&self, other: &Self) -> bool {
     if self.0.len() != other.0.len() {
@@ -64,28 +278,41 @@ impl<'a, L: SynthLanguage> IntoIterator for &'a mut Ruleset<L> {
   }
 }
 
+/// One line per rule, as in `Ruleset::pretty_strings`.
+impl<L: SynthLanguage> fmt::Display for Ruleset<L> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for line in self.pretty_strings() {
+      writeln!(f, "{}", line)?;
+    }
+    Ok(())
+  }
+}
+
 impl<L: SynthLanguage> Ruleset<L> {
+  /// The rules written as strings, see `Rule::from_string`. Panics on a rule
+  /// that does not parse, naming it and the error.
   pub fn new<I>(vals: I) -> Self
   where
     I: IntoIterator,
     I::Item: AsRef<str>,
   {
-    let mut map = IndexMap::default();
-    for v in vals {
-      if let Ok((forwards, backwards)) = Rule::from_string(v.as_ref()) {
-        map.insert(forwards.name.clone(), forwards);
-        if let Some(backwards) = backwards {
-          map.insert(backwards.name.clone(), backwards);
-        }
+    let mut rules = Ruleset::default();
+    for (i, v) in vals.into_iter().enumerate() {
+      let (forwards, backwards) = Rule::from_string(v.as_ref())
+        .unwrap_or_else(|e| panic!("rule {}: {}", i + 1, e));
+      rules.add(forwards);
+      if let Some(backwards) = backwards {
+        rules.add(backwards);
       }
     }
-    Ruleset(map)
+    rules
   }
 
+  /// Rules in both rulesets, modulo alpha-equivalence.
   pub fn union(&self, other: &Self) -> Self {
-    let mut map = self.0.clone();
-    map.extend(other.0.clone());
-    Ruleset(map)
+    let mut rules = self.clone();
+    rules.extend(other.clone());
+    rules
   }
 
   pub fn iter(&self) -> Values<'_, Arc<str>, Rule<L>> {
@@ -111,24 +338,30 @@ impl<L: SynthLanguage> Ruleset<L> {
   }
 
   pub fn bidir_len(&self) -> usize {
-    let mut bidir = 0;
-    let mut unidir = 0;
-    for (_, rule) in &self.0 {
-      let reverse = Rule::new(&rule.rhs, &rule.lhs);
-      if reverse.is_some() && self.contains(&reverse.unwrap()) {
-        bidir += 1;
-      } else {
-        unidir += 1;
+    let mut len = 0;
+    let mut paired: HashSet<Arc<str>> = HashSet::default();
+    for (name, rule) in &self.0 {
+      if paired.contains(name) {
+        continue;
+      }
+      len += 1;
+      if let Some(reverse) = rule.reverse() {
+        if self.contains(&reverse) {
+          paired.insert(reverse.canonical_name());
+        }
       }
     }
-    unidir + (bidir / 2)
+    len
   }
 
+  /// Whether the ruleset contains a rule alpha-equivalent to `rule`.
   pub fn contains(&self, rule: &Rule<L>) -> bool {
-    self.0.contains_key(&rule.name)
+    self.0.contains_key(&rule.canonical_name())
   }
 
+  /// Add a rule in canonical form, keyed by its canonical name.
   pub fn add(&mut self, rule: Rule<L>) {
+    let rule = rule.canonical();
     self.0.insert(rule.name.clone(), rule);
   }
 
@@ -158,14 +391,17 @@ impl<L: SynthLanguage> Ruleset<L> {
     }
   }
 
+  /// Remove the rules alpha-equivalent to a rule in `other`.
   pub fn remove_all(&mut self, other: Self) {
-    for (name, _) in other.0 {
-      self.0.remove(&name);
+    for (_, rule) in other.0 {
+      self.0.remove(&rule.canonical_name());
     }
   }
 
   pub fn extend(&mut self, other: Self) {
-    self.0.extend(other.0)
+    for (_, rule) in other.0 {
+      self.add(rule);
+    }
   }
 
   // pub fn extend_shrink(self, other: Self, minimize_limits: Limits ) -> Self {
@@ -191,48 +427,75 @@ impl<L: SynthLanguage> Ruleset<L> {
     (yes, no)
   }
 
-  pub fn to_file(&self, filename: &str) {
-    let mut file = std::fs::File::create(filename)
-      .unwrap_or_else(|_| panic!("Failed to open '{}'", filename));
-    for (name, _) in &self.0 {
-      writeln!(file, "{}", name).expect("Unable to write");
-    }
+  /// Validate all rules in parallel.
+  /// Results are cached, so rules validated before are not checked again.
+  pub fn validate_all(&self) -> IndexMap<Arc<str>, ValidationResult<L>> {
+    let rules: Vec<&Rule<L>> = self.0.values().collect();
+    let results: Vec<_> = rules
+      .into_par_iter()
+      .map(|rule| (rule.name.clone(), rule.validate()))
+      .collect();
+    results.into_iter().collect()
   }
 
-  pub fn from_file(filename: &str) -> Self {
-    let infile = std::fs::File::open(filename).expect("can't open file");
-    let reader = std::io::BufReader::new(infile);
-    let mut all_rules = IndexMap::default();
-    for line in std::io::BufRead::lines(reader) {
-      let line = line.unwrap();
-      if let Ok((forwards, backwards)) = Rule::from_string(&line) {
-        all_rules.insert(forwards.name.clone(), forwards);
-        if let Some(backwards) = backwards {
-          all_rules.insert(backwards.name.clone(), backwards);
+  /// Validate the rules and collect the counterexamples for the invalid ones.
+  /// Only one direction of each pair of rules is checked.
+  pub fn counterexamples(&self) -> Vec<Counterexample<L>> {
+    let mut one_way = Ruleset::default();
+    for rule in self.0.values() {
+      match rule.reverse() {
+        Some(reverse) if one_way.contains(&reverse) => (),
+        _ => one_way.add(rule.clone()),
+      }
+    }
+    let mut cexs = vec![];
+    for (_, result) in one_way.validate_all() {
+      if let ValidationResult::Invalid(Some(cex)) = result {
+        if !cexs.contains(&cex) {
+          cexs.push(cex);
         }
       }
     }
-    Self(all_rules)
+    cexs
+  }
+
+  /// Write the rules as a rule file, see `RuleFile`.
+  pub fn to_file(&self, filename: &str) -> io::Result<()> {
+    RuleFile::from(self).to_file(filename)
+  }
+
+  /// Read the rules of a rule file, see `RuleFile`.
+  pub fn from_file(filename: &str) -> Result<Self, RuleFileError> {
+    Ok(RuleFile::from_file(filename)?.ruleset())
   }
 
+  #[deprecated(note = "print the ruleset with its `Display` impl instead")]
   pub fn pretty_print(&self) {
-    println!("pretty-printing ruleset: {} rules", self.len());
+    print!("{}", self);
+  }
+
+  /// One line per rule, with each pair of a rule and its reverse merged into
+  /// one `<=>` line.
+  pub fn pretty_strings(&self) -> Vec<String> {
     let mut strs = vec![];
+    let mut paired: HashSet<Arc<str>> = HashSet::default();
     for (name, rule) in &self.0 {
-      let reverse = Rule::new(&rule.rhs, &rule.lhs);
-      if reverse.is_some() && self.contains(&reverse.unwrap()) {
-        let reverse_name = format!("{} <=> {}", rule.rhs, rule.lhs);
-        if !strs.contains(&reverse_name) {
-          strs.push(format!("{} <=> {}", rule.lhs, rule.rhs));
+      if paired.contains(name) {
+        continue;
+      }
+      match rule.reverse() {
+        Some(reverse) if self.contains(&reverse) => {
+          paired.insert(reverse.canonical_name());
+          let cond = match &rule.cond {
+            Some(cond) => format!(" if {}", cond),
+            None => String::new(),
+          };
+          strs.push(format!("{} <=> {}{}", rule.lhs, rule.rhs, cond));
         }
-      } else {
-        strs.push(name.to_string());
+        _ => strs.push(name.to_string()),
       }
     }
-
-    for s in strs {
-      println!("{s}");
-    }
+    strs
   }
 
   /// Find candidates from two e-graphs
@@ -283,7 +546,7 @@ impl<L: SynthLanguage> Ruleset<L> {
     // ∀𝑖. 𝑎𝑖 = 𝑏𝑖 ∨ 𝑎𝑖 = null ∨ 𝑏𝑖 = null and ∃𝑖. 𝑎𝑖 = 𝑏𝑖 ∧ 𝑎𝑖 ≠ null ∧ 𝑏𝑖 ≠
     // null
 
-    println!(
+    debug!(
       "starting cvec match with {} eclasses",
       egraph.number_of_classes()
     );
@@ -351,7 +614,7 @@ impl<L: SynthLanguage> Ruleset<L> {
       }
     }
 
-    println!(
+    debug!(
       "cvec match finished in {} ms",
       time_start.elapsed().as_millis()
     );
@@ -402,7 +665,7 @@ impl<L: SynthLanguage> Ruleset<L> {
           has_constant_subexpr = true;
           info!(
             "{} has constant subexpr: {} = {}",
-            e.to_string(),
+            e,
             extract.find_best_node(sub_eclass_id),
             constant
           )
@@ -427,69 +690,74 @@ impl<L: SynthLanguage> Ruleset<L> {
     candidates
   }
 
-  fn select(&mut self, step_size: usize, invalid: &mut Ruleset<L>) -> Self {
-    let mut chosen = Self::default();
-    self
-      .0
-      .sort_by(|_, rule1, _, rule2| rule1.score().cmp(&rule2.score()));
-
-    // 2. insert step_size best candidates into self.new_rws
+  /// Take the next `config.step_size` candidates off the queue and return
+  /// the valid ones. The reverse of a candidate is taken along with it.
+  /// Takes no more candidates once `budget` runs out.
+  fn select(
+    &mut self,
+    queue: &mut CandidateQueue<L>,
+    config: &MinimizeConfig<L>,
+    budget: &Budget,
+    result: &mut Minimized<L>,
+  ) -> Self {
     let mut selected: Ruleset<L> = Default::default();
-    while selected.len() < step_size {
-      let popped = self.0.pop();
-      if let Some((_, rule)) = popped {
-        if rule.is_valid() {
-          selected.add(rule.clone());
-        } else {
-          invalid.add(rule.clone());
-        }
-
-        // If reverse direction is also in candidates, add it at the same time
-        let reverse = Rule::new(&rule.rhs, &rule.lhs);
-        if let Some(reverse) = reverse {
-          if self.contains(&reverse) && reverse.is_valid() {
-            selected.add(reverse);
-          } else {
-            invalid.add(reverse);
-          }
-        }
+    let mut batch: Vec<Rule<L>> = vec![];
+    let taken = |selected: &Self, batch: &Vec<Rule<L>>| {
+      if config.parallel_validation {
+        batch.len()
       } else {
+        selected.len()
+      }
+    };
+    while taken(&selected, &batch) < config.step_size && !budget.exhausted() {
+      let Some(rule) = queue.pop(self) else {
         break;
+      };
+      // The reverse of an earlier pick of this round
+      if selected.contains(&rule) || batch.iter().any(|r| r.name == rule.name) {
+        continue;
       }
-    }
-    chosen.extend(selected);
-
-    // 3. return chosen candidates
-    chosen
-  }
-
-  fn shrink(&mut self, chosen: &Self, scheduler: Scheduler) {
-    // 1. make new egraph
-    // let mut egraph: EGraph<L, SynthAnalysis> = EGraph::default();
-    let mut egraph = EGraph::default();
 
-    let mut initial = vec![];
-    // 2. insert lhs and rhs of all candidates as roots
-    for rule in self.0.values() {
-      let lhs = egraph.add_expr(&L::instantiate(&rule.lhs));
-      let rhs = egraph.add_expr(&L::instantiate(&rule.rhs));
-      initial.push((lhs, rhs, rule.clone()));
+      // If reverse direction is also in candidates, add it at the same time.
+      // Rules like commutativity are their own reverse
+      let reverse = rule
+        .reverse()
+        .filter(|reverse| reverse.canonical_name() != rule.name);
+      let reverse = match reverse {
+        Some(reverse) if !self.contains(&reverse) => {
+          result.skipped_reverses.add(reverse);
+          None
+        }
+        reverse => reverse,
+      };
+
+      for rule in std::iter::once(rule).chain(reverse) {
+        if config.parallel_validation {
+          batch.push(rule);
+        } else if result.check(&rule) {
+          selected.add(rule);
+        }
+      }
     }
 
-    // 3. compress with the rules we've chosen so far
-    let egraph = scheduler.run(&egraph, chosen);
-
-    // 4. go through candidates and if they have merged, then
-    // they are no longer candidates
-    self.0 = Default::default();
-    for (l_id, r_id, rule) in initial {
-      if egraph.find(l_id) == egraph.find(r_id) {
-        // candidate has merged (derivable from other rewrites)
-        continue;
-      } else {
-        self.add(rule);
+    let results: Vec<_> = batch
+      .into_par_iter()
+      .map(|rule| {
+        let res = rule.validate();
+        (rule, res)
+      })
+      .collect();
+    for (rule, res) in results {
+      if result.record(&rule, res) {
+        selected.add(rule);
       }
     }
+    selected
+  }
+
+  /// Remove the candidates whose sides `graph` has merged.
+  fn shrink(&mut self, graph: &ShrinkGraph<L>) {
+    self.0.retain(|_, rule| !graph.merged(rule));
   }
 
   /// Minimization algorithm for rule selection
@@ -497,23 +765,103 @@ impl<L: SynthLanguage> Ruleset<L> {
   ///         1. select the best rule candidate
   ///         2. filter out candidates that are redundant given the addition of
   ///            the selected rule
+  ///
+  /// Candidates whose validation is `Unknown` are set aside. Once the
+  /// candidates run out, those that are not derivable from the chosen rules
+  /// are validated again with the `Escalation` of the config. Rules that turn
+  /// out to be valid become candidates again, the others end up in `invalid`
+  /// or `unknown`.
+  ///
+  /// Stops early, with a `partial` result, once the wall-clock or e-node
+  /// budget of the scheduler's `Limits` runs out.
   pub fn minimize(
     &mut self,
     prior: Ruleset<L>,
     scheduler: Scheduler,
-  ) -> (Self, Self) {
-    let mut invalid: Ruleset<L> = Default::default();
+  ) -> Minimized<L> {
+    self.minimize_with(prior, scheduler, &MinimizeConfig::default())
+  }
+
+  /// `minimize` with the step size, validation, escalation and candidate
+  /// order set by `config`.
+  pub fn minimize_with(
+    &mut self,
+    prior: Ruleset<L>,
+    scheduler: Scheduler,
+    config: &MinimizeConfig<L>,
+  ) -> Minimized<L> {
+    let mut budget = Budget::new(scheduler.limits());
+    self.minimize_budgeted(prior, scheduler, config, &mut budget)
+  }
+
+  /// `minimize_with`, within what is left of `budget` rather than the budget
+  /// of the scheduler's `Limits`. Candidates are only validated while the
+  /// budget lasts.
+  pub(crate) fn minimize_budgeted(
+    &mut self,
+    prior: Ruleset<L>,
+    scheduler: Scheduler,
+    config: &MinimizeConfig<L>,
+    budget: &mut Budget,
+  ) -> Minimized<L> {
+    let mut result = Minimized::default();
     let mut chosen = prior.clone();
-    let step_size = 1;
-    while !self.is_empty() {
-      let selected = self.select(step_size, &mut invalid);
-      chosen.extend(selected.clone());
-      self.shrink(&chosen, scheduler);
+    let mut queue = CandidateQueue::new(self, config.scorer.clone());
+    let mut graph = ShrinkGraph::new(self);
+    // Rules not yet run on `graph`
+    let mut new = prior.clone();
+    let mut escalated = false;
+    'minimize: loop {
+      while !self.is_empty() {
+        if budget.exhausted() {
+          result.partial = true;
+          break 'minimize;
+        }
+        let selected = self.select(&mut queue, config, budget, &mut result);
+        new.extend(selected.clone());
+        chosen.extend(selected);
+        graph.run(&std::mem::take(&mut new), &chosen, scheduler, budget);
+        self.shrink(&graph);
+      }
+      if escalated || result.unknown.is_empty() {
+        break;
+      }
+      if budget.exhausted() {
+        result.partial = true;
+        break;
+      }
+      escalated = true;
+
+      let mut unknown = std::mem::take(&mut result.unknown);
+      unknown.shrink(&graph);
+      let rules: Vec<&Rule<L>> = unknown.0.values().collect();
+      let budget = &*budget;
+      let results: Vec<_> = rules
+        .into_par_iter()
+        .map(|rule| {
+          let res = (!budget.exhausted())
+            .then(|| rule.validate_escalating(&config.escalation));
+          (rule, res)
+        })
+        .collect();
+      for (rule, res) in results {
+        let Some(res) = res else {
+          // Ran out of budget before validating it
+          result.unknown.add(rule.clone());
+          result.partial = true;
+          continue;
+        };
+        if result.record(rule, res) {
+          queue.push(rule);
+          self.add(rule.clone());
+        }
+      }
     }
     // Return only the new rules
     chosen.remove_all(prior);
+    result.chosen = chosen;
 
-    (chosen, invalid)
+    result
   }
 
   /// Whether a given rule can be derived by the ruleset with given resource
@@ -524,14 +872,77 @@ impl<L: SynthLanguage> Ruleset<L> {
   ///        rhs
   ///     2. Run the ruleset
   ///     3. Return true if the lhs and rhs are equivalent, false otherwise.
+  ///
+  /// Conditional rules in the ruleset only fire where their guard holds.
+  /// A conditional target rule is never derivable: the e-graph has no way to
+  /// assume its guard, and its sides merging without it would prove a
+  /// different rule.
   pub fn can_derive(
     &self,
     derive_type: DeriveType,
     rule: &Rule<L>,
     limits: Limits,
   ) -> bool {
+    if rule.is_conditional() {
+      return false;
+    }
+    let egraph =
+      self.derive_egraph(Default::default(), derive_type, rule, limits);
+    let lexpr = &L::instantiate(&rule.lhs);
+    let rexpr = &L::instantiate(&rule.rhs);
+
+    let l_id = egraph
+      .lookup_expr(lexpr)
+      .unwrap_or_else(|| panic!("Did not find {}", lexpr));
+    let r_id = egraph.lookup_expr(rexpr);
+    if let Some(r_id) = r_id {
+      l_id == r_id
+    } else {
+      false
+    }
+  }
+
+  /// Like `can_derive`, but with egg explanations enabled, so that a
+  /// successful derivation also returns the rewrites that take the lhs of
+  /// `rule` to its rhs. Returns None if `rule` is not derivable, which
+  /// includes every conditional rule.
+  ///
+  /// Explanations make the e-graph slower and larger, so use `can_derive`
+  /// when only the answer is needed.
+  pub fn prove(
+    &self,
+    derive_type: DeriveType,
+    rule: &Rule<L>,
+    limits: Limits,
+  ) -> Option<Proof<L>> {
+    if rule.is_conditional() {
+      return None;
+    }
+    let egraph = EGraph::default().with_explanations_enabled();
+    let mut egraph = self.derive_egraph(egraph, derive_type, rule, limits);
+    let lexpr = &L::instantiate(&rule.lhs);
+    let rexpr = &L::instantiate(&rule.rhs);
+
+    let l_id = egraph.lookup_expr(lexpr)?;
+    let r_id = egraph.lookup_expr(rexpr)?;
+    if l_id != r_id {
+      return None;
+    }
+    let mut explanation = egraph.explain_equivalence(lexpr, rexpr);
+    let flat = explanation.make_flat_explanation();
+    Some(Proof::from_flat(rule.clone(), flat))
+  }
+
+  /// Run this ruleset on `egraph` seeded according to `derive_type`, stopping
+  /// as soon as the sides of `rule` merge.
+  fn derive_egraph(
+    &self,
+    mut egraph: EGraph<L, SynthAnalysis>,
+    derive_type: DeriveType,
+    rule: &Rule<L>,
+    limits: Limits,
+  ) -> EGraph<L, SynthAnalysis> {
     let scheduler = Scheduler::Saturating(limits);
-    let mut egraph: EGraph<L, SynthAnalysis> = Default::default();
     let lexpr = &L::instantiate(&rule.lhs);
     let rexpr = &L::instantiate(&rule.rhs);
 
@@ -545,17 +956,7 @@ impl<L: SynthLanguage> Ruleset<L> {
       }
     }
 
-    let out_egraph = scheduler.run_derive(&egraph, self, rule);
-
-    let l_id = out_egraph
-      .lookup_expr(lexpr)
-      .unwrap_or_else(|| panic!("Did not find {}", lexpr));
-    let r_id = out_egraph.lookup_expr(rexpr);
-    if let Some(r_id) = r_id {
-      l_id == r_id
-    } else {
-      false
-    }
+    scheduler.run_derive(&egraph, self, rule)
   }
 
   /// Partition a ruleset into derivable / not-derivable with respect to this
@@ -569,20 +970,463 @@ impl<L: SynthLanguage> Ruleset<L> {
     against.partition(|rule| self.can_derive(derive_type, rule, limits))
   }
 
-  pub fn print_derive(derive_type: DeriveType, one: &str, two: &str) {
-    let r1: Ruleset<L> = Ruleset::from_file(one);
-    let r2: Ruleset<L> = Ruleset::from_file(two);
+  /// Partition `against` like `derive`, but check many rules at once.
+  ///
+  /// The rules are split into batches of `DERIVE_BATCH_SIZE`. The sides of
+  /// every rule in a batch are added to one e-graph, with each rule's
+  /// variables renamed apart, and this ruleset is run on it once. Rules can
+  /// still share ground terms, or terms built by the ruleset, and then one
+  /// rule's terms may help derive another that `can_derive` would not. So a
+  /// rule is only derivable if its sides merged in a part of the e-graph that
+  /// no other rule of the batch reaches. All other rules fall back to
+  /// `can_derive`, which also finds rules missed because the shared e-graph
+  /// hit a limit.
+  pub fn derive_batched(
+    &self,
+    derive_type: DeriveType,
+    against: &Self,
+    limits: Limits,
+  ) -> (Self, Self) {
+    let rules: Vec<&Rule<L>> =
+      against.iter().filter(|rule| !rule.is_conditional()).collect();
+    let proved: HashSet<Arc<str>> = rules
+      .par_chunks(DERIVE_BATCH_SIZE)
+      .flat_map_iter(|batch| self.derive_batch(derive_type, batch, limits))
+      .collect();
+    against.partition(|rule| {
+      proved.contains(&rule.name) || self.can_derive(derive_type, rule, limits)
+    })
+  }
+
+  /// The names of the rules in `batch` whose sides merge in a shared e-graph,
+  /// apart from the terms of the other rules.
+  fn derive_batch(
+    &self,
+    derive_type: DeriveType,
+    batch: &[&Rule<L>],
+    limits: Limits,
+  ) -> Vec<Arc<str>> {
+    let mut egraph: EGraph<L, SynthAnalysis> = Default::default();
+    let exprs: Vec<(RecExpr<L>, RecExpr<L>)> = batch
+      .iter()
+      .enumerate()
+      .map(|(i, rule)| {
+        let (lexpr, rexpr) = instantiate_apart(rule, i);
+        egraph.add_expr(&lexpr);
+        if let DeriveType::LhsAndRhs = derive_type {
+          egraph.add_expr(&rexpr);
+        }
+        (lexpr, rexpr)
+      })
+      .collect();
+
+    let out =
+      Scheduler::Saturating(limits).run_derive_all(&egraph, self, &exprs);
+    let component = components(&out);
+    // The components reached by each rule's terms
+    let reached: Vec<Vec<Id>> = exprs
+      .iter()
+      .map(|(lexpr, rexpr)| {
+        let mut roots = vec![out.lookup_expr(lexpr).unwrap()];
+        if let DeriveType::LhsAndRhs = derive_type {
+          roots.push(out.lookup_expr(rexpr).unwrap());
+        }
+        roots.iter().map(|id| component[id]).collect()
+      })
+      .collect();
+    let mut rules_reaching: HashMap<Id, usize> = HashMap::default();
+    for comps in &reached {
+      for comp in comps.iter().unique() {
+        *rules_reaching.entry(*comp).or_default() += 1;
+      }
+    }
+
+    batch
+      .iter()
+      .zip(exprs)
+      .zip(reached)
+      .filter(|((_, (lexpr, rexpr)), comps)| {
+        let merged = match (out.lookup_expr(lexpr), out.lookup_expr(rexpr)) {
+          (Some(l_id), Some(r_id)) => l_id == r_id,
+          _ => false,
+        };
+        merged && comps.iter().all(|comp| rules_reaching[comp] == 1)
+      })
+      .map(|((rule, _), _)| rule.name.clone())
+      .collect()
+  }
+
+  /// Like `derive`, but returns a proof for each derivable rule instead of
+  /// the derivable rules themselves.
+  pub fn derive_with_proofs(
+    &self,
+    derive_type: DeriveType,
+    against: &Self,
+    limits: Limits,
+  ) -> (Vec<Proof<L>>, Self) {
+    let mut proofs = vec![];
+    let mut cannot = Ruleset::default();
+    for rule in against.iter() {
+      match self.prove(derive_type, rule, limits) {
+        Some(proof) => proofs.push(proof),
+        None => cannot.add(rule.clone()),
+      }
+    }
+    (proofs, cannot)
+  }
+}
+
+/// Instantiate both sides of `rule`, giving its variables names that are
+/// unique to the `i`th rule of a batch.
+fn instantiate_apart<L: SynthLanguage>(
+  rule: &Rule<L>,
+  i: usize,
+) -> (RecExpr<L>, RecExpr<L>) {
+  let mut renaming: Vec<(Var, Var)> = vec![];
+  for var in rule.lhs.vars().into_iter().chain(rule.rhs.vars()) {
+    if !renaming.iter().any(|(_, old)| *old == var) {
+      let new = format!("{}_{}", var, i).parse().unwrap();
+      renaming.push((new, var));
+    }
+  }
+  (
+    L::instantiate(&rename_vars(&rule.lhs, &renaming)),
+    L::instantiate(&rename_vars(&rule.rhs, &renaming)),
+  )
+}
+
+/// The connected parts of `egraph`, as a representative e-class for every
+/// e-class. E-classes are connected when one has an e-node with the other as
+/// a child.
+fn components<L: SynthLanguage>(
+  egraph: &EGraph<L, SynthAnalysis>,
+) -> HashMap<Id, Id> {
+  fn find(parent: &HashMap<Id, Id>, mut id: Id) -> Id {
+    while parent[&id] != id {
+      id = parent[&id];
+    }
+    id
+  }
+
+  let mut parent: HashMap<Id, Id> =
+    egraph.classes().map(|class| (class.id, class.id)).collect();
+  for class in egraph.classes() {
+    for child in class.nodes.iter().flat_map(|node| node.children()) {
+      let a = find(&parent, class.id);
+      let b = find(&parent, egraph.find(*child));
+      parent.insert(a, b);
+    }
+  }
+  parent.keys().map(|id| (*id, find(&parent, *id))).collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{bv4, enumo::Workload, validation_cache};
+  use egg::SymbolLang;
+
+  /// `minimize` with a new e-graph of the remaining candidates every round,
+  /// on which all the chosen rules are run.
+  fn minimize_rebuilding<L: SynthLanguage>(
+    mut candidates: Ruleset<L>,
+    scheduler: Scheduler,
+  ) -> Ruleset<L> {
+    let config = MinimizeConfig::default();
+    let mut queue = CandidateQueue::new(&candidates, config.scorer.clone());
+    let mut result = Minimized::default();
+    let mut chosen = Ruleset::default();
+    while !candidates.is_empty() {
+      let budget = Budget::default();
+      chosen.extend(candidates.select(&mut queue, &config, &budget, &mut result));
+      let mut egraph: EGraph<L, SynthAnalysis> = EGraph::default();
+      let sides: HashMap<Arc<str>, (Id, Id)> = candidates
+        .iter()
+        .map(|rule| {
+          let lhs = egraph.add_expr(&L::instantiate(&rule.lhs));
+          let rhs = egraph.add_expr(&L::instantiate(&rule.rhs));
+          (rule.name.clone(), (lhs, rhs))
+        })
+        .collect();
+      let egraph = scheduler.run(&egraph, &chosen);
+      candidates.0.retain(|name, _| {
+        let (lhs, rhs) = sides[name];
+        egraph.find(lhs) != egraph.find(rhs)
+      });
+    }
+    chosen
+  }
+
+  #[test]
+  fn shrink_matches_rebuilding() {
+    let terms = Workload::new(["a", "b", "0", "1"])
+      .append(Workload::new(["(- a)", "(~ a)", "(- (- a))", "(~ (~ a))"]))
+      .append(Workload::new(["(+ a b)", "(+ b a)", "(* a 1)", "(& a a)"]))
+      .append(Workload::new(["(| a b)", "(| b a)", "(+ a 0)", "(-- a b)"]))
+      .append(Workload::new(["(+ a a)", "(* a 2)", "(<< a 1)", "(+ a (* a 1))"]))
+      .append(Workload::new(["(- (+ a b))", "(+ (- a) (- b))", "(* 2 (- a))"]));
+    let candidates: Ruleset<bv4::Bv> =
+      Ruleset::cvec_match(&terms.to_egraph::<bv4::Bv>());
+    let scheduler = Scheduler::Compress(Limits::minimize());
+
+    // The kept e-graph can merge more than rebuilding, but not on this
+    // workload
+    let rebuilt = minimize_rebuilding(candidates.clone(), scheduler);
+    let kept = candidates.clone().minimize(Ruleset::default(), scheduler);
+    assert_eq!(kept.chosen.to_str_vec(), rebuilt.to_str_vec());
+  }
+
+  #[test]
+  fn shrink_reruns_earlier_rules() {
+    let (target, _) =
+      Rule::<bv4::Bv>::from_string("(* ?a 2) ==> (<< ?a 1)").unwrap();
+    let mut candidates = Ruleset::default();
+    candidates.add(target.clone());
+    let mut graph = ShrinkGraph::new(&candidates);
+    let scheduler = Scheduler::Compress(Limits::minimize());
+    let mut budget = Budget::new(Limits::minimize());
+
+    let double: Ruleset<bv4::Bv> = Ruleset::new(["(* ?a 2) ==> (+ ?a ?a)"]);
+    graph.run(&double, &double, scheduler, &mut budget);
+    assert!(!graph.merged(&target));
+
+    // `shift` only fires on `(+ ?a ?a)`, which `double` creates but
+    // `Scheduler::Compress` does not keep
+    let shift = Ruleset::new(["(+ ?a ?a) ==> (<< ?a 1)"]);
+    graph.run(&shift, &double.union(&shift), scheduler, &mut budget);
+    assert!(graph.merged(&target));
+  }
+
+  #[test]
+  fn alpha_equivalent_rules_dedupe() {
+    let learned: Ruleset<SymbolLang> =
+      Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)", "(* ?a 1) ==> ?a"]);
+    let baseline: Ruleset<SymbolLang> =
+      Ruleset::new(["(+ ?y ?x) ==> (+ ?x ?y)", "(* ?x 0) ==> 0"]);
+    assert_eq!(learned.union(&baseline).len(), 3);
+
+    let (comm, _) = Rule::from_string("(+ ?x ?y) ==> (+ ?y ?x)").unwrap();
+    assert!(baseline.contains(&comm));
+    assert_eq!(comm.canonical().name, "(+ ?a ?b) ==> (+ ?b ?a)".into());
+    assert_eq!(comm.canonical_name(), comm.canonical().name);
+
+    let mut rules = learned.clone();
+    rules.remove_all(baseline);
+    assert_eq!(rules.to_str_vec(), vec!["(* ?a 1) ==> ?a"]);
+  }
+
+  #[test]
+  fn symmetric_rule_is_bidirectional() {
+    let rules: Ruleset<SymbolLang> =
+      Ruleset::new(["(+ ?a ?b) <=> (+ ?b ?a)", "(f ?a ?b) <=> (g ?b ?a)"]);
+    assert_eq!(rules.len(), 3);
+    assert_eq!(rules.bidir_len(), 2);
+  }
+
+  #[test]
+  fn display_pairs_reverses() {
+    let rules: Ruleset<SymbolLang> =
+      Ruleset::new(["(* ?a 1) <=> ?a", "(+ ?a 0) ==> ?a"]);
+    assert_eq!(rules.to_string(), "(* ?a 1) <=> ?a\n(+ ?a 0) ==> ?a\n");
+  }
+
+  #[test]
+  fn prove_lists_applied_rules() {
+    let rules: Ruleset<SymbolLang> =
+      Ruleset::new(["(+ ?a ?b) ==> (+ ?b ?a)", "(+ ?a 0) ==> ?a"]);
+    let (target, _) = Rule::from_string("(+ 0 (+ ?x 0)) ==> ?x").unwrap();
+    let proof = rules
+      .prove(DeriveType::Lhs, &target, Limits::deriving())
+      .unwrap();
+    assert_eq!(proof.start.to_string(), "(+ 0 (+ x 0))");
+    assert_eq!(proof.steps.last().unwrap().term.to_string(), "x");
+    for name in proof.rules() {
+      assert!(rules.0.contains_key(&name), "unknown rule {}", name);
+    }
+    assert!(proof.to_string().contains("by (+ ?a 0) ==> ?a"));
+
+    let (underivable, _) = Rule::from_string("(* ?x 1) ==> ?x").unwrap();
+    assert!(
+      rules
+        .prove(DeriveType::LhsAndRhs, &underivable, Limits::deriving())
+        .is_none()
+    );
+  }
+
+  #[test]
+  fn batched_derive_matches_derive() {
+    let rules: Ruleset<SymbolLang> = Ruleset::new([
+      "(+ ?a ?b) ==> (+ ?b ?a)",
+      "(+ ?a 0) ==> ?a",
+      "(* ?a 1) ==> ?a",
+      "(* ?a (+ ?b ?c)) ==> (+ (* ?a ?b) (* ?a ?c))",
+    ]);
+    let against: Ruleset<SymbolLang> = Ruleset::new([
+      "(+ 0 ?a) ==> ?a",
+      "(* (+ ?a 0) 1) ==> ?a",
+      "(* ?a 0) ==> 0",
+      "(+ (* ?a ?b) (* ?a ?c)) ==> (* ?a (+ ?c ?b))",
+      "(- ?a ?a) ==> 0",
+      "?b ==> (+ 0 (* ?b 1))",
+    ]);
+    for derive_type in [DeriveType::Lhs, DeriveType::LhsAndRhs] {
+      let (can, cannot) =
+        rules.derive(derive_type, &against, Limits::deriving());
+      let batched =
+        rules.derive_batched(derive_type, &against, Limits::deriving());
+      assert_eq!((can, cannot), batched);
+    }
+  }
+
+  #[test]
+  fn batched_derive_keeps_rules_apart() {
+    let rules: Ruleset<bv4::Bv> = Ruleset::new(["(-- ?a ?a) ==> 0"]);
+    // The rhs of the first rule is only in the e-graph with the second's lhs
+    let against: Ruleset<bv4::Bv> =
+      Ruleset::new(["(+ ?a 0) ==> (+ ?a (-- 1 1))", "(-- 1 1) ==> 0"]);
+    let (can, cannot) =
+      rules.derive(DeriveType::Lhs, &against, Limits::deriving());
+    assert_eq!(can.len(), 1);
+    assert_eq!(cannot.len(), 1);
+    assert_eq!(
+      rules.derive_batched(DeriveType::Lhs, &against, Limits::deriving()),
+      (can, cannot)
+    );
+  }
+
+  #[test]
+  fn unknown_candidates_are_escalated() {
+    use bv4::Bv;
+
+    let rule = |l: &str, r: &str| -> Rule<Bv> {
+      Rule::new(&l.parse().unwrap(), &r.parse().unwrap()).unwrap()
+    };
+    // The validation cache is shared by the tests of this process, so these
+    // rules must not be validated by any other test
+    let comm = rule("(^ ?a ?b)", "(^ ?b ?a)");
+    let wrong = rule("(^ ?a ?b)", "(-- ?a ?b)");
+    // Pretend the first attempt timed out
+    validation_cache::insert::<Bv>(&comm.name, ValidationResult::Unknown);
+    validation_cache::insert::<Bv>(&wrong.name, ValidationResult::Unknown);
 
-    let (can, cannot) = r1.derive(derive_type, &r2, Limits::deriving());
-    println!(
-      "Using {} ({}) to derive {} ({}).\nCan derive {}, cannot derive {}. Missing:",
-      one,
-      r1.len(),
-      two,
-      r2.len(),
-      can.len(),
-      cannot.len()
+    let mut candidates = Ruleset::default();
+    candidates.add(comm.clone());
+    candidates.add(wrong.clone());
+    let minimized = candidates
+      .minimize(Ruleset::default(), Scheduler::Compress(Limits::minimize()));
+    assert!(minimized.chosen.contains(&comm));
+    assert!(minimized.invalid.contains(&wrong));
+    assert!(minimized.counterexamples.contains_key(&wrong.name));
+    assert!(minimized.unknown.is_empty());
+  }
+
+  #[test]
+  fn reverses_without_candidates_are_not_invalid() {
+    use bv4::Bv;
+
+    let identity: Rule<Bv> =
+      Rule::new(&"(+ ?a 0)".parse().unwrap(), &"?a".parse().unwrap()).unwrap();
+    let mut candidates = Ruleset::default();
+    candidates.add(identity.clone());
+    let minimized = candidates
+      .minimize(Ruleset::default(), Scheduler::Compress(Limits::minimize()));
+    assert!(minimized.chosen.contains(&identity));
+    assert!(minimized.invalid.is_empty());
+    assert!(minimized
+      .skipped_reverses
+      .contains(&identity.reverse().unwrap()));
+  }
+
+  #[test]
+  fn minimize_in_parallel_steps() {
+    use bv4::Bv;
+
+    let terms = Workload::new(["a", "b", "0", "1"])
+      .append(Workload::new(["(- a)", "(~ a)", "(- (- a))", "(~ (~ a))"]))
+      .append(Workload::new(["(+ a b)", "(+ b a)", "(* a 1)", "(& a a)"]))
+      .append(Workload::new(["(| a b)", "(| b a)", "(+ a 0)", "(-- a b)"]));
+    let egraph = terms.to_egraph::<Bv>();
+    let candidates: Ruleset<Bv> = Ruleset::cvec_match(&egraph);
+    let scheduler = Scheduler::Compress(Limits::minimize());
+
+    let one = candidates.clone().minimize(Ruleset::default(), scheduler);
+    let config = MinimizeConfig {
+      step_size: 4,
+      parallel_validation: true,
+      ..Default::default()
+    };
+    let batched = candidates
+      .clone()
+      .minimize_with(Ruleset::default(), scheduler, &config);
+    assert!(!batched.chosen.is_empty());
+    for rule in batched.chosen.iter() {
+      assert!(matches!(rule.validate(), ValidationResult::Valid));
+    }
+    let (_, missing) =
+      batched
+        .chosen
+        .derive(DeriveType::LhsAndRhs, &one.chosen, Limits::deriving());
+    assert!(
+      missing.is_empty(),
+      "cannot derive {:?}",
+      missing.to_str_vec()
     );
-    cannot.pretty_print();
+  }
+
+  #[test]
+  fn minimize_within_budget() {
+    use bv4::Bv;
+    use std::time::Duration;
+
+    let terms = Workload::new(["a", "b", "0", "(- a)", "(~ a)", "(- (- a))"])
+      .append(Workload::new(["(+ a b)", "(+ b a)", "(* a 1)", "(& a a)"]));
+    let egraph = terms.to_egraph::<Bv>();
+    let candidates: Ruleset<Bv> = Ruleset::cvec_match(&egraph);
+    let full = candidates
+      .clone()
+      .minimize(Ruleset::default(), Scheduler::Compress(Limits::minimize()));
+    assert!(!full.partial);
+
+    // Spent after the first round
+    let mut rest = candidates.clone();
+    let few_nodes = Limits {
+      max_egraph_nodes: Some(1),
+      ..Limits::minimize()
+    };
+    let partial =
+      rest.minimize(Ruleset::default(), Scheduler::Compress(few_nodes));
+    assert!(partial.partial);
+    assert!(!partial.chosen.is_empty());
+    assert!(partial.chosen.len() < full.chosen.len());
+    assert!(!rest.is_empty());
+
+    // Above the largest e-graph of any round, though not their sum
+    let roomy = Limits {
+      max_egraph_nodes: Some(64),
+      ..Limits::minimize()
+    };
+    let whole = candidates
+      .clone()
+      .minimize(Ruleset::default(), Scheduler::Compress(roomy));
+    assert!(!whole.partial);
+    assert_eq!(whole.chosen, full.chosen);
+
+    let no_time = Limits {
+      time: Some(Duration::ZERO),
+      ..Limits::minimize()
+    };
+    let none = candidates
+      .clone()
+      .minimize(Ruleset::default(), Scheduler::Compress(no_time));
+    assert!(none.partial);
+    assert!(none.chosen.is_empty());
+
+    // A budget narrows the limits of a run, keeping their own bounds
+    let limits = Budget::new(no_time).remaining(roomy);
+    assert_eq!(limits.time, Some(Duration::ZERO));
+    assert_eq!(limits.max_egraph_nodes, Some(64));
+    let limits = Budget::default().remaining(no_time);
+    assert_eq!(limits.time, Some(Duration::ZERO));
+    assert_eq!(limits.max_egraph_nodes, None);
   }
 }
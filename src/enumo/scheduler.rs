@@ -1,8 +1,8 @@
-use std::time::Duration;
+use std::time::Instant;
 
-use egg::{Rewrite, Runner};
+use egg::{RecExpr, Rewrite, Runner};
 
-use crate::{EGraph, Id, Limits, SynthAnalysis, SynthLanguage};
+use crate::{EGraph, Id, Limits, SynthAnalysis, SynthLanguage, DEFAULT_TIME_LIMIT};
 
 use super::*;
 
@@ -34,52 +34,84 @@ impl<L: SynthLanguage> egg::RewriteScheduler<L, SynthAnalysis> for MatchSchedule
 }
 
 impl Scheduler {
+    pub fn limits(&self) -> Limits {
+        match self {
+            Scheduler::Simple(limits)
+            | Scheduler::Saturating(limits)
+            | Scheduler::Compress(limits) => *limits,
+        }
+    }
+
+    pub fn with_limits(&self, limits: Limits) -> Self {
+        match self {
+            Scheduler::Simple(_) => Scheduler::Simple(limits),
+            Scheduler::Saturating(_) => Scheduler::Saturating(limits),
+            Scheduler::Compress(_) => Scheduler::Compress(limits),
+        }
+    }
+
     pub fn run_internal<L: SynthLanguage>(
         &self,
         egraph: &EGraph<L, SynthAnalysis>,
         ruleset: &Ruleset<L>,
         rule: Option<&Rule<L>>,
     ) -> EGraph<L, SynthAnalysis> {
+        let goals: Vec<_> = rule
+            .map(|rule| (L::instantiate(&rule.lhs), L::instantiate(&rule.rhs)))
+            .into_iter()
+            .collect();
+        self.run_derive_all(egraph, ruleset, &goals)
+    }
+
+    /// Run `ruleset`, stopping early once the two sides of every goal are
+    /// equivalent. Also returns the e-nodes of the largest e-graph the run
+    /// built.
+    fn run_measured<L: SynthLanguage>(
+        &self,
+        egraph: &EGraph<L, SynthAnalysis>,
+        ruleset: &Ruleset<L>,
+        goals: &[(RecExpr<L>, RecExpr<L>)],
+    ) -> (EGraph<L, SynthAnalysis>, usize) {
+        // Saturating runs several runners. A time budget is shared by all of
+        // them; without one, each runner gets the default time limit.
+        let deadline = self.limits().time.map(|time| Instant::now() + time);
         let get_runner = |egraph: EGraph<L, SynthAnalysis>, limits: Limits| {
+            let node_limit = limits.node.min(limits.max_egraph_nodes.unwrap_or(usize::MAX));
             let base_runner = Runner::default()
                 .with_scheduler(MatchScheduler {
                     match_limit: limits.match_,
                 })
-                .with_node_limit(limits.node)
+                .with_node_limit(node_limit)
                 .with_iter_limit(limits.iter)
-                .with_time_limit(Duration::from_secs(600))
+                .with_time_limit(deadline.map_or(DEFAULT_TIME_LIMIT, |deadline| {
+                    deadline.saturating_duration_since(Instant::now())
+                }))
                 .with_egraph(egraph);
-            if let Some(rule) = rule {
-                let lexpr = L::instantiate(&rule.lhs);
-                let rexpr = L::instantiate(&rule.rhs);
-
+            if goals.is_empty() {
+                base_runner
+            } else {
+                let goals = goals.to_vec();
                 base_runner.with_hook(move |r| {
-                    let lhs = r.egraph.lookup_expr(&lexpr);
-                    let rhs = r.egraph.lookup_expr(&rexpr);
-                    match (lhs, rhs) {
-                        (Some(l), Some(r)) => {
-                            if l == r {
-                                Err("Done".to_owned())
-                            } else {
-                                Ok(())
-                            }
-                        }
-                        _ => Ok(()),
+                    let merged = goals.iter().all(|(lexpr, rexpr)| {
+                        let lhs = r.egraph.lookup_expr(lexpr);
+                        let rhs = r.egraph.lookup_expr(rexpr);
+                        matches!((lhs, rhs), (Some(l), Some(r)) if l == r)
+                    });
+                    if merged {
+                        Err("Done".to_owned())
+                    } else {
+                        Ok(())
                     }
                 })
-            } else {
-                base_runner
             }
         };
         match self {
             Scheduler::Simple(limits) => {
                 let rewrites = ruleset.0.values().map(|rule| &rule.rewrite);
-                let mut runner = get_runner(egraph.clone(), *limits)
-                    .with_iter_limit(limits.iter)
-                    .with_node_limit(limits.node)
-                    .run(rewrites);
+                let mut runner = get_runner(egraph.clone(), *limits).run(rewrites);
                 runner.egraph.rebuild();
-                runner.egraph
+                let peak = runner.egraph.total_size();
+                (runner.egraph, peak)
             }
             Scheduler::Saturating(limits) => {
                 let (sat, other) = ruleset.partition(|rule| rule.is_saturating());
@@ -97,33 +129,29 @@ impl Scheduler {
                 let max_limits = Limits {
                     iter: usize::MAX,
                     node: usize::MAX,
-                    match_: limits.match_,
+                    ..*limits
                 };
 
+                let mut peak = runner.egraph.total_size();
                 for _ in 0..limits.iter {
                     // Sat
                     runner = get_runner(runner.egraph, max_limits).run(&sat);
+                    peak = peak.max(runner.egraph.total_size());
 
                     // Other
-                    runner = get_runner(
-                        runner.egraph,
-                        Limits {
-                            iter: 1,
-                            node: limits.node,
-                            match_: limits.match_,
-                        },
-                    )
-                    .run(&other);
+                    runner = get_runner(runner.egraph, Limits { iter: 1, ..*limits }).run(&other);
+                    peak = peak.max(runner.egraph.total_size());
                 }
                 let mut runner = get_runner(runner.egraph, max_limits).run(&sat);
                 runner.egraph.rebuild();
-                runner.egraph
+                let peak = peak.max(runner.egraph.total_size());
+                (runner.egraph, peak)
             }
             Scheduler::Compress(limits) => {
                 let mut clone = egraph.clone();
                 let ids: Vec<Id> = egraph.classes().map(|c| c.id).collect();
 
-                let out = Self::Simple(*limits).run(egraph, ruleset);
+                let (out, peak) = Self::Simple(*limits).run_measured(egraph, ruleset, &[]);
 
                 // Build a map from id in out to all of the ids in egraph that are equivalent
                 let mut unions = HashMap::default();
@@ -141,7 +169,7 @@ impl Scheduler {
                     }
                 }
                 clone.rebuild();
-                clone
+                (clone, peak)
             }
         }
     }
@@ -154,6 +182,21 @@ impl Scheduler {
         self.run_internal(egraph, ruleset, None)
     }
 
+    /// `run` within what is left of `budget`, recording the size of the
+    /// largest e-graph it built.
+    pub(crate) fn run_budgeted<L: SynthLanguage>(
+        &self,
+        egraph: &EGraph<L, SynthAnalysis>,
+        ruleset: &Ruleset<L>,
+        budget: &mut Budget,
+    ) -> EGraph<L, SynthAnalysis> {
+        let (out, peak) = self
+            .with_limits(budget.remaining(self.limits()))
+            .run_measured(egraph, ruleset, &[]);
+        budget.record(peak);
+        out
+    }
+
     pub fn run_derive<L: SynthLanguage>(
         &self,
         egraph: &EGraph<L, SynthAnalysis>,
@@ -162,4 +205,68 @@ impl Scheduler {
     ) -> EGraph<L, SynthAnalysis> {
         self.run_internal(egraph, ruleset, Some(rule))
     }
+
+    /// Like `run_derive`, but stops only once the two sides of every goal
+    /// are equivalent.
+    pub fn run_derive_all<L: SynthLanguage>(
+        &self,
+        egraph: &EGraph<L, SynthAnalysis>,
+        ruleset: &Ruleset<L>,
+        goals: &[(RecExpr<L>, RecExpr<L>)],
+    ) -> EGraph<L, SynthAnalysis> {
+        self.run_measured(egraph, ruleset, goals).0
+    }
+}
+
+/// The time and e-node budget of some `Limits`, for work that spans several
+/// e-graph runs. The default budget is unbounded.
+#[derive(Default)]
+pub(crate) struct Budget {
+    deadline: Option<Instant>,
+    max_egraph_nodes: Option<usize>,
+    /// The e-nodes of the largest e-graph built so far.
+    peak_nodes: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            deadline: limits.time.map(|time| Instant::now() + time),
+            max_egraph_nodes: limits.max_egraph_nodes,
+            peak_nodes: 0,
+        }
+    }
+
+    pub(crate) fn exhausted(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+            || self
+                .max_egraph_nodes
+                .is_some_and(|total| self.peak_nodes >= total)
+    }
+
+    /// `limits`, with their time and e-node budgets narrowed to what is left
+    /// of this one.
+    pub(crate) fn remaining(&self, limits: Limits) -> Limits {
+        let left = self
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        Limits {
+            time: min_some(left, limits.time),
+            max_egraph_nodes: min_some(self.max_egraph_nodes, limits.max_egraph_nodes),
+            ..limits
+        }
+    }
+
+    fn record(&mut self, nodes: usize) {
+        self.peak_nodes = self.peak_nodes.max(nodes);
+    }
+}
+
+/// The smaller of two optional bounds, where None is unbounded.
+fn min_some<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
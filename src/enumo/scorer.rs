@@ -0,0 +1,154 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use egg::CostFunction;
+use num::ToPrimitive;
+use ordered_float::OrderedFloat;
+
+use crate::SynthLanguage;
+
+use super::Rule;
+
+/// A rule's priority in `minimize`, compared lexicographically. Higher is
+/// better.
+pub type Score = Vec<OrderedFloat<f64>>;
+
+/// Orders rule candidates for `Ruleset::minimize_with`, which picks the
+/// candidates with the highest score first.
+pub trait RuleScorer<L: SynthLanguage>: Send + Sync {
+    fn score(&self, rule: &Rule<L>) -> Score;
+}
+
+/// The domain's own ordering, `SynthLanguage::score`. This is the scorer
+/// `minimize` uses by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DomainScorer;
+
+impl<L: SynthLanguage> RuleScorer<L> for DomainScorer {
+    fn score(&self, rule: &Rule<L>) -> Score {
+        L::score(&rule.lhs, &rule.rhs)
+            .iter()
+            .map(|x| OrderedFloat(*x as f64))
+            .collect()
+    }
+}
+
+/// Prefers rules that reduce the cost of a term the most, according to an
+/// egg `CostFunction`, i.e., rules that simplify.
+///
+/// Cost functions need `&mut self`, so the scorer holds a function that
+/// makes one, e.g. `CostScorer::new(|| AstSize)`.
+pub struct CostScorer<F, CF> {
+    make_cost_fn: F,
+    phantom: PhantomData<fn() -> CF>,
+}
+
+impl<F, CF> CostScorer<F, CF>
+where
+    F: Fn() -> CF,
+{
+    pub fn new(make_cost_fn: F) -> Self {
+        Self {
+            make_cost_fn,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Cost of the lhs minus cost of the rhs of `rule`.
+    pub fn reduction<L>(&self, rule: &Rule<L>) -> f64
+    where
+        L: SynthLanguage,
+        CF: CostFunction<L>,
+        CF::Cost: ToPrimitive,
+    {
+        let mut cost_fn = (self.make_cost_fn)();
+        let mut cost = |pat| {
+            let cost = cost_fn.cost_rec(&L::instantiate(pat));
+            cost.to_f64().expect("cost is not a number")
+        };
+        cost(&rule.lhs) - cost(&rule.rhs)
+    }
+
+    /// The direction of `rule` that does not increase cost. Rules that can
+    /// not be reversed, because the rhs has variables the lhs does not, are
+    /// returned as is.
+    pub fn orient<L>(&self, rule: &Rule<L>) -> Rule<L>
+    where
+        L: SynthLanguage,
+        CF: CostFunction<L>,
+        CF::Cost: ToPrimitive,
+    {
+        match rule.reverse() {
+            Some(reverse) if self.reduction(rule) < 0.0 => reverse,
+            _ => rule.clone(),
+        }
+    }
+}
+
+impl<L, F, CF> RuleScorer<L> for CostScorer<F, CF>
+where
+    L: SynthLanguage,
+    F: Fn() -> CF + Send + Sync,
+    CF: CostFunction<L>,
+    CF::Cost: ToPrimitive,
+{
+    fn score(&self, rule: &Rule<L>) -> Score {
+        vec![OrderedFloat(self.reduction(rule))]
+    }
+}
+
+/// Compares rules by the first scorer, breaking ties with the next one.
+pub struct Lexicographic<L: SynthLanguage>(pub Vec<Arc<dyn RuleScorer<L>>>);
+
+impl<L: SynthLanguage> Lexicographic<L> {
+    pub fn new(scorers: Vec<Arc<dyn RuleScorer<L>>>) -> Self {
+        Self(scorers)
+    }
+}
+
+impl<L: SynthLanguage> RuleScorer<L> for Lexicographic<L> {
+    fn score(&self, rule: &Rule<L>) -> Score {
+        // Lexicographic order on the concatenation is lexicographic order on
+        // the parts, as long as each scorer returns scores of one length.
+        self.0
+            .iter()
+            .flat_map(|scorer| scorer.score(rule))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::{AstSize, SymbolLang};
+
+    fn rule(s: &str) -> Rule<SymbolLang> {
+        Rule::from_string(s).unwrap().0
+    }
+
+    #[test]
+    fn cost_scorer_prefers_simplifying_rules() {
+        let scorer = CostScorer::new(|| AstSize);
+        let simplify = rule("(+ ?a 0) ==> ?a");
+        let comm = rule("(+ ?a ?b) ==> (+ ?b ?a)");
+        let expand = rule("?a ==> (+ ?a 0)");
+        assert!(scorer.score(&simplify) > scorer.score(&comm));
+        assert!(scorer.score(&comm) > scorer.score(&expand));
+        assert_eq!(scorer.orient(&simplify).name, simplify.name);
+        assert_eq!(scorer.orient(&expand).name, simplify.name);
+    }
+
+    #[test]
+    fn lexicographic_breaks_ties() {
+        let one_var = rule("(* ?a 1) ==> ?a");
+        let two_vars = rule("(- ?a ?b) ==> ?a");
+        let cost: Arc<dyn RuleScorer<SymbolLang>> = Arc::new(CostScorer::new(|| AstSize));
+        assert_eq!(cost.score(&one_var), cost.score(&two_vars));
+
+        let scorer = Lexicographic::new(vec![cost, Arc::new(DomainScorer)]);
+        assert_eq!(
+            scorer.score(&one_var)[1..],
+            DomainScorer.score(&one_var)[..]
+        );
+        assert!(scorer.score(&two_vars) > scorer.score(&one_var));
+    }
+}
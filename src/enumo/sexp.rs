@@ -36,7 +36,7 @@ impl std::fmt::Display for Sexp {
 }
 
 impl Sexp {
-    fn from_symbolic_expr(sexp: symbolic_expressions::Sexp) -> Self {
+    pub(crate) fn from_symbolic_expr(sexp: symbolic_expressions::Sexp) -> Self {
         match sexp {
             symbolic_expressions::Sexp::String(s) => Self::Atom(s),
             symbolic_expressions::Sexp::List(ss) => Self::List(
@@ -2,7 +2,10 @@ use egg::{EGraph, ENodeOrVar, RecExpr};
 
 use super::*;
 use crate::{SynthAnalysis, SynthLanguage};
-use std::io::Write;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::Write,
+};
 
 /// Workloads are sets of terms from a domain
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -117,10 +120,9 @@ impl Workload {
         }
     }
 
+    #[deprecated(note = "print the workload with its `Display` impl instead")]
     pub fn pretty_print(&self) {
-        for t in self.force() {
-            println!("{}", t);
-        }
+        print!("{}", self);
     }
 
     pub fn plug(self, name: impl Into<String>, workload: &Workload) -> Self {
@@ -176,6 +178,16 @@ impl Workload {
     }
 }
 
+/// One term per line.
+impl Display for Workload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for t in self.force() {
+            writeln!(f, "{}", t)?;
+        }
+        Ok(())
+    }
+}
+
 impl From<&[&str]> for Workload {
     fn from(value: &[&str]) -> Self {
         Workload::new(value.iter().copied())
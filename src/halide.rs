@@ -0,0 +1,441 @@
+//! Halide's integer predicates: arithmetic, comparisons and boolean
+//! operators over `i64`, with `0` as false and any other value as true.
+//!
+//! Public so that the `enumo` command-line tool and other programs can run
+//! the domain; it is part of the library's API.
+
+use num::{ToPrimitive, Zero};
+use z3::ast::Ast;
+
+use crate::{
+    enumo::{OpMap, OpMaps},
+    map, self_product, with_z3_context, z3_solver, CVec, Counterexample, EGraph, Id, Pattern,
+    Symbol, SynthAnalysis, SynthLanguage, ValidationResult,
+};
+
+pub type Constant = i64;
+
+egg::define_language! {
+  pub enum Pred {
+    Lit(Constant),
+    "<" = Lt([Id;2]),
+    "<=" = Leq([Id;2]),
+    "==" = Eq([Id;2]),
+    "!=" = Neq([Id;2]),
+    "->" = Implies([Id; 2]),
+    "!" = Not(Id),
+    "-" = Neg(Id),
+    "&&" = And([Id;2]),
+    "||" = Or([Id;2]),
+    "^" = Xor([Id;2]),
+    "+" = Add([Id; 2]),
+    "-" = Sub([Id; 2]),
+    "*" = Mul([Id; 2]),
+    "/" = Div([Id; 2]),
+    "min" = Min([Id; 2]),
+    "max" = Max([Id; 2]),
+    "select" = Select([Id; 3]),
+    Var(Symbol),
+  }
+}
+
+impl SynthLanguage for Pred {
+    type Constant = Constant;
+    const NAME: &'static str = "halide";
+
+    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
+    where
+        F: FnMut(&'a Id) -> &'a CVec<Self>,
+    {
+        let one = 1.to_i64().unwrap();
+        let zero = 0.to_i64().unwrap();
+        match self {
+            Pred::Lit(c) => vec![Some(*c); cvec_len],
+            Pred::Lt([x, y]) => {
+                map!(get_cvec, x, y => if x < y {Some(one)} else {Some(zero)})
+            }
+            Pred::Leq([x, y]) => {
+                map!(get_cvec, x, y => if x <= y {Some(one)} else {Some(zero)})
+            }
+            Pred::Eq([x, y]) => {
+                map!(get_cvec, x, y => if x == y {Some(one)} else {Some(zero)})
+            }
+            Pred::Neq([x, y]) => {
+                map!(get_cvec, x, y => if x != y {Some(one)} else {Some(zero)})
+            }
+            Pred::Implies([x, y]) => {
+                map!(get_cvec, x, y => {
+                  let xbool = *x != zero;
+                  let ybool = *y != zero;
+                  if !xbool || ybool {Some(one)} else {Some(zero)}
+                })
+            }
+            Pred::Not(x) => {
+                map!(get_cvec, x => if *x == zero { Some(one)} else {Some(zero)})
+            }
+            Pred::Neg(x) => map!(get_cvec, x => Some(-x)),
+            Pred::And([x, y]) => {
+                map!(get_cvec, x, y => {
+                    let xbool = *x != zero;
+                    let ybool = *y != zero;
+                    if xbool && ybool { Some(one) } else { Some(zero) }
+                })
+            }
+            Pred::Or([x, y]) => {
+                map!(get_cvec, x, y => {
+                    let xbool = *x != zero;
+                    let ybool = *y != zero;
+                    if xbool || ybool { Some(one) } else { Some(zero) }
+                })
+            }
+            Pred::Xor([x, y]) => {
+                map!(get_cvec, x, y => {
+                    let xbool = *x != zero;
+                    let ybool = *y != zero;
+                    if xbool ^ ybool { Some(one) } else { Some(zero) }
+                })
+            }
+            Pred::Add([x, y]) => map!(get_cvec, x, y => x.checked_add(*y)),
+            Pred::Sub([x, y]) => map!(get_cvec, x, y => x.checked_sub(*y)),
+            Pred::Mul([x, y]) => map!(get_cvec, x, y => x.checked_mul(*y)),
+            Pred::Div([x, y]) => map!(get_cvec, x, y => {
+              if y.is_zero() {
+                Some(zero)
+              } else {
+                x.checked_div(*y)
+              }
+            }),
+            Pred::Min([x, y]) => map!(get_cvec, x, y => Some(*x.min(y))),
+            Pred::Max([x, y]) => map!(get_cvec, x, y => Some(*x.max(y))),
+            Pred::Select([x, y, z]) => map!(get_cvec, x, y, z => {
+              let xbool = *x != zero;
+              if xbool {Some(*y)} else {Some(*z)}
+            }),
+            Pred::Var(_) => vec![],
+        }
+    }
+
+    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
+        let consts = vec![
+            Some((-10).to_i64().unwrap()),
+            Some((-1).to_i64().unwrap()),
+            Some(0.to_i64().unwrap()),
+            Some(1.to_i64().unwrap()),
+            Some(2.to_i64().unwrap()),
+            Some(5.to_i64().unwrap()),
+            Some(100.to_i64().unwrap()),
+        ];
+        let cvecs = self_product(&consts, vars.len());
+
+        egraph.analysis.cvec_len = cvecs[0].len();
+
+        for (i, v) in vars.iter().enumerate() {
+            let id = egraph.add(Pred::Var(Symbol::from(v.clone())));
+            let cvec = cvecs[i].clone();
+            egraph[id].data.cvec = cvec;
+        }
+    }
+
+    fn to_var(&self) -> Option<Symbol> {
+        if let Pred::Var(sym) = self {
+            Some(*sym)
+        } else {
+            None
+        }
+    }
+
+    fn mk_var(sym: Symbol) -> Self {
+        Pred::Var(sym)
+    }
+
+    fn is_constant(&self) -> bool {
+        matches!(self, Pred::Lit(_))
+    }
+
+    fn mk_constant(c: Self::Constant) -> Self {
+        Pred::Lit(c)
+    }
+
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        Self::validate_under(lhs, rhs, None)
+    }
+
+    fn is_true(c: &Self::Constant) -> bool {
+        !c.is_zero()
+    }
+
+    fn validate_with_cond(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        cond: &Pattern<Self>,
+    ) -> ValidationResult<Self> {
+        Self::validate_under(lhs, rhs, Some(cond))
+    }
+}
+
+impl Pred {
+    /// Checks that lhs and rhs are equal, assuming the guard (if any) is
+    /// nonzero.
+    fn validate_under(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        guard: Option<&Pattern<Self>>,
+    ) -> ValidationResult<Self> {
+        with_z3_context(|ctx| {
+            let solver = z3_solver(ctx);
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            if let Some(guard) = guard {
+                let gexpr = egg_to_z3(ctx, Self::instantiate(guard).as_ref());
+                solver.assert(&gexpr._eq(&z3::ast::Int::from_i64(ctx, 0)).not());
+            }
+            solver.assert(&lexpr._eq(&rexpr).not());
+            match solver.check() {
+                z3::SatResult::Unsat => ValidationResult::Valid,
+                z3::SatResult::Unknown => ValidationResult::Unknown,
+                z3::SatResult::Sat => {
+                    let cex = solver.get_model().and_then(|model| {
+                        let mut assignment: Vec<(Symbol, Constant)> = vec![];
+                        for var in lhs.vars().into_iter().chain(rhs.vars()) {
+                            let name = &var.to_string()[1..];
+                            let sym = Symbol::from(name);
+                            if assignment.iter().any(|(v, _)| *v == sym) {
+                                continue;
+                            }
+                            let val = model.eval(&z3::ast::Int::new_const(ctx, name))?.as_i64()?;
+                            assignment.push((sym, val));
+                        }
+                        Some(Counterexample::new(lhs, rhs, assignment))
+                    });
+                    ValidationResult::Invalid(cex)
+                }
+            }
+        })
+    }
+}
+
+fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Pred]) -> z3::ast::Int<'a> {
+    let mut buf: Vec<z3::ast::Int> = vec![];
+    let zero = z3::ast::Int::from_i64(ctx, 0);
+    let one = z3::ast::Int::from_i64(ctx, 1);
+    for node in expr.as_ref().iter() {
+        match node {
+            Pred::Lit(c) => buf.push(z3::ast::Int::from_i64(ctx, c.to_i64().unwrap())),
+            Pred::Lt([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::lt(l, r), &one, &zero))
+            }
+            Pred::Leq([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), &one, &zero))
+            }
+            Pred::Eq([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::_eq(l, r), &one, &zero))
+            }
+            Pred::Neq([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::_eq(l, r), &zero, &one))
+            }
+            Pred::Implies([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
+                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &z3::ast::Bool::implies(&l_not_z, &r_not_z),
+                    &one,
+                    &zero,
+                ))
+            }
+            Pred::Not(x) => {
+                let l = &buf[usize::from(*x)];
+                buf.push(z3::ast::Bool::ite(&l._eq(&zero), &one, &zero))
+            }
+            Pred::Neg(x) => buf.push(z3::ast::Int::unary_minus(&buf[usize::from(*x)])),
+            Pred::And([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
+                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &z3::ast::Bool::and(ctx, &[&l_not_z, &r_not_z]),
+                    &one,
+                    &zero,
+                ))
+            }
+            Pred::Or([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
+                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &z3::ast::Bool::or(ctx, &[&l_not_z, &r_not_z]),
+                    &one,
+                    &zero,
+                ))
+            }
+            Pred::Xor([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
+                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &z3::ast::Bool::xor(&l_not_z, &r_not_z),
+                    &one,
+                    &zero,
+                ))
+            }
+            Pred::Add([x, y]) => buf.push(z3::ast::Int::add(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Pred::Sub([x, y]) => buf.push(z3::ast::Int::sub(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Pred::Mul([x, y]) => buf.push(z3::ast::Int::mul(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Pred::Div([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(
+                    &r._eq(&zero),
+                    &zero,
+                    &z3::ast::Int::div(l, r),
+                ))
+            }
+            Pred::Min([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), l, r))
+            }
+            Pred::Max([x, y]) => {
+                let l = &buf[usize::from(*x)];
+                let r = &buf[usize::from(*y)];
+                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), r, l))
+            }
+            Pred::Select([x, y, z]) => {
+                let cond = z3::ast::Bool::not(&buf[usize::from(*x)]._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &cond,
+                    &buf[usize::from(*y)],
+                    &buf[usize::from(*z)],
+                ))
+            }
+            Pred::Var(v) => buf.push(z3::ast::Int::new_const(ctx, v.to_string())),
+        }
+    }
+    buf.pop().unwrap()
+}
+
+impl OpMaps for Pred {
+    fn smtlib() -> OpMap {
+        let mut map = OpMap::new(
+            "Int",
+            &[
+                ("<", "(ite (< {0} {1}) 1 0)"),
+                ("<=", "(ite (<= {0} {1}) 1 0)"),
+                ("==", "(ite (= {0} {1}) 1 0)"),
+                ("!=", "(ite (= {0} {1}) 0 1)"),
+                ("->", "(ite (or (= {0} 0) (not (= {1} 0))) 1 0)"),
+                ("!", "(ite (= {0} 0) 1 0)"),
+                ("-/1", "(- {0})"),
+                ("&&", "(ite (and (not (= {0} 0)) (not (= {1} 0))) 1 0)"),
+                ("||", "(ite (or (not (= {0} 0)) (not (= {1} 0))) 1 0)"),
+                ("^", "(ite (xor (not (= {0} 0)) (not (= {1} 0))) 1 0)"),
+                ("/", "(ite (= {1} 0) 0 (div {0} {1}))"),
+                ("min", "(ite (<= {0} {1}) {0} {1})"),
+                ("max", "(ite (<= {0} {1}) {1} {0})"),
+                ("select", "(ite (not (= {0} 0)) {1} {2})"),
+            ],
+        );
+        map.literal = |c| match c.strip_prefix('-') {
+            Some(n) => format!("(- {})", n),
+            None => c.to_string(),
+        };
+        map.falsity = Some("0".into());
+        map
+    }
+
+    fn egglog() -> OpMap {
+        let mut map = OpMap::new(
+            "Pred",
+            &[
+                ("<", "Lt"),
+                ("<=", "Le"),
+                ("==", "Eq"),
+                ("!=", "Ne"),
+                ("->", "Implies"),
+                ("!", "Not"),
+                ("-/1", "Neg"),
+                ("&&", "And"),
+                ("||", "Or"),
+                ("^", "Xor"),
+                ("+", "Add"),
+                ("-", "Sub"),
+                ("*", "Mul"),
+                ("/", "Div"),
+                ("min", "Min"),
+                ("max", "Max"),
+                ("select", "Select"),
+            ],
+        );
+        map.literal = |c| format!("(Lit {})", c);
+        map.falsity = Some("(Lit 0)".into());
+        map
+    }
+
+    fn lean() -> OpMap {
+        let mut map = OpMap::new(
+            "Int",
+            &[
+                ("<", "(if {0} < {1} then 1 else 0)"),
+                ("<=", "(if {0} ≤ {1} then 1 else 0)"),
+                ("==", "(if {0} = {1} then 1 else 0)"),
+                ("!=", "(if {0} = {1} then 0 else 1)"),
+                ("->", "(if {0} = 0 ∨ {1} ≠ 0 then 1 else 0)"),
+                ("!", "(if {0} = 0 then 1 else 0)"),
+                ("-/1", "(-{0})"),
+                ("&&", "(if {0} ≠ 0 ∧ {1} ≠ 0 then 1 else 0)"),
+                ("||", "(if {0} ≠ 0 ∨ {1} ≠ 0 then 1 else 0)"),
+                ("^", "(if ({0} ≠ 0) ≠ ({1} ≠ 0) then 1 else 0)"),
+                ("+", "({0} + {1})"),
+                ("-", "({0} - {1})"),
+                ("*", "({0} * {1})"),
+                ("/", "({0} / {1})"),
+                ("min", "(min {0} {1})"),
+                ("max", "(max {0} {1})"),
+                ("select", "(if {0} ≠ 0 then {1} else {2})"),
+            ],
+        );
+        map.literal = |c| format!("({} : Int)", c);
+        map.falsity = Some("0".into());
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enumo::Ruleset;
+
+    #[test]
+    fn op_maps_keep_guards_nonzero() {
+        let rules: Ruleset<Pred> = Ruleset::new(["(< (min ?a ?b) ?c) ==> 1 if (< ?b ?c)"]);
+        let scripts = rules.to_smtlib(&Pred::smtlib());
+        assert!(scripts[0].contains(
+            "(=> (not (= (ite (< b c) 1 0) 0)) (= (ite (< (ite (<= a b) a b) c) 1 0) 1))"
+        ));
+        assert_eq!(
+            rules.to_egglog(&Pred::egglog()),
+            "(rewrite (Lt (Min a b) c) (Lit 1) :when ((!= (Lt b c) (Lit 0))))\n"
+        );
+    }
+}
@@ -50,31 +50,18 @@ impl<L: SynthLanguage> Signature<L> {
     }
   }
   pub fn get_fuzz_constant(&self) -> Option<L::Constant> {
-    let fuzz_constant = if self.cvec.is_empty() {
-      None
-    } else {
-      if let Some(first_constant) = self
-        .cvec
-        .iter()
-        .find(|v| v.is_some())
-        .map(|x| x.clone().unwrap())
-      {
-        if self.cvec.iter().all(|v| {
-          if let Some(constant) = v {
-            constant == &first_constant
-          } else {
-            true
-          }
-        }) {
-          Some(first_constant)
-        } else {
-          None
-        }
+    let first_constant = self.cvec.iter().find_map(|v| v.clone())?;
+    if self.cvec.iter().all(|v| {
+      if let Some(constant) = v {
+        constant == &first_constant
       } else {
-        None
+        true
       }
-    };
-    fuzz_constant
+    }) {
+      Some(first_constant)
+    } else {
+      None
+    }
   }
 }
 
@@ -94,7 +81,7 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
       let mut nodes: Vec<L> = vec![];
       let mut map: HashMap<Id, Id> = HashMap::default();
       enode.for_each(|id| {
-        if map.get(&id).is_none() {
+        map.entry(id).or_insert_with(|| {
           let s = get_simplest(&id);
           let i = nodes.len();
           for n in s.as_ref() {
@@ -102,8 +89,8 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
               .push(n.clone().map_children(|id| Id::from(usize::from(id) + i)));
           }
 
-          map.insert(id, Id::from(nodes.len() - 1));
-        }
+          Id::from(nodes.len() - 1)
+        });
       });
 
       nodes.push(enode.clone().map_children(|id| *map.get(&id).unwrap()));
@@ -136,8 +123,8 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
           }
           (Some(x), Some(y)) => {
             if x != y {
-              println!("to is {}", to.simplest.to_string());
-              println!("from is {}", from.simplest.to_string());
+              log::error!("to is {}", to.simplest);
+              log::error!("from is {}", from.simplest);
             }
             assert_eq!(
               x, y,
@@ -200,7 +187,7 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
       if low == high {
         let enode = L::mk_constant(low.clone());
         let added = egraph.add(enode);
-        egraph.union(id, added);
+        egraph.union_trusted(id, added, "constant-fold");
       }
     }
   }
@@ -217,7 +204,11 @@ pub trait SynthLanguage:
   Language + Send + Sync + Display + FromOp + 'static
 {
   /// Domain value type
-  type Constant: Clone + Hash + Eq + Debug + Display + Ord;
+  type Constant: Clone + Hash + Eq + Debug + Display + Ord + Send + Sync;
+
+  /// A name for the domain, unique among the domains of a program. Keys
+  /// checkpoints, so it must not change between builds.
+  const NAME: &'static str;
 
   /// Hook into the e-graph analysis modify method
   /// Useful for domain-specific purposes (for example, constant folding)
@@ -392,5 +383,46 @@ pub trait SynthLanguage:
     ]
   }
 
-  fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult;
+  /// Soundness check for a rule.
+  ///
+  /// By default, rules are checked by random testing if the domain provides
+  /// a `fuzz_config`, and are `Unknown` otherwise.
+  fn validate(
+    lhs: &Pattern<Self>,
+    rhs: &Pattern<Self>,
+  ) -> ValidationResult<Self> {
+    match Self::fuzz_config() {
+      Some(config) => fuzz_validate(lhs, rhs, &config),
+      None => ValidationResult::Unknown,
+    }
+  }
+
+  /// Configuration for checking rules by random testing, see `fuzz_validate`.
+  ///
+  /// Domains without an SMT encoding can implement this instead of
+  /// `validate`.
+  fn fuzz_config() -> Option<FuzzConfig<Self>> {
+    None
+  }
+
+  /// Whether a domain value counts as true when it is the result of a rule's
+  /// guard.
+  ///
+  /// Conditional rules only fire where their guard is known to be true, so
+  /// domains that want to use conditional rules must implement this.
+  fn is_true(_c: &Self::Constant) -> bool {
+    false
+  }
+
+  /// Soundness check for a conditional rule: lhs and rhs must be equivalent
+  /// whenever `cond` is true.
+  ///
+  /// Domains without support for conditions report `Unknown`.
+  fn validate_with_cond(
+    _lhs: &Pattern<Self>,
+    _rhs: &Pattern<Self>,
+    _cond: &Pattern<Self>,
+  ) -> ValidationResult<Self> {
+    ValidationResult::Unknown
+  }
 }
@@ -1,6 +1,7 @@
 use std::{
     fmt::{self, Display},
     hash::BuildHasherDefault,
+    time::Duration,
 };
 
 pub use bv::*;
@@ -8,13 +9,21 @@ use enumo::{Ruleset, Scheduler};
 pub use language::*;
 use serde::Serialize;
 pub use util::*;
+pub use validation::*;
 
 mod bv;
+pub mod checkpoint;
 pub mod enumo;
+pub mod halide;
 mod language;
 pub mod logger;
+pub mod rational;
+pub mod recipe;
 pub mod recipe_utils;
+pub mod report;
 mod util;
+mod validation;
+pub mod validation_cache;
 
 /// Egg Id
 pub type Id = egg::Id;
@@ -40,15 +49,78 @@ pub type IndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<rustc_hash
 
 /// Whether a rule is sound.
 #[derive(Debug, Clone)]
-pub enum ValidationResult {
+pub enum ValidationResult<L: SynthLanguage> {
     /// The rule is sound.
     Valid,
-    /// The rule is unsound.
-    Invalid,
+    /// The rule is unsound. Validators that can produce a model should
+    /// return it, so that it can be used to refine the cvecs.
+    Invalid(Option<Counterexample<L>>),
     /// The soundness of the rule is unknown, for example, the SMT query timed out.
     Unknown,
 }
 
+/// A variable assignment on which the two sides of a rule disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample<L: SynthLanguage> {
+    pub assignment: Vec<(Symbol, L::Constant)>,
+    /// Value of the left-hand side under the assignment, `None` if undefined.
+    pub lhs: Option<L::Constant>,
+    /// Value of the right-hand side under the assignment, `None` if undefined.
+    pub rhs: Option<L::Constant>,
+}
+
+impl<L: SynthLanguage> Counterexample<L> {
+    /// Build a counterexample to `lhs ==> rhs`, computing the value of both
+    /// sides under `assignment` with the domain's interpreter.
+    pub fn new(lhs: &Pattern<L>, rhs: &Pattern<L>, assignment: Vec<(Symbol, L::Constant)>) -> Self {
+        let mut cex = Self {
+            assignment,
+            lhs: None,
+            rhs: None,
+        };
+        cex.lhs = cex.eval(&L::instantiate(lhs));
+        cex.rhs = cex.eval(&L::instantiate(rhs));
+        cex
+    }
+
+    /// The value assigned to a variable, if any.
+    pub fn get(&self, var: Symbol) -> Option<&L::Constant> {
+        self.assignment
+            .iter()
+            .find(|(v, _)| *v == var)
+            .map(|(_, c)| c)
+    }
+
+    /// Evaluate an expression under the assignment.
+    pub fn eval(&self, expr: &egg::RecExpr<L>) -> Option<L::Constant> {
+        let mut values: Vec<CVec<L>> = vec![];
+        for node in expr.as_ref() {
+            let cvec = match node.to_var() {
+                Some(var) => vec![self.get(var).cloned()],
+                None => node.eval(1, |id| &values[usize::from(*id)]),
+            };
+            values.push(cvec);
+        }
+        values.last()?.first().cloned().flatten()
+    }
+}
+
+impl<L: SynthLanguage> Display for Counterexample<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |c: &Option<L::Constant>| match c {
+            Some(c) => c.to_string(),
+            None => "undefined".to_string(),
+        };
+        for (i, (var, val)) in self.assignment.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} = {}", var, val)?;
+        }
+        write!(f, ": lhs = {}, rhs = {}", show(&self.lhs), show(&self.rhs))
+    }
+}
+
 // Cost function for ast size in the domain
 // Penalizes ops not in the domain
 pub struct ExtractableAstSize;
@@ -61,7 +133,7 @@ impl<L: SynthLanguage> egg::CostFunction<L> for ExtractableAstSize {
         if enode.is_allowed_op() {
             enode.fold(1, |sum, id| sum.saturating_add(costs(id)))
         } else {
-            usize::max_value()
+            usize::MAX
         }
     }
 }
@@ -72,8 +144,21 @@ pub struct Limits {
     pub iter: usize,
     pub node: usize,
     pub match_: usize,
+    /// Wall-clock budget for everything run under these limits: a single
+    /// e-graph run, all of `Ruleset::minimize`, or the refinement and
+    /// minimization of a `run_workload` step. Without a budget, each e-graph
+    /// run stops after `DEFAULT_TIME_LIMIT`.
+    pub time: Option<Duration>,
+    /// Cap on the e-nodes of the largest e-graph built under these limits,
+    /// not a total over them. Unlike `node`, it also ends work that spans
+    /// several e-graph runs, e.g. the rounds of `Ruleset::minimize`, once one
+    /// of them reaches it.
+    pub max_egraph_nodes: Option<usize>,
 }
 
+/// Time limit of an e-graph run whose `Limits` have no wall-clock budget.
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(600);
+
 /// What metric for derivability to measure. Derivability tests whether a
 /// ruleset, R, can recover the proving power of a specified rule, lhs->rhs
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -93,6 +178,8 @@ impl Limits {
             iter: 2,
             node: 300_000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         }
     }
 
@@ -102,6 +189,8 @@ impl Limits {
             iter: 2,
             node: 300_000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         }
     }
 
@@ -111,6 +200,8 @@ impl Limits {
             iter: 5,
             node: 100_000,
             match_: 1000,
+            time: None,
+            max_egraph_nodes: None,
         }
     }
 }
@@ -170,6 +261,7 @@ impl<L: SynthLanguage> fmt::Display for Phase<L> {
 /// can write domain-agnostic tests
 impl SynthLanguage for egg::SymbolLang {
     type Constant = usize;
+    const NAME: &'static str = "symbol";
 
     fn eval<'a, F>(&'a self, _cvec_len: usize, _get_cvec: F) -> CVec<Self>
     where
@@ -204,7 +296,7 @@ impl SynthLanguage for egg::SymbolLang {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Invalid
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        ValidationResult::Invalid(None)
     }
 }
@@ -15,12 +15,14 @@ use crate::{count_lines, enumo::Ruleset, DeriveType, Limits, Phase, SynthLanguag
  */
 fn add_json_to_file(json: Value) {
     let path = "__temp/enumo/data/output.json";
-    std::fs::create_dir_all("__temp/enumo/data").unwrap_or_else(|e| panic!("Error creating dir: {}", e));
+    std::fs::create_dir_all("__temp/enumo/data")
+        .unwrap_or_else(|e| panic!("Error creating dir: {}", e));
 
     OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(path)
         .expect("Unable to open or create file");
 
@@ -54,13 +56,38 @@ pub fn write_baseline<L: SynthLanguage>(
     baseline: &Ruleset<L>,
     baseline_name: &str,
     time: Duration,
+) {
+    write_baseline_row(ruleset, spec_name, baseline, baseline_name, time, false)
+}
+
+/**
+ * Like `write_baseline`, but each derivability result also includes a proof
+ * (see `Ruleset::prove`) for every derivable rule
+ */
+pub fn write_baseline_with_proofs<L: SynthLanguage>(
+    ruleset: &Ruleset<L>,
+    spec_name: &str,
+    baseline: &Ruleset<L>,
+    baseline_name: &str,
+    time: Duration,
+) {
+    write_baseline_row(ruleset, spec_name, baseline, baseline_name, time, true)
+}
+
+fn write_baseline_row<L: SynthLanguage>(
+    ruleset: &Ruleset<L>,
+    spec_name: &str,
+    baseline: &Ruleset<L>,
+    baseline_name: &str,
+    time: Duration,
+    proofs: bool,
 ) {
     // Items in this list will *not* run derivability
     // Format is (a, b) where a and b are spec/baseline names
     // and a.derive(b) will *not* run.
     // Note: b.derive(a) will still be computed unless (b, a)
     // is also in this list.
-    let skip_derive = vec![
+    let skip_derive = [
         ("herbie", "rational_replicate"),
         ("herbie", "rational_best"),
     ];
@@ -73,8 +100,8 @@ pub fn write_baseline<L: SynthLanguage>(
         json!({})
     } else {
         json!({
-            "lhs": get_derivability(ruleset, baseline, DeriveType::Lhs),
-            "lhs_rhs": get_derivability(ruleset, baseline, DeriveType::LhsAndRhs)
+            "lhs": get_derivability(ruleset, baseline, DeriveType::Lhs, proofs),
+            "lhs_rhs": get_derivability(ruleset, baseline, DeriveType::LhsAndRhs, proofs)
         })
     };
 
@@ -82,8 +109,8 @@ pub fn write_baseline<L: SynthLanguage>(
         json!({})
     } else {
         json!({
-            "lhs": get_derivability(baseline, ruleset, DeriveType::Lhs),
-            "lhs_rhs": get_derivability(baseline, ruleset, DeriveType::LhsAndRhs)
+            "lhs": get_derivability(baseline, ruleset, DeriveType::Lhs, proofs),
+            "lhs_rhs": get_derivability(baseline, ruleset, DeriveType::LhsAndRhs, proofs)
         })
     };
 
@@ -124,7 +151,8 @@ pub fn write_bv_derivability<L: SynthLanguage>(
 
     // Compute derivability
     let start = Instant::now();
-    let (can, cannot) = sound_bv4.derive(DeriveType::LhsAndRhs, &gen_rules, Limits::deriving());
+    let (can, cannot) =
+        sound_bv4.derive_batched(DeriveType::LhsAndRhs, &gen_rules, Limits::deriving());
     let derive_time = start.elapsed();
     let lhsrhs = json!({
         "can": can.to_str_vec(),
@@ -133,7 +161,7 @@ pub fn write_bv_derivability<L: SynthLanguage>(
     });
 
     let start = Instant::now();
-    let (can, cannot) = sound_bv4.derive(DeriveType::Lhs, &gen_rules, Limits::deriving());
+    let (can, cannot) = sound_bv4.derive_batched(DeriveType::Lhs, &gen_rules, Limits::deriving());
     let derive_time = start.elapsed();
     let lhs = json!({
         "can": can.to_str_vec(),
@@ -185,15 +213,31 @@ pub fn write_ff_phase<L: SynthLanguage>(
 /**
  * Uses `ruleset` to derive `against` rules
  * with the specified derive type
- * Returns a JSON object containing the derivability results and time
+ * Returns a JSON object containing the derivability results and time,
+ * and a proof for each derivable rule if `proofs` is set
  */
 fn get_derivability<L: SynthLanguage>(
     ruleset: &Ruleset<L>,
     against: &Ruleset<L>,
     derive_type: DeriveType,
+    proofs: bool,
 ) -> Value {
     let start = Instant::now();
-    let (can, cannot) = ruleset.derive(derive_type, against, Limits::deriving());
+    if proofs {
+        let (proofs, cannot) = ruleset.derive_with_proofs(derive_type, against, Limits::deriving());
+        let elapsed = start.elapsed();
+        let can: Vec<String> = proofs.iter().map(|p| p.rule.name.to_string()).collect();
+        let proofs: Vec<Value> = proofs.iter().map(|p| p.to_json()).collect();
+        return json!({
+            "derive_type": derive_type,
+            "can": can,
+            "cannot": cannot.to_str_vec(),
+            "proofs": proofs,
+            "time": elapsed.as_secs_f64()
+        });
+    }
+
+    let (can, cannot) = ruleset.derive_batched(derive_type, against, Limits::deriving());
     let elapsed = start.elapsed();
 
     json!({
@@ -0,0 +1,502 @@
+//! Rational arithmetic with division and a conditional, over `Ratio<i64>`.
+//! Division by zero is an error that a rule must preserve.
+//!
+//! Public so that the `enumo` command-line tool and other programs can run
+//! the domain; it is part of the library's API.
+
+use num::{
+    rational::Ratio, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Signed, ToPrimitive, Zero,
+};
+use symbolic_expressions::{parser::parse_str, Sexp};
+use z3::ast::Ast;
+
+use crate::{
+    enumo::{OpMap, OpMaps},
+    map, self_product, with_z3_context, z3_solver, CVec, Counterexample, EGraph, HashSet, Id,
+    Interval, Pattern, Symbol, SynthAnalysis, SynthLanguage, ValidationResult,
+};
+
+/// define `Constant` for rationals.
+pub type Constant = Ratio<i64>;
+
+pub fn mk_rat(n: i64, d: i64) -> Constant {
+    if d.is_zero() {
+        panic!("mk_rat: denominator is zero!");
+    }
+    Ratio::new(n, d)
+}
+
+egg::define_language! {
+  pub enum Math {
+    "+" = Add([Id; 2]),
+    "-" = Sub([Id; 2]),
+    "*" = Mul([Id; 2]),
+    "/" = Div([Id; 2]),
+    "~" = Neg(Id),
+    "fabs" = Abs(Id),
+    "if" = If([Id; 3]),
+    Lit(Constant),
+    Var(egg::Symbol),
+  }
+}
+
+impl SynthLanguage for Math {
+    type Constant = Constant;
+    const NAME: &'static str = "rational";
+
+    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
+    where
+        F: FnMut(&'a Id) -> &'a CVec<Self>,
+    {
+        match self {
+            Math::Add([x, y]) => map!(get_cvec, x, y => x.checked_add(y)),
+            Math::Sub([x, y]) => map!(get_cvec, x, y => x.checked_sub(y)),
+            Math::Mul([x, y]) => map!(get_cvec, x, y => x.checked_mul(y)),
+            Math::Div([x, y]) => map!(get_cvec, x, y => {
+                x.checked_div(y)
+            }),
+            Math::Neg(x) => map!(get_cvec, x => Some(-x)),
+            Math::Abs(a) => map!(get_cvec, a => Some(a.abs())),
+            Math::Lit(c) => vec![Some(*c); cvec_len],
+            Math::Var(_) => vec![],
+            Math::If([x, y, z]) => get_cvec(x)
+                .iter()
+                .zip(get_cvec(y).iter())
+                .zip(get_cvec(z).iter())
+                .map(|tup| {
+                    let ((x, y), z) = tup;
+                    if let Some(cond) = x {
+                        if !cond.is_zero() {
+                            *y
+                        } else {
+                            *z
+                        }
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>(),
+        }
+    }
+
+    fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
+    where
+        F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
+    {
+        let mut get_const = |x: &'a Id| {
+            let ival = get_interval(x);
+            if ival.low == ival.high {
+                ival.low
+            } else {
+                None
+            }
+        };
+        match self {
+            Math::Lit(n) => Some(*n),
+            Math::Var(_) => None,
+            Math::Neg(x) => get_const(x).map(|c| -c),
+            Math::Abs(a) => get_const(a).map(|c| c.abs()),
+            Math::Add([x, y]) => match (get_const(x), get_const(y)) {
+                (Some(x), Some(y)) => x.checked_add(&y),
+                _ => None,
+            },
+            Math::Sub([x, y]) => match (get_const(x), get_const(y)) {
+                (Some(x), Some(y)) => x.checked_sub(&y),
+                _ => None,
+            },
+            Math::Mul([x, y]) => match (get_const(x), get_const(y)) {
+                (Some(x), Some(y)) => x.checked_mul(&y),
+                _ => None,
+            },
+            Math::Div([x, y]) => match (get_const(x), get_const(y)) {
+                (Some(x), Some(y)) => x.checked_div(&y),
+                _ => None,
+            },
+            Math::If([x, y, z]) => {
+                if let Some(x) = get_const(x) {
+                    if !x.is_zero() {
+                        get_const(y)
+                    } else {
+                        get_const(z)
+                    }
+                } else {
+                    None
+                }
+            }
+        }
+        .map(|c| Interval::new(Some(c), Some(c)))
+        .unwrap_or_default()
+    }
+
+    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
+        let consts = vec![
+            Some(mk_rat(-1, 1)),
+            Some(mk_rat(0, 1)),
+            Some(mk_rat(1, 1)),
+            Some(mk_rat(2, 1)),
+            Some(mk_rat(-3, 1)),
+        ];
+        let cvecs = self_product(&consts, vars.len());
+
+        egraph.analysis.cvec_len = cvecs[0].len();
+
+        for (i, v) in vars.iter().enumerate() {
+            let id = egraph.add(Math::Var(Symbol::from(v.clone())));
+            let cvec = cvecs[i].clone();
+            egraph[id].data.cvec = cvec;
+        }
+    }
+
+    fn mk_var(sym: egg::Symbol) -> Self {
+        Math::Var(sym)
+    }
+
+    fn to_var(&self) -> Option<Symbol> {
+        match self {
+            Math::Var(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        // TODO if you drop variables, it's unsound because
+        // we may have lost an error
+        /*if lhs.vars().into_iter().collect::<HashSet<Var>>()
+            != rhs.vars().into_iter().collect::<HashSet<Var>>()
+        {
+            return ValidationResult::Invalid(None);
+        }*/
+        Self::validate_under(lhs, rhs, None)
+    }
+
+    fn is_true(c: &Self::Constant) -> bool {
+        !c.is_zero()
+    }
+
+    fn validate_with_cond(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        cond: &Pattern<Self>,
+    ) -> ValidationResult<Self> {
+        Self::validate_under(lhs, rhs, Some(cond))
+    }
+
+    fn is_constant(&self) -> bool {
+        matches!(self, Math::Lit(_))
+    }
+
+    fn mk_constant(c: Self::Constant) -> Self {
+        Math::Lit(c)
+    }
+}
+
+impl Math {
+    /// Checks that lhs and rhs are equal and error on the same inputs,
+    /// assuming the guard (if any) is defined and nonzero.
+    fn validate_under(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        guard: Option<&Pattern<Self>>,
+    ) -> ValidationResult<Self> {
+        with_z3_context(|ctx| {
+            let solver = z3_solver(ctx);
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            let lhs_denom = Self::error_conditions(
+                ctx,
+                Self::pat_to_sexp(lhs),
+                z3::ast::Bool::from_bool(ctx, true),
+            );
+            let rhs_denom = Self::error_conditions(
+                ctx,
+                Self::pat_to_sexp(rhs),
+                z3::ast::Bool::from_bool(ctx, true),
+            );
+
+            let mut assert_equal = lexpr._eq(&rexpr);
+
+            for condition in lhs_denom.iter().chain(rhs_denom.iter()) {
+                assert_equal = condition.not().implies(&assert_equal);
+            }
+
+            let rhs_errors =
+                z3::ast::Bool::or(ctx, &rhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
+            let lhs_errors =
+                z3::ast::Bool::or(ctx, &lhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
+            let error_preserved = rhs_errors.iff(&lhs_errors);
+            let mut assertion = z3::ast::Bool::and(ctx, &[&assert_equal, &error_preserved]);
+
+            if let Some(guard) = guard {
+                let zero = z3::ast::Real::from_real(ctx, 0, 1);
+                let guard_expr = egg_to_z3(ctx, Self::instantiate(guard).as_ref());
+                let guard_errors = Self::error_conditions(
+                    ctx,
+                    Self::pat_to_sexp(guard),
+                    z3::ast::Bool::from_bool(ctx, true),
+                );
+                let guard_defined =
+                    z3::ast::Bool::or(ctx, &guard_errors.iter().collect::<Vec<&z3::ast::Bool>>())
+                        .not();
+                let guard_holds =
+                    z3::ast::Bool::and(ctx, &[&guard_defined, &guard_expr._eq(&zero).not()]);
+                assertion = guard_holds.implies(&assertion);
+            }
+
+            solver.assert(&assertion.clone().not());
+            /*if let ValidationResult::Valid = res {
+                eprintln!("verifying {} => {}", lhs, rhs);
+            eprintln!("assertion: {}", assertion);
+            }*/
+            match solver.check() {
+                z3::SatResult::Sat => {
+                    ValidationResult::Invalid(Self::counterexample(ctx, &solver, lhs, rhs))
+                }
+                res => Self::z3_res_to_validationresult(res),
+            }
+        })
+    }
+
+    fn _one_of_errors(ctx: &z3::Context, denoms: HashSet<String>) -> z3::ast::Bool {
+        let zero_z3 = z3::ast::Real::from_real(ctx, 0, 1);
+
+        let mut one_of_rhs_errors = z3::ast::Bool::from_bool(ctx, false);
+        for d in denoms {
+            let expr = egg_to_z3(
+                ctx,
+                Self::instantiate(&d.to_string().parse::<Pattern<Math>>().unwrap()).as_ref(),
+            );
+            one_of_rhs_errors = z3::ast::Bool::or(ctx, &[&one_of_rhs_errors, &expr._eq(&zero_z3)]);
+        }
+        one_of_rhs_errors
+    }
+
+    /// Read the values of the rule's variables from the solver's model.
+    fn counterexample(
+        ctx: &z3::Context,
+        solver: &z3::Solver,
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+    ) -> Option<Counterexample<Self>> {
+        let model = solver.get_model()?;
+        let mut assignment: Vec<(Symbol, Constant)> = vec![];
+        for var in lhs.vars().into_iter().chain(rhs.vars()) {
+            let name = &var.to_string()[1..];
+            let sym = Symbol::from(name);
+            if assignment.iter().any(|(v, _)| *v == sym) {
+                continue;
+            }
+            let (n, d) = model
+                .eval(&z3::ast::Real::new_const(ctx, name))?
+                .as_real()?;
+            assignment.push((sym, mk_rat(n, d)));
+        }
+        Some(Counterexample::new(lhs, rhs, assignment))
+    }
+
+    fn z3_res_to_validationresult(res: z3::SatResult) -> ValidationResult<Self> {
+        match res {
+            z3::SatResult::Unsat => ValidationResult::Valid,
+            z3::SatResult::Sat => ValidationResult::Invalid(None),
+            z3::SatResult::Unknown => ValidationResult::Unknown,
+        }
+    }
+
+    fn pat_to_sexp(pat: &Pattern<Math>) -> Sexp {
+        parse_str(&pat.to_string()).unwrap()
+    }
+
+    /// Given an expression, returns a vector
+    /// of conditions for when the expression
+    /// divides by zero.
+    ///
+    /// For example, the expression
+    /// (/ 1 x) errors when (== x 0)
+    ///
+    /// The path variable stores the path conditions for reaching this expression.
+    ///
+    /// For example,
+    /// In (if x y z), the expression y
+    /// has condition (!= x 0)
+    fn error_conditions<'a>(
+        ctx: &'a z3::Context,
+        sexp: Sexp,
+        path: z3::ast::Bool<'a>,
+    ) -> Vec<z3::ast::Bool<'a>> {
+        let mut res = Vec::<z3::ast::Bool<'a>>::default();
+        match sexp {
+            Sexp::List(list) => {
+                if list[0] == Sexp::String("/".to_string()) {
+                    let denom = list[2].to_string();
+                    let expr = egg_to_z3(
+                        ctx,
+                        Self::instantiate(&denom.to_string().parse::<Pattern<Math>>().unwrap())
+                            .as_ref(),
+                    );
+                    let is_zero = expr._eq(&z3::ast::Real::from_real(ctx, 0, 1));
+
+                    res.push(z3::ast::Bool::and(ctx, &[&is_zero, &path]));
+                }
+
+                if list[0] == Sexp::String("if".to_string()) {
+                    let cond_real = egg_to_z3(
+                        ctx,
+                        Self::instantiate(&list[1].to_string().parse::<Pattern<Math>>().unwrap())
+                            .as_ref(),
+                    );
+                    let zero = z3::ast::Real::from_real(ctx, 0, 1);
+                    let new_path_pos = z3::ast::Bool::and(
+                        ctx,
+                        &[&path, &z3::ast::Bool::not(&cond_real._eq(&zero))],
+                    );
+                    let new_path_neg = z3::ast::Bool::and(ctx, &[&path, &cond_real._eq(&zero)]);
+                    res.extend(Self::error_conditions(ctx, list[2].clone(), new_path_pos));
+                    res.extend(Self::error_conditions(ctx, list[3].clone(), new_path_neg));
+                } else {
+                    for s in list {
+                        res.extend(Self::error_conditions(ctx, s, path.clone()));
+                    }
+                };
+            }
+            Sexp::String(_) => (),
+            Sexp::Empty => (),
+        }
+
+        res
+    }
+}
+
+fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Math]) -> z3::ast::Real<'a> {
+    let mut buf: Vec<z3::ast::Real> = vec![];
+    for node in expr.as_ref().iter() {
+        match node {
+            Math::Add([x, y]) => buf.push(z3::ast::Real::add(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Math::Sub([x, y]) => buf.push(z3::ast::Real::sub(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Math::Mul([x, y]) => buf.push(z3::ast::Real::mul(
+                ctx,
+                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
+            )),
+            Math::Div([x, y]) => buf.push(z3::ast::Real::div(
+                &buf[usize::from(*x)],
+                &buf[usize::from(*y)],
+            )),
+            Math::Neg(x) => buf.push(z3::ast::Real::unary_minus(&buf[usize::from(*x)])),
+            Math::Abs(a) => {
+                let inner = &buf[usize::from(*a)].clone();
+                let zero = z3::ast::Real::from_real(ctx, 0, 1);
+                buf.push(z3::ast::Bool::ite(
+                    &z3::ast::Real::le(inner, &zero),
+                    &z3::ast::Real::unary_minus(inner),
+                    inner,
+                ));
+            }
+            Math::Lit(c) => buf.push(z3::ast::Real::from_real(
+                ctx,
+                (c.numer()).to_i32().unwrap(),
+                (c.denom()).to_i32().unwrap(),
+            )),
+            Math::Var(v) => buf.push(z3::ast::Real::new_const(ctx, v.to_string())),
+            Math::If([x, y, z]) => {
+                let zero = z3::ast::Real::from_real(ctx, 0, 1);
+                let cond = z3::ast::Bool::not(&buf[usize::from(*x)]._eq(&zero));
+                buf.push(z3::ast::Bool::ite(
+                    &cond,
+                    &buf[usize::from(*y)],
+                    &buf[usize::from(*z)],
+                ))
+            }
+        }
+    }
+    buf.pop().unwrap()
+}
+
+impl OpMaps for Math {
+    fn smtlib() -> OpMap {
+        let mut map = OpMap::new(
+            "Real",
+            &[
+                ("~", "(- {0})"),
+                ("fabs", "(ite (< {0} 0.0) (- {0}) {0})"),
+                ("if", "(ite (not (= {0} 0.0)) {1} {2})"),
+            ],
+        );
+        map.literal = |c| match c.split_once('/') {
+            Some((n, d)) => format!("(/ {} {})", smt_real(n), smt_real(d)),
+            None => smt_real(c),
+        };
+        map.falsity = Some("0.0".into());
+        map
+    }
+
+    fn egglog() -> OpMap {
+        let mut map = OpMap::new(
+            "Math",
+            &[
+                ("+", "Add"),
+                ("-", "Sub"),
+                ("*", "Mul"),
+                ("/", "Div"),
+                ("~", "Neg"),
+                ("fabs", "Abs"),
+                ("if", "If"),
+            ],
+        );
+        map.literal = |c| match c.split_once('/') {
+            Some((n, d)) => format!("(Lit (rational {} {}))", n, d),
+            None => format!("(Lit (rational {} 1))", c),
+        };
+        map.falsity = Some("(Lit (rational 0 1))".into());
+        map
+    }
+
+    fn lean() -> OpMap {
+        let mut map = OpMap::new(
+            "Rat",
+            &[
+                ("+", "({0} + {1})"),
+                ("-", "({0} - {1})"),
+                ("*", "({0} * {1})"),
+                ("/", "({0} / {1})"),
+                ("~", "(-{0})"),
+                ("fabs", "|{0}|"),
+                ("if", "(if {0} ≠ 0 then {1} else {2})"),
+            ],
+        );
+        map.literal = |c| format!("({} : Rat)", c);
+        map.falsity = Some("0".into());
+        map
+    }
+}
+
+/// An integer as an SMT-LIB2 real, e.g. `(- 3.0)` for `-3`.
+fn smt_real(n: &str) -> String {
+    match n.strip_prefix('-') {
+        Some(n) => format!("(- {}.0)", n),
+        None => format!("{}.0", n),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::enumo::Ruleset;
+
+    #[test]
+    fn op_maps_keep_guards_nonzero() {
+        let rules: Ruleset<Math> =
+            Ruleset::new(["(/ ?a ?a) ==> 1 if ?a", "(~ ?a) ==> (* -1/2 ?a)"]);
+        let scripts = rules.to_smtlib(&Math::smtlib());
+        assert!(scripts[0].contains("(=> (not (= a 0.0)) (= (/ a a) 1.0))"));
+        assert!(scripts[1].contains("(= (- a) (* (/ (- 1.0) 2.0) a))"));
+        assert_eq!(
+            rules.to_egglog(&Math::egglog()),
+            "(rewrite (Div a a) (Lit (rational 1 1)) :when ((!= a (Lit (rational 0 1)))))\n\
+             (rewrite (Neg a) (Mul (Lit (rational -1 2)) a))\n"
+        );
+    }
+}
@@ -0,0 +1,555 @@
+/*!
+Recipes as data: a file describing the steps of a synthesis run, and an
+interpreter that runs it for any `SynthLanguage`.
+
+A recipe file is a sequence of s-expressions. `;` starts a comment.
+
+```text
+; Operators and leaves for `recursive` steps.
+(lang bool (vals 0 1) (vars a b c) (ops (!) (&& || ^)))
+
+; A named workload, usable wherever a workload is expected.
+(workload leaves (set a b c))
+
+(step bool-only (recursive bool atoms 5))
+
+(step nested
+  (run
+    (filter (canon a b c)
+      (plug (set (bop e e) v)
+        (e (set (bop v v) (uop v) v))
+        (bop (set && ||))
+        (uop (set !))
+        (v leaves))))
+  (prior bool-only)
+  (limits synthesis (iter 1) (node 100000))
+  (fast-match false))
+```
+
+A step learns rules in one of three ways:
+- `(recursive LANG METRIC N)`: `recursive_rules` over a `lang`,
+- `(run WORKLOAD)`: `run_workload`,
+- `(fast-forward WORKLOAD)`: `run_fast_forwarding`.
+
+Workloads are `(set TERM ...)`, `(plug WORKLOAD (HOLE WORKLOAD) ...)`,
+`(filter FILTER WORKLOAD)`, `(append WORKLOAD ...)`,
+`(iter-metric WORKLOAD ATOM METRIC N)`, `(base-lang N)` or the name of a
+`workload`. Filters are `(metric-lt METRIC N)`, `(metric-eq METRIC N)`,
+`(contains PATTERN)`, `(excludes PATTERN)`, `(canon SYMBOL ...)`,
+`(and FILTER ...)`, `(or FILTER ...)` and `(not FILTER)`, and metrics are
+`atoms`, `lists` and `depth`.
+
+The prior rules of a step are those the recipe started from and those
+learned by all earlier steps, unless it says otherwise with
+`(prior STEP ...)` or `(prior none)`. `(limits synthesis ...)` and
+`(limits minimize ...)` override fields of `Limits::synthesis()` and
+`Limits::minimize()`; their fields are `iter`, `node`, `match`, `time` in
+seconds and `max-egraph-nodes`. `(fast-match false)` turns off fast cvec
+matching. A `recursive` step applies its limits to the run of each term size.
+The recipe learns the rules of all its steps.
+!*/
+
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+use crate::{
+    enumo::{Filter, Metric, Ruleset, Sexp, Workload},
+    recipe_utils::{
+        base_lang, iter_metric, recursive_rules_with, run_fast_forwarding, run_workload, Lang,
+    },
+    HashMap, Limits, SynthLanguage,
+};
+
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,
+    pub kind: StepKind,
+    pub prior: Prior,
+    pub synthesis_limits: Limits,
+    pub minimize_limits: Limits,
+    pub fast_match: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum StepKind {
+    Recursive {
+        lang: Lang,
+        metric: Metric,
+        n: usize,
+    },
+    Run(Workload),
+    FastForward(Workload),
+}
+
+/// Which learned rules a step starts from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prior {
+    /// The rules of all earlier steps.
+    All,
+    /// The rules of the given earlier steps.
+    Steps(Vec<String>),
+}
+
+type ParseResult<T> = Result<T, String>;
+
+/// Why a recipe file could not be loaded.
+#[derive(Debug)]
+pub enum RecipeError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file is not a valid recipe.
+    Parse(String),
+}
+
+impl Display for RecipeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeError::Io(e) => write!(f, "{}", e),
+            RecipeError::Parse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RecipeError {}
+
+impl Recipe {
+    pub fn parse(s: &str) -> ParseResult<Self> {
+        let forms = parse_forms(s)?;
+        let mut langs: HashMap<String, Lang> = HashMap::default();
+        let mut workloads: HashMap<String, Workload> = HashMap::default();
+        let mut steps: Vec<Step> = vec![];
+        for form in &forms {
+            let (head, args) = split(form)?;
+            match (head, args) {
+                ("lang", [name, specs @ ..]) => {
+                    let lang = parse_lang(specs)?;
+                    langs.insert(atom(name)?.to_string(), lang);
+                }
+                ("workload", [name, workload]) => {
+                    let workload = parse_workload(workload, &workloads)?;
+                    workloads.insert(atom(name)?.to_string(), workload);
+                }
+                ("step", [name, kind, options @ ..]) => {
+                    let name = atom(name)?.to_string();
+                    if steps.iter().any(|s| s.name == name) {
+                        return Err(format!("duplicate step `{}`", name));
+                    }
+                    let step = parse_step(name.clone(), kind, options, &langs, &workloads, &steps)
+                        .map_err(|e| format!("in step `{}`: {}", name, e))?;
+                    steps.push(step);
+                }
+                _ => {
+                    return Err(format!(
+                        "expected `lang`, `workload` or `step`, found {}",
+                        form
+                    ))
+                }
+            }
+        }
+        Ok(Self { steps })
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self, RecipeError> {
+        let s = std::fs::read_to_string(filename).map_err(RecipeError::Io)?;
+        Self::parse(&s).map_err(RecipeError::Parse)
+    }
+
+    /// Run the steps in order, returning all the rules they learn. `prior` is
+    /// part of the prior rules of steps without a `prior` option.
+    pub fn run<L: SynthLanguage>(&self, prior: Ruleset<L>) -> Ruleset<L> {
+        let mut learned: HashMap<&str, Ruleset<L>> = HashMap::default();
+        let mut all = prior.clone();
+        for step in &self.steps {
+            let prior = match &step.prior {
+                Prior::All => all.clone(),
+                Prior::Steps(names) => {
+                    let mut prior = Ruleset::default();
+                    for name in names {
+                        prior.extend(learned[name.as_str()].clone());
+                    }
+                    prior
+                }
+            };
+            let new = step.run(prior);
+            all.extend(new.clone());
+            learned.insert(&step.name, new);
+        }
+        all.remove_all(prior);
+        all
+    }
+}
+
+impl Step {
+    pub fn run<L: SynthLanguage>(&self, prior: Ruleset<L>) -> Ruleset<L> {
+        match &self.kind {
+            StepKind::Recursive { lang, metric, n } => recursive_rules_with(
+                *metric,
+                *n,
+                lang.clone(),
+                prior,
+                self.synthesis_limits,
+                self.minimize_limits,
+                self.fast_match,
+            ),
+            StepKind::Run(workload) => run_workload(
+                workload.clone(),
+                prior,
+                self.synthesis_limits,
+                self.minimize_limits,
+                self.fast_match,
+            ),
+            StepKind::FastForward(workload) => run_fast_forwarding(
+                workload.clone(),
+                prior,
+                self.synthesis_limits,
+                self.minimize_limits,
+            ),
+        }
+    }
+}
+
+/// The top-level forms of a recipe file, with comments removed.
+fn parse_forms(s: &str) -> ParseResult<Vec<Sexp>> {
+    let mut text = String::from("(");
+    let mut depth = 0usize;
+    for (i, line) in s.lines().enumerate() {
+        let line = line.split(';').next().unwrap();
+        for c in line.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return Err(format!("line {}: unexpected `)`", i + 1)),
+                ')' => depth -= 1,
+                '"' => return Err(format!("line {}: strings are not supported", i + 1)),
+                _ => (),
+            }
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+    if depth > 0 {
+        return Err("unclosed `(` at end of file".into());
+    }
+    text.push(')');
+    let sexp = symbolic_expressions::parser::parse_str(&text).map_err(|e| e.to_string())?;
+    match Sexp::from_symbolic_expr(sexp) {
+        Sexp::List(forms) => Ok(forms),
+        Sexp::Atom(_) => unreachable!("recipe is wrapped in a list"),
+    }
+}
+
+fn atom(sexp: &Sexp) -> ParseResult<&str> {
+    match sexp {
+        Sexp::Atom(a) => Ok(a),
+        Sexp::List(_) => Err(format!("expected an atom, found {}", sexp)),
+    }
+}
+
+fn atoms(sexps: &[Sexp]) -> ParseResult<Vec<String>> {
+    sexps.iter().map(|s| atom(s).map(String::from)).collect()
+}
+
+fn number(sexp: &Sexp) -> ParseResult<usize> {
+    let a = atom(sexp)?;
+    a.parse()
+        .map_err(|_| format!("expected a number, found `{}`", a))
+}
+
+/// The head and arguments of a form like `(head args...)`.
+fn split(sexp: &Sexp) -> ParseResult<(&str, &[Sexp])> {
+    match sexp {
+        Sexp::List(items) if !items.is_empty() => Ok((atom(&items[0])?, &items[1..])),
+        _ => Err(format!("expected a form `(name ...)`, found {}", sexp)),
+    }
+}
+
+fn parse_lang(specs: &[Sexp]) -> ParseResult<Lang> {
+    let mut lang = Lang {
+        vals: vec![],
+        vars: vec![],
+        ops: vec![],
+    };
+    for spec in specs {
+        match split(spec)? {
+            ("vals", vals) => lang.vals = atoms(vals)?,
+            ("vars", vars) => lang.vars = atoms(vars)?,
+            ("ops", ops) => {
+                lang.ops = ops
+                    .iter()
+                    .map(|arity| match arity {
+                        Sexp::List(ops) => atoms(ops),
+                        Sexp::Atom(_) => {
+                            Err(format!("expected a list of operators, found {}", arity))
+                        }
+                    })
+                    .collect::<ParseResult<_>>()?
+            }
+            _ => return Err(format!("expected `vals`, `vars` or `ops`, found {}", spec)),
+        }
+    }
+    Ok(lang)
+}
+
+fn parse_metric(sexp: &Sexp) -> ParseResult<Metric> {
+    match atom(sexp)? {
+        "atoms" => Ok(Metric::Atoms),
+        "lists" => Ok(Metric::Lists),
+        "depth" => Ok(Metric::Depth),
+        m => Err(format!("unknown metric `{}`", m)),
+    }
+}
+
+fn parse_filter(sexp: &Sexp) -> ParseResult<Filter> {
+    let filters = |fs: &[Sexp]| fs.iter().map(parse_filter).collect::<ParseResult<Vec<_>>>();
+    Ok(match split(sexp)? {
+        ("metric-lt", [metric, n]) => Filter::MetricLt(parse_metric(metric)?, number(n)?),
+        ("metric-eq", [metric, n]) => Filter::MetricEq(parse_metric(metric)?, number(n)?),
+        ("contains", [pattern]) => Filter::Contains(pattern.to_string().parse()?),
+        ("excludes", [pattern]) => Filter::Excludes(pattern.to_string().parse()?),
+        ("canon", symbols) => Filter::Canon(atoms(symbols)?),
+        ("and", fs) => Filter::And(filters(fs)?),
+        ("or", fs) => Filter::Or(filters(fs)?),
+        ("not", [f]) => Filter::Invert(Box::new(parse_filter(f)?)),
+        _ => return Err(format!("bad filter {}", sexp)),
+    })
+}
+
+fn parse_workload(sexp: &Sexp, named: &HashMap<String, Workload>) -> ParseResult<Workload> {
+    if let Sexp::Atom(name) = sexp {
+        return named
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("unknown workload `{}`", name));
+    }
+    Ok(match split(sexp)? {
+        ("set", terms) => Workload::Set(terms.to_vec()),
+        ("plug", [workload, plugs @ ..]) => {
+            let mut workload = parse_workload(workload, named)?;
+            for plug in plugs {
+                match plug {
+                    Sexp::List(hole_and_pegs) if hole_and_pegs.len() == 2 => {
+                        let pegs = parse_workload(&hole_and_pegs[1], named)?;
+                        workload = workload.plug(atom(&hole_and_pegs[0])?, &pegs);
+                    }
+                    _ => return Err(format!("expected `(HOLE WORKLOAD)`, found {}", plug)),
+                }
+            }
+            workload
+        }
+        ("filter", [filter, workload]) => {
+            parse_workload(workload, named)?.filter(parse_filter(filter)?)
+        }
+        ("append", workloads) => workloads.iter().try_fold(Workload::empty(), |acc, w| {
+            Ok::<_, String>(acc.append(parse_workload(w, named)?))
+        })?,
+        ("iter-metric", [workload, hole, metric, n]) => iter_metric(
+            parse_workload(workload, named)?,
+            atom(hole)?,
+            parse_metric(metric)?,
+            number(n)?,
+        ),
+        ("base-lang", [n]) => base_lang(number(n)?),
+        _ => return Err(format!("bad workload {}", sexp)),
+    })
+}
+
+fn parse_limits(default: Limits, fields: &[Sexp]) -> ParseResult<Limits> {
+    let mut limits = default;
+    for field in fields {
+        match split(field)? {
+            ("iter", [n]) => limits.iter = number(n)?,
+            ("node", [n]) => limits.node = number(n)?,
+            ("match", [n]) => limits.match_ = number(n)?,
+            ("time", [secs]) => limits.time = Some(Duration::from_secs(number(secs)? as u64)),
+            ("max-egraph-nodes", [n]) => limits.max_egraph_nodes = Some(number(n)?),
+            _ => {
+                return Err(format!(
+                    "expected `iter`, `node`, `match`, `time` or `max-egraph-nodes`, found {}",
+                    field
+                ))
+            }
+        }
+    }
+    Ok(limits)
+}
+
+fn parse_step(
+    name: String,
+    kind: &Sexp,
+    options: &[Sexp],
+    langs: &HashMap<String, Lang>,
+    workloads: &HashMap<String, Workload>,
+    earlier: &[Step],
+) -> ParseResult<Step> {
+    let kind = match split(kind)? {
+        ("recursive", [lang, metric, n]) => StepKind::Recursive {
+            lang: langs
+                .get(atom(lang)?)
+                .cloned()
+                .ok_or_else(|| format!("unknown lang `{}`", lang))?,
+            metric: parse_metric(metric)?,
+            n: number(n)?,
+        },
+        ("run", [workload]) => StepKind::Run(parse_workload(workload, workloads)?),
+        ("fast-forward", [workload]) => StepKind::FastForward(parse_workload(workload, workloads)?),
+        _ => {
+            return Err(format!(
+                "expected `recursive`, `run` or `fast-forward`, found {}",
+                kind
+            ))
+        }
+    };
+    let mut step = Step {
+        name,
+        kind,
+        prior: Prior::All,
+        synthesis_limits: Limits::synthesis(),
+        minimize_limits: Limits::minimize(),
+        fast_match: true,
+    };
+    for option in options {
+        match split(option)? {
+            ("prior", [Sexp::Atom(all)]) if all == "all" => step.prior = Prior::All,
+            ("prior", [Sexp::Atom(none)]) if none == "none" => step.prior = Prior::Steps(vec![]),
+            ("prior", names) => {
+                let names = atoms(names)?;
+                if let Some(unknown) = names
+                    .iter()
+                    .find(|n| !earlier.iter().any(|s| &s.name == *n))
+                {
+                    return Err(format!("prior `{}` is not an earlier step", unknown));
+                }
+                step.prior = Prior::Steps(names);
+            }
+            ("limits", [which, fields @ ..]) => match atom(which)? {
+                "synthesis" => step.synthesis_limits = parse_limits(step.synthesis_limits, fields)?,
+                "minimize" => step.minimize_limits = parse_limits(step.minimize_limits, fields)?,
+                w => {
+                    return Err(format!(
+                        "expected `synthesis` or `minimize` limits, found `{}`",
+                        w
+                    ))
+                }
+            },
+            ("fast-match", [b]) => {
+                step.fast_match = atom(b)?
+                    .parse()
+                    .map_err(|_| format!("expected `true` or `false`, found {}", b))?
+            }
+            _ => return Err(format!("bad step option {}", option)),
+        }
+    }
+    Ok(step)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{enumo::Rule, recipe_utils::recursive_rules};
+    use crate::bv4;
+
+    const RECIPE: &str = "
+; Operators and leaves
+(lang bv (vals 0 1) (vars a b c) (ops (~) (& |)))
+(workload leaves (set a b))
+
+(step base (recursive bv atoms 3))
+(step nested
+  (run
+    (filter (canon a b)
+      (plug (set (op e e))
+        (e (set (op v v) v))
+        (op (set & |))
+        (v leaves))))
+  (prior base)
+  (limits synthesis (iter 1))
+  (limits minimize (time 60) (max-egraph-nodes 1000000))
+  (fast-match false))
+";
+
+    #[test]
+    fn parse_recipe() {
+        let recipe = Recipe::parse(RECIPE).unwrap();
+        assert_eq!(recipe.steps.len(), 2);
+        let nested = &recipe.steps[1];
+        assert_eq!(nested.prior, Prior::Steps(vec!["base".into()]));
+        assert_eq!(nested.synthesis_limits.iter, 1);
+        assert_eq!(nested.synthesis_limits.node, Limits::synthesis().node);
+        assert_eq!(nested.minimize_limits.time, Some(Duration::from_secs(60)));
+        assert_eq!(nested.minimize_limits.max_egraph_nodes, Some(1_000_000));
+        assert!(!nested.fast_match);
+        match &nested.kind {
+            StepKind::Run(workload) => {
+                let expected = Workload::new(["(op e e)"])
+                    .plug("e", &Workload::new(["(op v v)", "v"]))
+                    .plug("op", &Workload::new(["&", "|"]))
+                    .plug("v", &Workload::new(["a", "b"]))
+                    .filter(Filter::Canon(vec!["a".into(), "b".into()]));
+                assert_eq!(workload.force(), expected.force());
+            }
+            kind => panic!("expected a run step, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |s: &str| Recipe::parse(s).unwrap_err();
+        assert_eq!(error("(step a (run (set x)))\n)"), "line 2: unexpected `)`");
+        assert_eq!(error("(lang x (vals 0)"), "unclosed `(` at end of file");
+        assert_eq!(
+            error("(step a (recursive nope atoms 3))"),
+            "in step `a`: unknown lang `nope`"
+        );
+        assert_eq!(
+            error("(step a (run (set x)) (prior b))"),
+            "in step `a`: prior `b` is not an earlier step"
+        );
+        assert_eq!(
+            error("(step a (run (filter (metric-lt size 3) (set x))))"),
+            "in step `a`: unknown metric `size`"
+        );
+    }
+
+    #[test]
+    fn recipe_file_errors() {
+        let missing = std::env::temp_dir().join("enumo-missing.recipe");
+        let _ = std::fs::remove_file(&missing);
+        assert!(matches!(
+            Recipe::from_file(missing.to_str().unwrap()),
+            Err(RecipeError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn recursive_step_limits() {
+        let recipe = Recipe::parse(
+            "(lang bv (vals 0) (vars a b) (ops (~) (&)))
+             (step base (recursive bv atoms 3) (limits minimize (time 0)))",
+        )
+        .unwrap();
+        assert_eq!(recipe.steps[0].minimize_limits.time, Some(Duration::ZERO));
+        let limited: Ruleset<bv4::Bv> = recipe.run(Ruleset::default());
+        let full: Ruleset<bv4::Bv> = recursive_rules(
+            Metric::Atoms,
+            3,
+            Lang::new(&["0"], &["a", "b"], &[&["~"], &["&"]]),
+            Ruleset::default(),
+        );
+        assert!(limited.len() < full.len());
+    }
+
+    #[test]
+    fn run_recipe() {
+        let recipe = Recipe::parse(RECIPE).unwrap();
+        let rules: Ruleset<bv4::Bv> = recipe.run(Ruleset::default());
+        let (comm, _) = Rule::from_string("(& ?b ?a) ==> (& ?a ?b)").unwrap();
+        assert!(rules.contains(&comm));
+        assert!(rules.iter().all(|rule| rule.is_valid()));
+    }
+}
@@ -1,10 +1,20 @@
-use std::time::Instant;
+use std::{cmp::Reverse, sync::Arc, time::Instant};
+
+use log::{info, warn};
 
 use crate::{
-  Limits, SynthLanguage,
-  enumo::{Filter, Metric, Ruleset, Scheduler, Workload},
+  CVec, Counterexample, EGraph, HashMap, HashSet, Id, Limits, SynthAnalysis,
+  SynthLanguage, checkpoint,
+  enumo::{
+    Budget, Filter, Metric, MinimizeConfig, Minimized, Rule, RuleScorer,
+    Ruleset, Scheduler, Workload,
+  },
+  report::{self, EGraphSize, StageTimes, SynthReport},
 };
 
+/// Number of candidates validated per refinement round.
+const REFINE_BATCH: usize = 64;
+
 /// Iterate a grammar (represented as a workload) up to a certain size metric
 pub fn iter_metric(
   wkld: Workload,
@@ -31,6 +41,194 @@ pub fn substitute(workload: Workload, sub: Workload, atom: &str) -> Workload {
   pegs
 }
 
+/// Find rule candidates by cvec matching, refining the cvecs with
+/// counterexamples to invalid candidates.
+///
+/// Each round validates the best `REFINE_BATCH` candidates not validated
+/// yet, in the order `minimize` would pick them with the default
+/// `MinimizeConfig`, so the likeliest rules are checked first. Every
+/// counterexample returned by the domain's validator is added to the e-graph
+/// as an extra cvec column and the candidates are recomputed. Stops once no
+/// candidate is left to validate, i.e., none of them is invalid, or when the
+/// time or e-node budget of `limits` runs out.
+pub fn refine_candidates<L: SynthLanguage>(
+  egraph: &mut EGraph<L, SynthAnalysis>,
+  fast_match: bool,
+  limits: Limits,
+) -> Ruleset<L> {
+  refine_candidates_timed(
+    egraph,
+    fast_match,
+    MinimizeConfig::default().scorer.as_ref(),
+    &Budget::new(limits),
+    &mut StageTimes::default(),
+  )
+}
+
+/// `refine_candidates` within what is left of `budget`, picking the
+/// candidates to validate with `scorer` and adding the time spent matching
+/// and validating to `times`.
+fn refine_candidates_timed<L: SynthLanguage>(
+  egraph: &mut EGraph<L, SynthAnalysis>,
+  fast_match: bool,
+  scorer: &dyn RuleScorer<L>,
+  budget: &Budget,
+  times: &mut StageTimes,
+) -> Ruleset<L> {
+  let mut find = |egraph: &EGraph<L, SynthAnalysis>| {
+    let t = Instant::now();
+    let candidates = if fast_match {
+      Ruleset::fast_cvec_match(egraph)
+    } else {
+      Ruleset::cvec_match(egraph)
+    };
+    times.match_ += t.elapsed();
+    candidates
+  };
+
+  let mut candidates = find(egraph);
+  let mut checked: HashSet<Arc<str>> = HashSet::default();
+  while !budget.exhausted() {
+    // Candidates checked in an earlier round already contributed their
+    // counterexamples, so only validate the new ones.
+    let mut unchecked: Vec<&Rule<L>> = candidates
+      .iter()
+      .filter(|rule| !checked.contains(&rule.name))
+      .collect();
+    if unchecked.is_empty() {
+      break;
+    }
+    unchecked.sort_by_cached_key(|rule| Reverse(scorer.score(rule)));
+    let mut batch = Ruleset::default();
+    for rule in unchecked.into_iter().take(REFINE_BATCH) {
+      checked.insert(rule.name.clone());
+      batch.add(rule.clone());
+    }
+    let t = Instant::now();
+    let counterexamples = batch.counterexamples();
+    times.validate += t.elapsed();
+    if counterexamples.is_empty() {
+      continue;
+    }
+    info!(
+      "refining cvecs with {} counterexamples",
+      counterexamples.len()
+    );
+    for cex in &counterexamples {
+      add_counterexample(egraph, cex);
+    }
+    candidates = find(egraph);
+  }
+  candidates
+}
+
+/// Add a counterexample to the e-graph as an extra cvec column.
+///
+/// Variables take the value assigned by the counterexample. Variables it
+/// does not mention reuse their first sample. Every other e-class is then
+/// re-evaluated bottom-up on the new column.
+pub fn add_counterexample<L: SynthLanguage>(
+  egraph: &mut EGraph<L, SynthAnalysis>,
+  cex: &Counterexample<L>,
+) {
+  let cvec_len = egraph.analysis.cvec_len;
+  let mut column: HashMap<Id, CVec<L>> = HashMap::default();
+  for class in egraph.classes() {
+    for node in &class.nodes {
+      if let Some(var) = node.to_var() {
+        let value = cex
+          .get(var)
+          .cloned()
+          .or_else(|| class.data.cvec.first().cloned().flatten());
+        column.insert(class.id, vec![value]);
+      }
+    }
+  }
+
+  loop {
+    let mut new_values = vec![];
+    for class in egraph.classes() {
+      if column.contains_key(&class.id) {
+        continue;
+      }
+      for node in &class.nodes {
+        if node.all(|id| column.contains_key(&id)) {
+          let cvec = node.eval(1, |id| &column[id]);
+          new_values.push((class.id, cvec.into_iter().next().flatten()));
+          break;
+        }
+      }
+    }
+    if new_values.is_empty() {
+      break;
+    }
+    for (id, value) in new_values {
+      column.insert(id, vec![value]);
+    }
+  }
+
+  let ids: Vec<Id> = egraph.classes().map(|c| c.id).collect();
+  for id in ids {
+    let data = &mut egraph[id].data;
+    if data.cvec.len() != cvec_len {
+      continue;
+    }
+    let value = column.get(&id).and_then(|v| v[0].clone());
+    data.cvec.push(value);
+    data.update_fuzz_constant();
+  }
+  egraph.analysis.cvec_len += 1;
+}
+
+/// Warn about candidates whose soundness could not be decided. They are left
+/// out of the learned rules, but callers may choose to include them.
+fn report_unverified<L: SynthLanguage>(minimized: &Minimized<L>) {
+  if minimized.unknown.is_empty() {
+    return;
+  }
+  warn!(
+    "{} candidate rules could not be verified:",
+    minimized.unknown.len()
+  );
+  for name in minimized.unknown.0.keys() {
+    warn!("  {}", name);
+  }
+}
+
+/// The report of a completed step, see `checkpoint`.
+fn resume<L: SynthLanguage>(
+  key: Option<&str>,
+  prior: &Ruleset<L>,
+) -> Option<SynthReport<L>> {
+  let rules = checkpoint::load(key?, prior)?;
+  let report = SynthReport::from_checkpoint(rules);
+  report::notify(&report);
+  Some(report)
+}
+
+/// Save a step's rules, along with the `prior` rules it started from, if
+/// checkpointing is enabled and the step completed, and report the step.
+fn finish<L: SynthLanguage>(
+  report: SynthReport<L>,
+  key: Option<&str>,
+  prior: &Ruleset<L>,
+) -> SynthReport<L> {
+  if report.partial {
+    warn!("Minimization ran out of budget, keeping the rules chosen so far");
+  } else if let Some(key) = key {
+    checkpoint::save(
+      key,
+      prior,
+      &report.rules,
+      report.times.total().as_secs_f64(),
+    );
+  }
+  report::notify(&report);
+  report
+}
+
+/// `config` is `None` for the default `MinimizeConfig`, which is the only
+/// one steps are checkpointed with.
 fn run_workload_internal<L: SynthLanguage>(
   workload: Workload,
   prior: Ruleset<L>,
@@ -38,51 +236,135 @@ fn run_workload_internal<L: SynthLanguage>(
   minimize_limits: Limits,
   fast_match: bool,
   allow_empty: bool,
-) -> Ruleset<L> {
-  let t = Instant::now();
+  config: Option<&MinimizeConfig<L>>,
+) -> SynthReport<L> {
+  let key = match (config, checkpoint::dir()) {
+    (None, Some(_)) => Some(
+      checkpoint::Step {
+        kind: "run_workload",
+        workload: &workload,
+        prior: &prior,
+        prior_limits,
+        minimize_limits,
+        fast_match,
+      }
+      .key(),
+    ),
+    _ => None,
+  };
+  if let Some(report) = resume(key.as_deref(), &prior) {
+    return report;
+  }
+  let mut times = StageTimes::default();
+  let default_config = MinimizeConfig::default();
+  let config = config.unwrap_or(&default_config);
 
   let egraph = workload.to_egraph::<L>();
+  let t = Instant::now();
   let mut compressed = Scheduler::Compress(prior_limits).run(&egraph, &prior);
+  times.compress = t.elapsed();
 
-  let mut candidates = if fast_match {
-    Ruleset::fast_cvec_match(&compressed)
-  } else {
-    Ruleset::cvec_match(&mut compressed)
-  };
+  // Refinement and minimization share the budget of `minimize_limits`
+  let mut budget = Budget::new(minimize_limits);
+  let mut candidates = refine_candidates_timed(
+    &mut compressed,
+    fast_match,
+    config.scorer.as_ref(),
+    &budget,
+    &mut times,
+  );
+  let num_candidates = candidates.len();
 
   let num_prior = prior.len();
-  let (chosen, _) =
-    candidates.minimize(prior, Scheduler::Compress(minimize_limits));
-  let time = t.elapsed().as_secs_f64();
+  let t = Instant::now();
+  let minimized = candidates.minimize_budgeted(
+    prior.clone(),
+    Scheduler::Compress(minimize_limits),
+    config,
+    &mut budget,
+  );
+  times.minimize = t.elapsed();
+  report_unverified(&minimized);
 
-  if chosen.is_empty() && !allow_empty {
+  if minimized.chosen.is_empty() && !allow_empty && !minimized.partial {
     panic!("Didn't learn any rules!");
   }
 
-  println!(
-    "Learned {} bidirectional rewrites ({} total rewrites) in {} using {} prior rewrites",
-    chosen.bidir_len(),
-    chosen.len(),
-    time,
-    num_prior
-  );
-
-  chosen.pretty_print();
-
-  chosen
+  let report = SynthReport {
+    initial: EGraphSize::of(&egraph),
+    compressed: EGraphSize::of(&compressed),
+    candidates: num_candidates,
+    prior: num_prior,
+    valid: minimized.chosen.len(),
+    invalid: minimized.invalid.len(),
+    unknown: minimized.unknown.len(),
+    times,
+    rules: minimized.chosen,
+    partial: minimized.partial,
+    from_checkpoint: false,
+  };
+  finish(report, key.as_deref(), &prior)
 }
 
 /// Runs rule inference:
 ///     1. convert workload to e-graph
 ///     2. If there are prior rules, compress the e-graph using them
-///     3. Find candidates via CVec matching
+///     3. Find candidates via CVec matching, refining the CVecs with
+///        counterexamples to invalid candidates
 ///     4. Minimize the candidates with respect to the prior rules
+///
+/// Refinement and minimization share the budget of `minimize_limits`. If it
+/// runs out, the rules chosen so far are returned and the step is not
+/// checkpointed.
 pub fn run_workload<L: SynthLanguage>(
   workload: Workload,
   prior: Ruleset<L>,
   prior_limits: Limits,
   minimize_limits: Limits,
   fast_match: bool,
+) -> Ruleset<L> {
+  run_workload_report(
+    workload,
+    prior,
+    prior_limits,
+    minimize_limits,
+    fast_match,
+  )
+  .rules
+}
+
+/// `run_workload`, returning what the step did along with the learned rules.
+pub fn run_workload_report<L: SynthLanguage>(
+  workload: Workload,
+  prior: Ruleset<L>,
+  prior_limits: Limits,
+  minimize_limits: Limits,
+  fast_match: bool,
+) -> SynthReport<L> {
+  run_workload_internal(
+    workload,
+    prior,
+    prior_limits,
+    minimize_limits,
+    fast_match,
+    true,
+    None,
+  )
+}
+
+/// `run_workload` with the candidates minimized according to `config`, e.g.,
+/// ordered by a custom `RuleScorer`.
+///
+/// Steps run this way are never checkpointed, since the scorer cannot be
+/// hashed into the step's key: they always run, even when checkpointing is
+/// enabled.
+pub fn run_workload_with<L: SynthLanguage>(
+  workload: Workload,
+  prior: Ruleset<L>,
+  prior_limits: Limits,
+  minimize_limits: Limits,
+  fast_match: bool,
+  config: &MinimizeConfig<L>,
 ) -> Ruleset<L> {
   run_workload_internal(
     workload,
@@ -91,7 +373,9 @@ pub fn run_workload<L: SynthLanguage>(
     minimize_limits,
     fast_match,
     true,
+    Some(config),
   )
+  .rules
 }
 
 /// rule_workload + extend
@@ -109,7 +393,9 @@ pub fn run_workload_extend<L: SynthLanguage>(
     minimize_limits,
     fast_match,
     true,
-  );
+    None,
+  )
+  .rules;
 
   new.extend(prior);
   new
@@ -130,12 +416,40 @@ pub fn run_fast_forwarding<L: SynthLanguage>(
   prior_limits: Limits,
   minimize_limits: Limits,
 ) -> Ruleset<L> {
-  let t = Instant::now();
+  run_fast_forwarding_report(workload, prior, prior_limits, minimize_limits)
+    .rules
+}
+
+/// `run_fast_forwarding`, returning what the step did along with the learned
+/// rules. Growing the e-graph with the exploratory rules counts as
+/// compression.
+pub fn run_fast_forwarding_report<L: SynthLanguage>(
+  workload: Workload,
+  prior: Ruleset<L>,
+  prior_limits: Limits,
+  minimize_limits: Limits,
+) -> SynthReport<L> {
+  let key = checkpoint::dir().map(|_| {
+    checkpoint::Step {
+      kind: "run_fast_forwarding",
+      workload: &workload,
+      prior: &prior,
+      prior_limits,
+      minimize_limits,
+      fast_match: false,
+    }
+    .key()
+  });
+  if let Some(report) = resume(key.as_deref(), &prior) {
+    return report;
+  }
+  let mut times = StageTimes::default();
 
   let eg_init = workload.to_egraph::<L>();
   let num_prior = prior.len();
 
   // Allowed rules: compress e-graph, no candidates
+  let t = Instant::now();
   let (allowed, _) =
     prior.partition(|rule| L::is_allowed_rewrite(&rule.lhs, &rule.rhs));
   let eg_allowed = Scheduler::Compress(prior_limits).run(&eg_init, &allowed);
@@ -144,30 +458,42 @@ pub fn run_fast_forwarding<L: SynthLanguage>(
   let exploratory = L::get_exploratory_rules();
   let eg_denote =
     Scheduler::Simple(prior_limits).run(&eg_allowed, &exploratory);
+  times.compress += t.elapsed();
+  let t = Instant::now();
   let mut candidates = Ruleset::extract_candidates(&eg_allowed, &eg_denote);
+  times.match_ += t.elapsed();
 
   // All rules: compress e-graph, extract candidates
+  let t = Instant::now();
   let mut all_rules = prior.clone();
   all_rules.extend(exploratory);
   let eg_final = Scheduler::Compress(prior_limits).run(&eg_denote, &all_rules);
+  times.compress += t.elapsed();
+  let t = Instant::now();
   candidates.extend(Ruleset::extract_candidates(&eg_denote, &eg_final));
+  times.match_ += t.elapsed();
+  let num_candidates = candidates.len();
 
-  let chosen = candidates
-    .minimize(prior, Scheduler::Compress(minimize_limits))
-    .0;
-  let time = t.elapsed().as_secs_f64();
-
-  println!(
-    "Learned {} bidirectional rewrites ({} total rewrites) in {} using {} prior rewrites",
-    chosen.bidir_len(),
-    chosen.len(),
-    time,
-    num_prior
-  );
-
-  chosen.pretty_print();
-
-  chosen
+  let t = Instant::now();
+  let minimized =
+    candidates.minimize(prior.clone(), Scheduler::Compress(minimize_limits));
+  times.minimize = t.elapsed();
+  report_unverified(&minimized);
+
+  let report = SynthReport {
+    initial: EGraphSize::of(&eg_init),
+    compressed: EGraphSize::of(&eg_final),
+    candidates: num_candidates,
+    prior: num_prior,
+    valid: minimized.chosen.len(),
+    invalid: minimized.invalid.len(),
+    unknown: minimized.unknown.len(),
+    times,
+    rules: minimized.chosen,
+    partial: minimized.partial,
+    from_checkpoint: false,
+  };
+  finish(report, key.as_deref(), &prior)
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -197,11 +523,41 @@ pub fn recursive_rules<L: SynthLanguage>(
   n: usize,
   lang: Lang,
   prior: Ruleset<L>,
+) -> Ruleset<L> {
+  recursive_rules_with(
+    metric,
+    n,
+    lang,
+    prior,
+    Limits::synthesis(),
+    Limits::minimize(),
+    true,
+  )
+}
+
+/// `recursive_rules` with the limits and cvec matching of each size's
+/// `run_workload`.
+pub fn recursive_rules_with<L: SynthLanguage>(
+  metric: Metric,
+  n: usize,
+  lang: Lang,
+  prior: Ruleset<L>,
+  synthesis_limits: Limits,
+  minimize_limits: Limits,
+  fast_match: bool,
 ) -> Ruleset<L> {
   if n < 1 {
     Ruleset::default()
   } else {
-    let mut rec = recursive_rules(metric, n - 1, lang.clone(), prior.clone());
+    let mut rec = recursive_rules_with(
+      metric,
+      n - 1,
+      lang.clone(),
+      prior.clone(),
+      synthesis_limits,
+      minimize_limits,
+      fast_match,
+    );
     let base_lang = if lang.ops.len() == 2 {
       base_lang(2)
     } else {
@@ -221,11 +577,13 @@ pub fn recursive_rules<L: SynthLanguage>(
     let new = run_workload_internal(
       wkld,
       rec.clone(),
-      Limits::synthesis(),
-      Limits::minimize(),
-      true,
+      synthesis_limits,
+      minimize_limits,
+      fast_match,
       allow_empty,
-    );
+      None,
+    )
+    .rules;
     let mut all = new;
     all.extend(rec);
     all
@@ -246,9 +604,13 @@ pub fn base_lang(n: usize) -> Workload {
 #[cfg(test)]
 mod test {
   use crate::{
-    enumo::{Metric, Workload},
-    recipe_utils::{base_lang, iter_metric},
+    Limits, SynthLanguage, ValidationResult,
+    enumo::{Metric, Ruleset, Workload},
+    recipe_utils::{
+      add_counterexample, base_lang, iter_metric, refine_candidates,
+    },
   };
+  use crate::bv4;
 
   #[test]
   fn iter_metric_test() {
@@ -327,4 +689,48 @@ mod test {
       .plug("OP3", &Workload::empty());
     assert_eq!(wkld.force().len(), 188);
   }
+
+  #[test]
+  fn counterexample_refines_cvecs() {
+    use bv4::Bv;
+
+    let lhs = "(* ?a ?a)".parse().unwrap();
+    let rhs = "?a".parse().unwrap();
+    let cex = match Bv::validate(&lhs, &rhs) {
+      ValidationResult::Invalid(Some(cex)) => cex,
+      res => panic!("expected a counterexample, got {:?}", res),
+    };
+    let a = *cex.get("a".into()).unwrap();
+    assert_ne!(a.wrapping_mul(a), a);
+    assert_eq!(cex.lhs, Some(a.wrapping_mul(a)));
+    assert_eq!(cex.rhs, Some(a));
+
+    let mut egraph = Workload::new(["(* a a)", "a"]).to_egraph::<Bv>();
+    let cvec_len = egraph.analysis.cvec_len;
+    add_counterexample(&mut egraph, &cex);
+    assert_eq!(egraph.analysis.cvec_len, cvec_len + 1);
+
+    let var = egraph.lookup_expr(&"a".parse().unwrap()).unwrap();
+    let square = egraph.lookup_expr(&"(* a a)".parse().unwrap()).unwrap();
+    assert_eq!(egraph[var].data.cvec.last().unwrap(), &Some(a));
+    assert_eq!(
+      egraph[square].data.cvec.last().unwrap(),
+      &Some(a.wrapping_mul(a))
+    );
+  }
+
+  #[test]
+  fn refinement_validates_every_candidate() {
+    use bv4::Bv;
+
+    // `(| a (* a a))` and `a` agree on the initial samples of `a`
+    let terms = ["a", "b", "(* a a)", "(| a (* a a))", "(& a b)", "(& b a)"];
+    let mut egraph = Workload::new(terms).to_egraph::<Bv>();
+    let cvec_len = egraph.analysis.cvec_len;
+    assert!(!Ruleset::cvec_match(&egraph).counterexamples().is_empty());
+    let candidates = refine_candidates(&mut egraph, false, Limits::minimize());
+    assert!(egraph.analysis.cvec_len > cvec_len);
+    assert!(candidates.counterexamples().is_empty());
+    assert!(!candidates.is_empty());
+  }
 }
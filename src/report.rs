@@ -0,0 +1,224 @@
+//! What a rule inference step did, for callers that want numbers rather than
+//! console output. The pipeline logs its progress with the `log` crate; an
+//! observer passed to `observe` also receives a `SynthReport` for every step
+//! of the run it observes, including those run by `recursive_rules` and
+//! recipes.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+    time::Duration,
+};
+
+use crate::{enumo::Ruleset, EGraph, SynthAnalysis, SynthLanguage};
+
+/// The outcome of one `run_workload` or `run_fast_forwarding` step.
+#[derive(Debug, Clone)]
+pub struct SynthReport<L: SynthLanguage> {
+    /// The workload's e-graph, before compression with the prior rules.
+    pub initial: EGraphSize,
+    /// The e-graph the candidates were found in.
+    pub compressed: EGraphSize,
+    /// Number of rule candidates, after cvec refinement.
+    pub candidates: usize,
+    /// Number of prior rules.
+    pub prior: usize,
+    /// Candidates found valid and chosen, i.e. `rules.len()`.
+    pub valid: usize,
+    /// Candidates that failed validation during minimization.
+    pub invalid: usize,
+    /// Candidates whose soundness could not be decided. They are left out of
+    /// `rules`.
+    pub unknown: usize,
+    pub times: StageTimes,
+    /// The learned rules, not including the prior rules.
+    pub rules: Ruleset<L>,
+    /// Whether minimization ran out of budget, see `Minimized::partial`.
+    pub partial: bool,
+    /// Whether the rules were read from a checkpoint, see `checkpoint`. The
+    /// other fields are then zero.
+    pub from_checkpoint: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EGraphSize {
+    pub nodes: usize,
+    pub classes: usize,
+}
+
+impl EGraphSize {
+    pub fn of<L: SynthLanguage>(egraph: &EGraph<L, SynthAnalysis>) -> Self {
+        Self {
+            nodes: egraph.total_size(),
+            classes: egraph.number_of_classes(),
+        }
+    }
+}
+
+/// Time spent in each stage of a step.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimes {
+    /// Running the prior (and, for fast-forwarding, exploratory) rules.
+    pub compress: Duration,
+    /// Finding candidates.
+    pub match_: Duration,
+    /// Validating candidates to refine the cvecs. Validation during
+    /// minimization is counted in `minimize`.
+    pub validate: Duration,
+    pub minimize: Duration,
+}
+
+impl StageTimes {
+    pub fn total(&self) -> Duration {
+        self.compress + self.match_ + self.validate + self.minimize
+    }
+}
+
+impl<L: SynthLanguage> SynthReport<L> {
+    /// The report of a step whose rules were read from a checkpoint.
+    pub(crate) fn from_checkpoint(rules: Ruleset<L>) -> Self {
+        Self {
+            initial: EGraphSize::default(),
+            compressed: EGraphSize::default(),
+            candidates: 0,
+            prior: 0,
+            valid: 0,
+            invalid: 0,
+            unknown: 0,
+            times: StageTimes::default(),
+            rules,
+            partial: false,
+            from_checkpoint: true,
+        }
+    }
+}
+
+impl<L: SynthLanguage> Display for SynthReport<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.from_checkpoint {
+            return write!(f, "Loaded {} rewrites from a checkpoint", self.rules.len());
+        }
+        let times = &self.times;
+        if self.partial {
+            write!(f, "Out of budget: ")?;
+        }
+        write!(
+            f,
+            "Learned {} bidirectional rewrites ({} total rewrites) in {:.3}s using {} prior rewrites\n\
+             e-graph: {} nodes, {} classes; compressed: {} nodes, {} classes\n\
+             candidates: {}; valid: {}, invalid: {}, unknown: {}\n\
+             compress {:.3}s, match {:.3}s, validate {:.3}s, minimize {:.3}s",
+            self.rules.bidir_len(),
+            self.rules.len(),
+            times.total().as_secs_f64(),
+            self.prior,
+            self.initial.nodes,
+            self.initial.classes,
+            self.compressed.nodes,
+            self.compressed.classes,
+            self.candidates,
+            self.valid,
+            self.invalid,
+            self.unknown,
+            times.compress.as_secs_f64(),
+            times.match_.as_secs_f64(),
+            times.validate.as_secs_f64(),
+            times.minimize.as_secs_f64(),
+        )
+    }
+}
+
+type Observer<L> = Rc<dyn Fn(&SynthReport<L>)>;
+
+thread_local! {
+    /// The observers of the runs in progress on this thread, innermost last.
+    static OBSERVERS: RefCell<Vec<Box<dyn Any>>> = const { RefCell::new(vec![]) };
+}
+
+/// Removes an observer once its run ends, even by unwinding.
+struct Unobserve;
+
+impl Drop for Unobserve {
+    fn drop(&mut self) {
+        OBSERVERS.with(|observers| observers.borrow_mut().pop());
+    }
+}
+
+/// Run `run`, calling `f` with the report of every step over the domain `L`
+/// that it runs on this thread. Observers of enclosing runs see the reports
+/// too.
+pub fn observe<L: SynthLanguage, R>(
+    f: impl Fn(&SynthReport<L>) + 'static,
+    run: impl FnOnce() -> R,
+) -> R {
+    let observer: Observer<L> = Rc::new(f);
+    OBSERVERS.with(|observers| observers.borrow_mut().push(Box::new(observer)));
+    let _unobserve = Unobserve;
+    run()
+}
+
+/// Log a step's report and pass it to the observers of the domain.
+pub(crate) fn notify<L: SynthLanguage>(report: &SynthReport<L>) {
+    log::info!("{}", report);
+    for line in report.rules.pretty_strings() {
+        log::debug!("{}", line);
+    }
+    // The observers run without the registry borrowed, so they may
+    // themselves observe.
+    let observers: Vec<Observer<L>> = OBSERVERS.with(|observers| {
+        observers
+            .borrow()
+            .iter()
+            .filter_map(|o| o.downcast_ref::<Observer<L>>().cloned())
+            .collect()
+    });
+    for observer in observers {
+        observer(report);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{enumo::Workload, recipe_utils::run_workload_report, Limits};
+    use crate::bv4;
+
+    #[test]
+    fn report_counts() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let sink = seen.clone();
+        let workload = Workload::new(["(& a b)", "(& b a)", "(| a b)", "(| b a)", "(& a a)", "a"]);
+        let run = || {
+            run_workload_report(
+                workload.clone(),
+                Ruleset::default(),
+                Limits::synthesis(),
+                Limits::minimize(),
+                true,
+            )
+        };
+        let report: SynthReport<bv4::Bv> = observe(
+            move |report: &SynthReport<bv4::Bv>| sink.borrow_mut().push(report.rules.len()),
+            run,
+        );
+        assert_eq!(*seen.borrow(), vec![report.rules.len()]);
+        // Steps outside the observed run are not reported
+        run();
+        assert_eq!(seen.borrow().len(), 1);
+
+        assert!(!report.from_checkpoint);
+        assert!(!report.partial);
+        assert!(report.initial.nodes > 0);
+        assert!(report.compressed.classes <= report.initial.classes);
+        assert!(report.candidates >= report.valid);
+        assert_eq!(report.valid, report.rules.len());
+        assert_eq!(report.prior, 0);
+        assert!(report.rules.iter().all(|rule| rule.is_valid()));
+        assert!(report.to_string().starts_with(&format!(
+            "Learned {} bidirectional rewrites",
+            report.rules.bidir_len()
+        )));
+    }
+}
@@ -0,0 +1,327 @@
+use std::cell::Cell;
+
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+
+use crate::*;
+
+/// Default time limit for a z3 query, in milliseconds.
+pub const DEFAULT_Z3_TIMEOUT_MS: u32 = 1000;
+
+thread_local! {
+    static Z3_CONTEXT: z3::Context = z3::Context::new(&z3::Config::new());
+    static Z3_TIMEOUT_MS: Cell<u32> = const { Cell::new(DEFAULT_Z3_TIMEOUT_MS) };
+}
+
+/// Run `f` with this thread's z3 context.
+///
+/// Creating a context is expensive, so validators should share one context
+/// per thread instead of making a new one for every query, and create their
+/// solvers with `z3_solver`.
+pub fn with_z3_context<R>(f: impl FnOnce(&z3::Context) -> R) -> R {
+    Z3_CONTEXT.with(f)
+}
+
+/// A solver that gives up after this thread's current timeout, see
+/// `with_z3_timeout`.
+pub fn z3_solver(ctx: &z3::Context) -> z3::Solver<'_> {
+    let solver = z3::Solver::new(ctx);
+    let mut params = z3::Params::new(ctx);
+    params.set_u32("timeout", Z3_TIMEOUT_MS.with(Cell::get));
+    solver.set_params(&params);
+    solver
+}
+
+/// Run `f` with the z3 timeout of this thread set to `ms` milliseconds.
+pub fn with_z3_timeout<R>(ms: u32, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreTimeout(Z3_TIMEOUT_MS.with(|t| t.replace(ms)));
+    f()
+}
+
+/// Puts back the previous timeout when `with_z3_timeout` returns or unwinds.
+struct RestoreTimeout(u32);
+
+impl Drop for RestoreTimeout {
+    fn drop(&mut self) {
+        Z3_TIMEOUT_MS.with(|t| t.set(self.0));
+    }
+}
+
+/// How to retry rules whose validation was `Unknown`.
+pub struct Escalation<L: SynthLanguage> {
+    /// z3 timeouts to retry with, in milliseconds, in increasing order.
+    pub timeouts_ms: Vec<u32>,
+    /// Random testing to fall back on if the solver still gives up. Testing
+    /// can only refute a rule, so rules that pass remain unverified.
+    pub fuzz: Option<FuzzConfig<L>>,
+}
+
+impl<L: SynthLanguage> Default for Escalation<L> {
+    fn default() -> Self {
+        Self {
+            timeouts_ms: vec![5_000, 20_000],
+            fuzz: L::fuzz_config(),
+        }
+    }
+}
+
+impl<L: SynthLanguage> Clone for Escalation<L> {
+    fn clone(&self) -> Self {
+        Self {
+            timeouts_ms: self.timeouts_ms.clone(),
+            fuzz: self.fuzz.clone(),
+        }
+    }
+}
+
+/// Configuration for `fuzz_validate`.
+pub struct FuzzConfig<L: SynthLanguage> {
+    /// Number of variable assignments to try.
+    pub samples: usize,
+    /// Seed for the random number generator, so that results are reproducible.
+    pub seed: u64,
+    /// Draws a value for a single variable.
+    pub sampler: fn(&mut Pcg64) -> L::Constant,
+}
+
+impl<L: SynthLanguage> FuzzConfig<L> {
+    pub fn new(sampler: fn(&mut Pcg64) -> L::Constant) -> Self {
+        Self {
+            samples: 1000,
+            seed: 0,
+            sampler,
+        }
+    }
+}
+
+impl<L: SynthLanguage> Clone for FuzzConfig<L> {
+    fn clone(&self) -> Self {
+        Self {
+            samples: self.samples,
+            seed: self.seed,
+            sampler: self.sampler,
+        }
+    }
+}
+
+/// Check a rule by random testing.
+///
+/// Both sides are evaluated with `SynthLanguage::eval` on `config.samples`
+/// random variable assignments. Returns `Invalid` with the first assignment
+/// on which they disagree. A side that is undefined only disagrees with a
+/// defined one. Testing cannot prove a rule sound, so `Valid` only means no
+/// mismatch was found; if neither side was ever defined the result is
+/// `Unknown`.
+pub fn fuzz_validate<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    config: &FuzzConfig<L>,
+) -> ValidationResult<L> {
+    let vars = pattern_vars(lhs, rhs);
+    let lexpr = L::instantiate(lhs);
+    let rexpr = L::instantiate(rhs);
+    let mut rng = Pcg64::seed_from_u64(config.seed);
+    let mut tested = false;
+    for _ in 0..config.samples {
+        let assignment = vars
+            .iter()
+            .map(|v| (*v, (config.sampler)(&mut rng)))
+            .collect();
+        let mut cex = Counterexample {
+            assignment,
+            lhs: None,
+            rhs: None,
+        };
+        cex.lhs = cex.eval(&lexpr);
+        cex.rhs = cex.eval(&rexpr);
+        if cex.lhs != cex.rhs {
+            return ValidationResult::Invalid(Some(cex));
+        }
+        tested |= cex.lhs.is_some();
+    }
+    if tested {
+        ValidationResult::Valid
+    } else {
+        ValidationResult::Unknown
+    }
+}
+
+/// A constant type with finitely many values.
+pub trait FiniteDomain: Sized {
+    /// Every value of the type, or `None` if there are too many to list.
+    fn all_values() -> Option<Vec<Self>>;
+}
+
+impl FiniteDomain for bool {
+    fn all_values() -> Option<Vec<Self>> {
+        Some(vec![false, true])
+    }
+}
+
+/// Check a rule by evaluating both sides on every variable assignment.
+///
+/// Unlike `fuzz_validate`, the result is definitive: `Invalid` with the first
+/// assignment on which the sides disagree, and `Valid` otherwise. The number
+/// of assignments is exponential in the number of variables, so this is only
+/// practical for small domains. `Unknown` if the domain cannot list its
+/// values.
+pub fn exhaustive_validate<L>(lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L>
+where
+    L: SynthLanguage,
+    L::Constant: FiniteDomain,
+{
+    match L::Constant::all_values() {
+        Some(values) => exhaustive_validate_with(lhs, rhs, |_| values.clone()),
+        None => ValidationResult::Unknown,
+    }
+}
+
+/// `exhaustive_validate` with the values of each variable given by `values`,
+/// e.g., for domains with several sorts whose variables range over only some
+/// of the constants. `Unknown` if a variable has no values.
+pub fn exhaustive_validate_with<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    values: impl Fn(Symbol) -> Vec<L::Constant>,
+) -> ValidationResult<L> {
+    let vars = pattern_vars(lhs, rhs);
+    let values: Vec<Vec<L::Constant>> = vars.iter().map(|v| values(*v)).collect();
+    let lexpr = L::instantiate(lhs);
+    let rexpr = L::instantiate(rhs);
+    // Nothing to check the rule on
+    if values.iter().any(|vals| vals.is_empty()) {
+        return ValidationResult::Unknown;
+    }
+
+    // Odometer over the indices of each variable's value
+    let mut indices = vec![0; vars.len()];
+    loop {
+        let assignment = vars
+            .iter()
+            .zip(&indices)
+            .zip(&values)
+            .map(|((v, i), vals)| (*v, vals[*i].clone()))
+            .collect();
+        let mut cex = Counterexample {
+            assignment,
+            lhs: None,
+            rhs: None,
+        };
+        cex.lhs = cex.eval(&lexpr);
+        cex.rhs = cex.eval(&rexpr);
+        if cex.lhs != cex.rhs {
+            return ValidationResult::Invalid(Some(cex));
+        }
+
+        let mut pos = 0;
+        while pos < indices.len() && indices[pos] + 1 == values[pos].len() {
+            indices[pos] = 0;
+            pos += 1;
+        }
+        if pos == indices.len() {
+            return ValidationResult::Valid;
+        }
+        indices[pos] += 1;
+    }
+}
+
+/// The variables of a rule, in order of first occurrence.
+fn pattern_vars<L: SynthLanguage>(lhs: &Pattern<L>, rhs: &Pattern<L>) -> Vec<Symbol> {
+    let mut vars: Vec<Symbol> = vec![];
+    for var in lhs.vars().into_iter().chain(rhs.vars()) {
+        let sym = Symbol::from(&var.to_string()[1..]);
+        if !vars.contains(&sym) {
+            vars.push(sym);
+        }
+    }
+    vars
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bv4;
+
+    fn config() -> FuzzConfig<bv4::Bv> {
+        FuzzConfig::new(rand::Rng::random)
+    }
+
+    #[test]
+    fn fuzz_finds_mismatch() {
+        let lhs = "(+ ?a ?b)".parse().unwrap();
+        let rhs = "(| ?a ?b)".parse().unwrap();
+        match fuzz_validate(&lhs, &rhs, &config()) {
+            ValidationResult::Invalid(Some(cex)) => {
+                let a = *cex.get("a".into()).unwrap();
+                let b = *cex.get("b".into()).unwrap();
+                assert_eq!(cex.lhs, Some(a.wrapping_add(b)));
+                assert_eq!(cex.rhs, Some(a | b));
+                assert_ne!(cex.lhs, cex.rhs);
+            }
+            res => panic!("expected a counterexample, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn fuzz_accepts_sound_rule() {
+        let lhs = "(+ ?a ?b)".parse().unwrap();
+        let rhs = "(+ ?b ?a)".parse().unwrap();
+        assert!(matches!(
+            fuzz_validate(&lhs, &rhs, &config()),
+            ValidationResult::Valid
+        ));
+    }
+
+    #[test]
+    fn exhaustive_checks_every_assignment() {
+        let valid = |l: &str, r: &str| {
+            let res = exhaustive_validate::<bv4::Bv>(&l.parse().unwrap(), &r.parse().unwrap());
+            matches!(res, ValidationResult::Valid)
+        };
+        assert!(valid("(- (- ?a))", "?a"));
+        assert!(valid("(* ?a (+ ?b ?c))", "(+ (* ?a ?b) (* ?a ?c))"));
+        assert!(valid("(+ 1 1)", "2"));
+        assert!(!valid("(& ?a 8)", "0"));
+        assert!(!valid("(<< ?a ?b)", "(* ?a 2)"));
+    }
+
+    #[test]
+    fn exhaustive_with_restricted_values() {
+        // Sound when `?a` is 0 or 1, but not for every bitvector
+        let lhs = "(& ?a 1)".parse().unwrap();
+        let rhs = "?a".parse().unwrap();
+        let bits = |_| vec![BV::from(0), BV::from(1)];
+        assert!(matches!(
+            exhaustive_validate_with::<bv4::Bv>(&lhs, &rhs, bits),
+            ValidationResult::Valid
+        ));
+        assert!(matches!(
+            exhaustive_validate::<bv4::Bv>(&lhs, &rhs),
+            ValidationResult::Invalid(_)
+        ));
+        // No values certify nothing
+        assert!(matches!(
+            exhaustive_validate_with::<bv4::Bv>(&lhs, &rhs, |_| vec![]),
+            ValidationResult::Unknown
+        ));
+    }
+
+    #[test]
+    fn timeout_is_restored_after_panic() {
+        let before = Z3_TIMEOUT_MS.with(Cell::get);
+        let res = std::panic::catch_unwind(|| with_z3_timeout(before + 1, || panic!("give up")));
+        assert!(res.is_err());
+        assert_eq!(Z3_TIMEOUT_MS.with(Cell::get), before);
+    }
+
+    #[test]
+    fn fuzz_is_reproducible() {
+        let lhs = "(* ?a ?a)".parse().unwrap();
+        let rhs = "?a".parse().unwrap();
+        let cex = |seed| match fuzz_validate(&lhs, &rhs, &FuzzConfig { seed, ..config() }) {
+            ValidationResult::Invalid(Some(cex)) => cex,
+            res => panic!("expected a counterexample, got {:?}", res),
+        };
+        assert_eq!(cex(7), cex(7));
+    }
+}
@@ -0,0 +1,190 @@
+//! Process-wide cache of validation results, one per domain.
+//! Keys are alpha-normalized rules (see `Rule::validate`), so a candidate is
+//! never validated twice in a process, no matter how its variables are named.
+
+use std::{
+    any::{Any, TypeId},
+    fs,
+    io::{self, Write},
+    sync::{LazyLock, Mutex},
+};
+
+use crate::{HashMap, SynthLanguage, ValidationResult};
+
+/// Validation results of one domain, keyed by rule.
+pub struct ValidationCache<L: SynthLanguage> {
+    results: HashMap<String, ValidationResult<L>>,
+}
+
+impl<L: SynthLanguage> Default for ValidationCache<L> {
+    fn default() -> Self {
+        Self {
+            results: Default::default(),
+        }
+    }
+}
+
+impl<L: SynthLanguage> ValidationCache<L> {
+    pub fn get(&self, key: &str) -> Option<&ValidationResult<L>> {
+        self.results.get(key)
+    }
+
+    /// Record the result for `key`, replacing any cached result.
+    pub fn insert(&mut self, key: &str, result: ValidationResult<L>) {
+        self.results.insert(key.to_string(), result);
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    /// Write the `Valid` results to a file, one rule per line. `Invalid`
+    /// results are not saved, since their counterexamples cannot be, and
+    /// neither are `Unknown` ones.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        write_valid(path, self.valid())
+    }
+
+    /// Add the results saved by `save` to the cache, keeping results that are
+    /// already cached. Returns the number of results read.
+    pub fn load(&mut self, path: &str) -> io::Result<usize> {
+        let keys = read_valid(path)?;
+        let n = keys.len();
+        self.add_valid(keys);
+        Ok(n)
+    }
+
+    fn valid(&self) -> Vec<String> {
+        self.results
+            .iter()
+            .filter(|(_, result)| matches!(result, ValidationResult::Valid))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn add_valid(&mut self, keys: Vec<String>) {
+        for key in keys {
+            self.results.entry(key).or_insert(ValidationResult::Valid);
+        }
+    }
+}
+
+fn write_valid(path: &str, mut keys: Vec<String>) -> io::Result<()> {
+    keys.sort();
+    let mut file = fs::File::create(path)?;
+    for key in keys {
+        writeln!(file, "valid\t{}", key)?;
+    }
+    Ok(())
+}
+
+fn read_valid(path: &str) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    let mut keys = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        match line.split_once('\t') {
+            Some(("valid", key)) => keys.push(key.to_string()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: malformed cache entry", path, i + 1),
+                ))
+            }
+        }
+    }
+    Ok(keys)
+}
+
+static CACHES: LazyLock<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>> =
+    LazyLock::new(Default::default);
+
+fn with_cache<L: SynthLanguage, R>(f: impl FnOnce(&mut ValidationCache<L>) -> R) -> R {
+    let mut caches = CACHES.lock().unwrap();
+    let cache = caches
+        .entry(TypeId::of::<L>())
+        .or_insert_with(|| Box::new(ValidationCache::<L>::default()));
+    f(cache.downcast_mut().unwrap())
+}
+
+/// Look up the result for `key`, running `validate` on a miss.
+/// The cache is not locked while `validate` runs, so validations can happen
+/// in parallel.
+pub fn get_or_validate<L: SynthLanguage>(
+    key: &str,
+    validate: impl FnOnce() -> ValidationResult<L>,
+) -> ValidationResult<L> {
+    if let Some(result) = with_cache(|cache: &mut ValidationCache<L>| cache.get(key).cloned()) {
+        return result;
+    }
+    let result = validate();
+    with_cache(|cache: &mut ValidationCache<L>| cache.insert(key, result.clone()));
+    result
+}
+
+/// Record the result for `key`, replacing any cached result.
+pub fn insert<L: SynthLanguage>(key: &str, result: ValidationResult<L>) {
+    with_cache(|cache: &mut ValidationCache<L>| cache.insert(key, result));
+}
+
+/// Number of cached results for the domain.
+pub fn len<L: SynthLanguage>() -> usize {
+    with_cache(|cache: &mut ValidationCache<L>| cache.len())
+}
+
+/// Forget all cached results for the domain.
+pub fn clear<L: SynthLanguage>() {
+    with_cache(|cache: &mut ValidationCache<L>| cache.clear())
+}
+
+/// `ValidationCache::save` for the domain's process-wide cache.
+pub fn save<L: SynthLanguage>(path: &str) -> io::Result<()> {
+    write_valid(path, with_cache(|cache: &mut ValidationCache<L>| cache.valid()))
+}
+
+/// `ValidationCache::load` for the domain's process-wide cache.
+pub fn load<L: SynthLanguage>(path: &str) -> io::Result<usize> {
+    let keys = read_valid(path)?;
+    let n = keys.len();
+    with_cache(|cache: &mut ValidationCache<L>| cache.add_valid(keys));
+    Ok(n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use egg::SymbolLang;
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!(
+            "enumo_validation_cache_save_and_load_{}",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let mut cache = ValidationCache::<SymbolLang>::default();
+        cache.insert("(f ?a) ==> ?a", ValidationResult::Invalid(None));
+        cache.insert("(g ?a) ==> (g ?a)", ValidationResult::Valid);
+        cache.insert("(h ?a) ==> ?a", ValidationResult::Unknown);
+        cache.save(path).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert_eq!(contents, "valid\t(g ?a) ==> (g ?a)\n");
+
+        let mut loaded = ValidationCache::<SymbolLang>::default();
+        assert_eq!(loaded.load(path).unwrap(), 1);
+        assert!(matches!(
+            loaded.get("(g ?a) ==> (g ?a)"),
+            Some(ValidationResult::Valid)
+        ));
+        assert!(loaded.get("(f ?a) ==> ?a").is_none());
+        fs::remove_file(path).unwrap();
+    }
+}
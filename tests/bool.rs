@@ -1,4 +1,5 @@
 use ::enumo::{enumo::Scheduler, *};
+use rand::Rng;
 use std::ops::*;
 #[path = "./recipes/bool.rs"]
 pub mod bool;
@@ -17,6 +18,7 @@ egg::define_language! {
 
 impl SynthLanguage for Bool {
     type Constant = bool;
+    const NAME: &'static str = "bool";
 
     fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
     where
@@ -105,8 +107,17 @@ impl SynthLanguage for Bool {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        exhaustive_validate(lhs, rhs)
+    }
+
+    // Used by `Escalation`, and as a check of `fuzz_validate` against
+    // `exhaustive_validate`
+    fn fuzz_config() -> Option<FuzzConfig<Self>> {
+        Some(FuzzConfig {
+            samples: 64,
+            ..FuzzConfig::new(|rng| rng.random())
+        })
     }
 
     fn is_constant(&self) -> bool {
@@ -123,11 +134,29 @@ mod test {
     use super::*;
     use crate::bool::bool_rules;
     use ::enumo::{
-        enumo::{Filter, Metric, Ruleset, Workload},
+        enumo::{Filter, Metric, RuleFile, Ruleset, Workload},
+        recipe::{Recipe, StepKind},
         recipe_utils::{base_lang, iter_metric, run_workload},
     };
     use std::time::Instant;
 
+    #[test]
+    fn fuzz_agrees_with_exhaustive() {
+        let config = Bool::fuzz_config().unwrap();
+        for (lhs, rhs) in [
+            ("(& ?a ?b)", "(& ?b ?a)"),
+            ("(-> ?a ?b)", "(| (~ ?a) ?b)"),
+            ("(| ?a ?b)", "(& ?a ?b)"),
+            ("(^ ?a ?b)", "(| ?a ?b)"),
+        ] {
+            let (lhs, rhs) = (lhs.parse().unwrap(), rhs.parse().unwrap());
+            assert_eq!(
+                matches!(fuzz_validate(&lhs, &rhs, &config), ValidationResult::Valid),
+                matches!(Bool::validate(&lhs, &rhs), ValidationResult::Valid),
+            );
+        }
+    }
+
     fn iter_bool(n: usize) -> Workload {
         iter_metric(base_lang(2), "EXPR", Metric::Atoms, n)
             .filter(Filter::Contains("VAR".parse().unwrap()))
@@ -145,25 +174,25 @@ mod test {
 
         let scheduler = Scheduler::Compress(Limits::synthesis());
 
-        let mut egraph = scheduler.run(&atoms3.to_egraph(), &all_rules);
-        let mut candidates = Ruleset::cvec_match(&mut egraph);
-        let rules3 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let egraph = scheduler.run(&atoms3.to_egraph(), &all_rules);
+        let mut candidates = Ruleset::cvec_match(&egraph);
+        let rules3 = candidates.minimize(all_rules.clone(), scheduler).chosen;
         all_rules.extend(rules3);
 
         let atoms4 = iter_bool(4);
         assert_eq!(atoms4.force().len(), 348);
 
-        let mut egraph = scheduler.run(&atoms4.to_egraph(), &all_rules);
-        candidates = Ruleset::cvec_match(&mut egraph);
-        let rules4 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let egraph = scheduler.run(&atoms4.to_egraph(), &all_rules);
+        candidates = Ruleset::cvec_match(&egraph);
+        let rules4 = candidates.minimize(all_rules.clone(), scheduler).chosen;
         all_rules.extend(rules4);
 
         let atoms5 = iter_bool(5);
         assert_eq!(atoms5.force().len(), 4599);
 
-        let mut egraph = scheduler.run(&atoms5.to_egraph(), &all_rules);
-        candidates = Ruleset::cvec_match(&mut egraph);
-        let rules5 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let egraph = scheduler.run(&atoms5.to_egraph(), &all_rules);
+        candidates = Ruleset::cvec_match(&egraph);
+        let rules5 = candidates.minimize(all_rules.clone(), scheduler).chosen;
         all_rules.extend(rules5);
 
         let expected: Ruleset<Bool> = Ruleset::new(&[
@@ -289,11 +318,31 @@ mod test {
         let start = Instant::now();
         let rules = bool_rules();
         let duration = start.elapsed();
-        let baseline = Ruleset::<_>::from_file("baseline/bool.rules");
+        let baseline = Ruleset::<_>::from_file("baseline/bool.rules").unwrap();
 
         logger::write_baseline(&rules, "bool", &baseline, "oopsla", duration);
     }
 
+    #[test]
+    fn recipe_file() {
+        let recipe = Recipe::from_file("tests/recipes/bool.recipe").unwrap();
+        assert_eq!(recipe.steps.len(), 2);
+        let a7_canon = iter_metric(base_lang(2), "EXPR", Metric::Atoms, 7)
+            .plug("VAL", &Workload::empty())
+            .plug("VAR", &Workload::new(["a", "b", "c"]))
+            .plug("OP1", &Workload::new(["~"]))
+            .plug("OP2", &Workload::new(["&", "|", "^"]))
+            .filter(Filter::Canon(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+            ]));
+        match &recipe.steps[1].kind {
+            StepKind::Run(workload) => assert_eq!(workload.force(), a7_canon.force()),
+            kind => panic!("expected a run step, found {:?}", kind),
+        }
+    }
+
     #[test]
     fn round_trip_to_file() {
         let rules: Ruleset<Bool> = Ruleset::new(&[
@@ -304,26 +353,43 @@ mod test {
             "?a ==> (~ (~ ?a))",
         ]);
 
-        rules.to_file("out.txt");
+        rules.to_file("out.txt").unwrap();
 
-        let read: Ruleset<Bool> = Ruleset::from_file("out.txt");
+        let read: Ruleset<Bool> = Ruleset::from_file("out.txt").unwrap();
 
         assert_eq!(rules, read)
     }
 
+    #[test]
+    fn read_plain_rule_file() {
+        let plain: Ruleset<Bool> = Ruleset::from_file("baseline/bool.rules").unwrap();
+        let file: RuleFile<Bool> = RuleFile::from_file("baseline/bool.rules").unwrap();
+        assert_eq!(file.ruleset(), plain);
+
+        let file = RuleFile::from(&plain);
+        let path = std::env::temp_dir().join("bool-structured.rules");
+        let path = path.to_str().unwrap();
+        file.to_file(path).unwrap();
+        let read: RuleFile<Bool> = RuleFile::from_file(path).unwrap();
+        assert_eq!(read.ruleset(), plain);
+        assert!(read.entries.iter().any(|entry| entry.bidirectional));
+    }
+
     #[test]
     fn derive_rules() {
         let limits = Limits {
             iter: 4,
             node: 1000000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
         let three: Ruleset<Bool> =
             run_workload(iter_bool(3), Ruleset::default(), limits, limits, false);
-        three.to_file("three.txt");
+        three.to_file("three.txt").unwrap();
 
         let four = run_workload(iter_bool(4), Ruleset::default(), limits, limits, false);
-        four.to_file("four.txt");
+        four.to_file("four.txt").unwrap();
 
         let (can, cannot) = three.derive(
             DeriveType::LhsAndRhs,
@@ -332,9 +398,11 @@ mod test {
                 iter: 10,
                 node: 1000000,
                 match_: 1000,
+                time: None,
+                max_egraph_nodes: None,
             },
         );
-        assert!(can.len() > 0);
-        assert!(cannot.len() > 0);
+        assert!(!can.is_empty());
+        assert!(!cannot.is_empty());
     }
 }
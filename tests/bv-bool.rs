@@ -1,4 +1,4 @@
-use egg::Subst;
+use egg::{ENodeOrVar, Language, Subst};
 use ::enumo::{
     enumo::{Rule, Ruleset},
     *,
@@ -37,6 +37,7 @@ fn is_bv_str(s: &'static str) -> impl Fn(&mut EGraph<BvBool, SynthAnalysis>, Id,
 
 impl SynthLanguage for BvBool {
     type Constant = BV<2>;
+    const NAME: &'static str = "bv-bool";
 
     fn is_fast_forwarding() -> bool {
         true
@@ -58,6 +59,7 @@ impl SynthLanguage for BvBool {
             name: "def-bv".into(),
             lhs: "?a".parse().unwrap(),
             rhs: "(bv (first ?a) (second ?a))".parse().unwrap(),
+            cond: None,
             rewrite: egg::rewrite!("def-bv"; "?a" => "(bv (first ?a) (second ?a))" if is_bv_str("?a")),
         });
         rules
@@ -76,12 +78,32 @@ impl SynthLanguage for BvBool {
         )
     }
 
-    // No eval needed for rule lifting
-    fn eval<'a, F>(&'a self, _cvec_len: usize, _get_cvec: F) -> CVec<Self>
+    // Rule lifting doesn't use cvecs, this is only used for validation.
+    // Booleans are represented by the bitvectors 0 and 1.
+    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
     where
         F: FnMut(&'a Id) -> &'a CVec<Self>,
     {
-        vec![]
+        let bit = |b: bool| BV::<2>::from(b as u128);
+        match self {
+            BvBool::Not(a) => map!(get_cvec, a => Some(bit(a.0 == 0))),
+            BvBool::And([a, b]) => map!(get_cvec, a, b => Some(*a & *b)),
+            BvBool::Or([a, b]) => map!(get_cvec, a, b => Some(*a | *b)),
+            BvBool::Xor([a, b]) => map!(get_cvec, a, b => Some(*a ^ *b)),
+            BvBool::Implies([a, b]) => map!(get_cvec, a, b => Some(bit(a.0 == 0 || b.0 == 1))),
+            BvBool::Lit(b) => vec![Some(bit(*b)); cvec_len],
+
+            BvBool::Band([a, b]) => map!(get_cvec, a, b => Some(*a & *b)),
+            BvBool::Bor([a, b]) => map!(get_cvec, a, b => Some(*a | *b)),
+            BvBool::Bxor([a, b]) => map!(get_cvec, a, b => Some(*a ^ *b)),
+            BvBool::Bnot(a) => map!(get_cvec, a => Some(a.not())),
+            BvBool::Num(n) => vec![Some(*n); cvec_len],
+            BvBool::Var(_) => vec![],
+
+            BvBool::Make([a, b]) => map!(get_cvec, a, b => Some(BV::from(a.0 << 1 | b.0))),
+            BvBool::First(a) => map!(get_cvec, a => Some(bit(a.0 & 2 != 0))),
+            BvBool::Second(a) => map!(get_cvec, a => Some(bit(a.0 & 1 != 0))),
+        }
     }
 
     fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
@@ -150,7 +172,66 @@ impl SynthLanguage for BvBool {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
+    // Variables in boolean positions only range over 0 and 1
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        let mut bools = vec![];
+        bool_vars(lhs, &mut bools);
+        bool_vars(rhs, &mut bools);
+        exhaustive_validate_with(lhs, rhs, |var| {
+            let values: &[u128] = if bools.contains(&var) {
+                &[0, 1]
+            } else {
+                &[0, 1, 2, 3]
+            };
+            values.iter().map(|v| BV::from(*v)).collect()
+        })
+    }
+}
+
+/// The variables of `pat` that are arguments of boolean operators or of `bv`.
+fn bool_vars(pat: &Pattern<BvBool>, vars: &mut Vec<Symbol>) {
+    let ast = pat.ast.as_ref();
+    for node in ast {
+        let ENodeOrVar::ENode(node) = node else {
+            continue;
+        };
+        let boolean = matches!(
+            node,
+            BvBool::Not(_)
+                | BvBool::And(_)
+                | BvBool::Or(_)
+                | BvBool::Xor(_)
+                | BvBool::Implies(_)
+                | BvBool::Make(_)
+        );
+        if !boolean {
+            continue;
+        }
+        for child in node.children() {
+            if let ENodeOrVar::Var(v) = &ast[usize::from(*child)] {
+                vars.push(Symbol::from(&v.to_string()[1..]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn valid(lhs: &str, rhs: &str) -> bool {
+        matches!(
+            BvBool::validate(&lhs.parse().unwrap(), &rhs.parse().unwrap()),
+            ValidationResult::Valid
+        )
+    }
+
+    #[test]
+    fn boolean_variables_are_bits() {
+        assert!(valid("(~ (~ ?a))", "?a"));
+        assert!(valid("(| ?a (~ ?a))", "true"));
+        assert!(valid("(first (bv ?a ?b))", "?a"));
+        assert!(valid("(not (not ?a))", "?a"));
+        assert!(!valid("(& ?a ?b)", "?a"));
     }
 }
@@ -19,12 +19,12 @@ impl Bv {
         let t = Instant::now();
 
         let egraph = workload.to_egraph::<Self>();
-        let mut compressed = Scheduler::Compress(limits).run(&egraph, &prior);
+        let compressed = Scheduler::Compress(limits).run(&egraph, &prior);
 
-        let mut candidates = Ruleset::cvec_match(&mut compressed);
+        let mut candidates = Ruleset::cvec_match(&compressed);
 
         let num_prior = prior.len();
-        let chosen = candidates.minimize(prior, Scheduler::Compress(limits)).0;
+        let chosen = candidates.minimize(prior, Scheduler::Compress(limits)).chosen;
         let time = t.elapsed().as_secs_f64();
 
         println!(
@@ -35,7 +35,7 @@ impl Bv {
             num_prior
         );
 
-        chosen.pretty_print();
+        println!("{}", chosen);
 
         chosen
     }
@@ -106,7 +106,7 @@ pub mod test {
         let start = Instant::now();
         let rules = bv32_rules();
         let duration = start.elapsed();
-        let baseline = Ruleset::<_>::from_file("baseline/bv32.rules");
+        let baseline = Ruleset::<_>::from_file("baseline/bv32.rules").unwrap();
 
         logger::write_baseline(&rules, "bv32", &baseline, "oopsla", duration);
     }
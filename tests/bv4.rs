@@ -28,14 +28,14 @@ pub mod test {
         let start = Instant::now();
         let rules = bv4_fancy_rules();
         let duration = start.elapsed();
-        let baseline = Ruleset::<bv4_fancy::Bv>::from_file("baseline/bv4.rules");
+        let baseline = Ruleset::<bv4_fancy::Bv>::from_file("baseline/bv4.rules").unwrap();
 
         logger::write_baseline(&rules, "bv4_fancy", &baseline, "oopsla", duration);
 
         let start = Instant::now();
         let rules = bv4_rules();
         let duration = start.elapsed();
-        let baseline = Ruleset::<bv4_base::Bv>::from_file("baseline/bv4.rules");
+        let baseline = Ruleset::<bv4_base::Bv>::from_file("baseline/bv4.rules").unwrap();
 
         logger::write_baseline(&rules, "bv4_base", &baseline, "oopsla", duration);
     }
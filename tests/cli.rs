@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Whether enumo succeeded, its output and its log.
+fn enumo(args: &[&str]) -> (bool, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_enumo"))
+        .args(args)
+        .output()
+        .expect("Failed to run enumo");
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// An empty directory for the files of the test `name`. Tests run in
+/// parallel, and several runs of the suite may share the temp dir.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("enumo-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn rule_file(dir: &Path, name: &str, rules: &str) -> String {
+    let path = dir.join(name);
+    fs::write(&path, rules).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn validate_rule_file() {
+    let dir = test_dir("validate");
+    let file = rule_file(&dir, "validate.rules", "(+ ?a 0) <=> ?a\n(+ ?a 1) ==> ?a\n");
+    let (ok, out, _) = enumo(&["--domain", "bv4", "validate", &file, "--update"]);
+    assert!(!ok);
+    assert_eq!(out, "Valid\t(+ ?a 0) ==> ?a\nInvalid\t(+ ?a 1) ==> ?a\n");
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "(+ ?a 0) <=> ?a ; status=valid\n(+ ?a 1) ==> ?a ; status=invalid\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_files() {
+    let dir = test_dir("missing");
+    let missing = dir.join("missing.rules");
+    let missing = missing.to_str().unwrap();
+    let rules = rule_file(&dir, "present.rules", "(+ ?a 0) ==> ?a\n");
+    let unwritable = "/nonexistent/out.rules";
+    for (args, path) in [
+        (vec!["validate", missing], missing),
+        (vec!["diff", &rules, missing], missing),
+        (
+            vec!["convert", "--to", "rules", missing, unwritable],
+            missing,
+        ),
+        (vec!["synth", "--prior", missing], missing),
+        (vec!["synth", "--recipe", missing], missing),
+        (
+            vec!["convert", "--to", "rules", &rules, unwritable],
+            unwritable,
+        ),
+    ] {
+        let args: Vec<&str> = ["-d", "bv4"].into_iter().chain(args).collect();
+        let (ok, _, log) = enumo(&args);
+        assert!(!ok, "{:?}", args);
+        assert!(log.starts_with(path), "{:?}: {}", args, log);
+        assert!(!log.contains("panicked"), "{:?}: {}", args, log);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_and_convert() {
+    let dir = test_dir("diff");
+    let old = rule_file(&dir, "old.rules", "(+ ?a 0) <=> ?a\n(+ ?a ?b) ==> (+ ?b ?a)\n");
+    let new = rule_file(&dir, "new.rules", "(+ 0 ?a) ==> ?a\n(+ ?a ?b) ==> (+ ?b ?a)\n");
+    let (ok, out, _) = enumo(&["-d", "bv4", "diff", &old, &new]);
+    assert!(ok);
+    assert!(out.contains("+ (+ 0 ?a) ==> ?a  (derivable)"));
+
+    let smt = dir.join("smt");
+    let (ok, _, _) = enumo(&[
+        "-d",
+        "bv4",
+        "convert",
+        &new,
+        smt.to_str().unwrap(),
+        "--to",
+        "smtlib",
+    ]);
+    assert!(ok);
+    let script = fs::read_to_string(smt.join("0.smt2")).unwrap();
+    assert!(script.contains("(bvadd (_ bv0 4) a)"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resume_from_checkpoint() {
+    let test = test_dir("checkpoint");
+    let recipe = rule_file(
+        &test,
+        "checkpoint.recipe",
+        "(lang bv (vals 0 1) (vars a b) (ops (~) (& |)))\n(step base (recursive bv atoms 3))\n",
+    );
+    let dir = test.join("checkpoints");
+    let output = test.join("checkpoint.rules");
+    let args = [
+        "-d",
+        "bv4",
+        "--checkpoint",
+        dir.to_str().unwrap(),
+        "synth",
+        "--recipe",
+        &recipe,
+        "-o",
+        output.to_str().unwrap(),
+    ];
+    let (ok, _, log) = enumo(&args);
+    assert!(ok);
+    assert!(!log.contains("from a checkpoint"));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    let learned = fs::read_to_string(&output).unwrap();
+
+    let (ok, _, log) = enumo(&args);
+    assert!(ok);
+    assert_eq!(log.matches("from a checkpoint").count(), 3);
+    assert_eq!(fs::read_to_string(&output).unwrap(), learned);
+    fs::remove_dir_all(&test).unwrap();
+}
@@ -40,6 +40,7 @@ egg::define_language! {
 
 impl SynthLanguage for Exponential {
     type Constant = Rational;
+    const NAME: &'static str = "exponential";
 
     // cvec-less domain
     fn eval<'a, F>(&'a self, _cvec_len: usize, _get_cvec: F) -> CVec<Self>
@@ -85,9 +86,12 @@ impl SynthLanguage for Exponential {
         Exponential::Num(c)
     }
 
-    // no validation possible
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
+    // No validation possible: `exp`, `log` and `pow` of rationals are in
+    // general irrational, so `eval` cannot compute them over `Rational` cvecs
+    // (the domain is cvec-less, see `eval`) and `fuzz_validate` has nothing to
+    // compare, nor can z3 reason about them.
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        ValidationResult::Unknown
     }
 
     fn is_fast_forwarding() -> bool {
@@ -288,7 +292,7 @@ mod test {
             return;
         }
 
-        let herbie: Ruleset = Ruleset::from_file("baseline/herbie-exp.rules");
+        let herbie: Ruleset = Ruleset::from_file("baseline/herbie-exp.rules").unwrap();
 
         let start = Instant::now();
         let rules = make_rules();
@@ -1,285 +1,4 @@
-use ::enumo::*;
-use num::{ToPrimitive, Zero};
-use z3::ast::Ast;
-
-type Constant = i64;
-
-egg::define_language! {
-  pub enum Pred {
-    Lit(Constant),
-    "<" = Lt([Id;2]),
-    "<=" = Leq([Id;2]),
-    "==" = Eq([Id;2]),
-    "!=" = Neq([Id;2]),
-    "->" = Implies([Id; 2]),
-    "!" = Not(Id),
-    "-" = Neg(Id),
-    "&&" = And([Id;2]),
-    "||" = Or([Id;2]),
-    "^" = Xor([Id;2]),
-    "+" = Add([Id; 2]),
-    "-" = Sub([Id; 2]),
-    "*" = Mul([Id; 2]),
-    "/" = Div([Id; 2]),
-    "min" = Min([Id; 2]),
-    "max" = Max([Id; 2]),
-    "select" = Select([Id; 3]),
-    Var(Symbol),
-  }
-}
-
-impl SynthLanguage for Pred {
-    type Constant = Constant;
-
-    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
-    where
-        F: FnMut(&'a Id) -> &'a CVec<Self>,
-    {
-        let one = 1.to_i64().unwrap();
-        let zero = 0.to_i64().unwrap();
-        match self {
-            Pred::Lit(c) => vec![Some(c.clone()); cvec_len],
-            Pred::Lt([x, y]) => {
-                map!(get_cvec, x, y => if x < y {Some(one.clone())} else {Some(zero.clone())})
-            }
-            Pred::Leq([x, y]) => {
-                map!(get_cvec, x, y => if x <= y {Some(one.clone())} else {Some(zero.clone())})
-            }
-            Pred::Eq([x, y]) => {
-                map!(get_cvec, x, y => if x == y {Some(one.clone())} else {Some(zero.clone())})
-            }
-            Pred::Neq([x, y]) => {
-                map!(get_cvec, x, y => if x != y {Some(one.clone())} else {Some(zero.clone())})
-            }
-            Pred::Implies([x, y]) => {
-                map!(get_cvec, x, y => {
-                  let xbool = x.clone() != zero;
-                  let ybool = y.clone() != zero;
-                  if !xbool || ybool {Some(one.clone())} else {Some(zero.clone())}
-                })
-            }
-            Pred::Not(x) => {
-                map!(get_cvec, x => if x.clone() == zero { Some(one.clone())} else {Some(zero.clone())})
-            }
-            Pred::Neg(x) => map!(get_cvec, x => Some(-x)),
-            Pred::And([x, y]) => {
-                map!(get_cvec, x, y => {
-                    let xbool = x.clone() != zero;
-                    let ybool = y.clone() != zero;
-                    if xbool && ybool { Some(one.clone()) } else { Some(zero.clone()) }
-                })
-            }
-            Pred::Or([x, y]) => {
-                map!(get_cvec, x, y => {
-                    let xbool = x.clone() != zero;
-                    let ybool = y.clone() != zero;
-                    if xbool || ybool { Some(one.clone()) } else { Some(zero.clone()) }
-                })
-            }
-            Pred::Xor([x, y]) => {
-                map!(get_cvec, x, y => {
-                    let xbool = x.clone() != zero;
-                    let ybool = y.clone() != zero;
-                    if xbool ^ ybool { Some(one.clone()) } else { Some(zero.clone()) }
-                })
-            }
-            Pred::Add([x, y]) => map!(get_cvec, x, y => x.checked_add(*y)),
-            Pred::Sub([x, y]) => map!(get_cvec, x, y => x.checked_sub(*y)),
-            Pred::Mul([x, y]) => map!(get_cvec, x, y => x.checked_mul(*y)),
-            Pred::Div([x, y]) => map!(get_cvec, x, y => {
-              if y.is_zero() {
-                Some(zero.clone())
-              } else {
-                x.checked_div(*y)
-              }
-            }),
-            Pred::Min([x, y]) => map!(get_cvec, x, y => Some(x.min(y).clone())),
-            Pred::Max([x, y]) => map!(get_cvec, x, y => Some(x.max(y).clone())),
-            Pred::Select([x, y, z]) => map!(get_cvec, x, y, z => {
-              let xbool = x.clone() != zero;
-              if xbool {Some(y.clone())} else {Some(z.clone())}
-            }),
-            Pred::Var(_) => vec![],
-        }
-    }
-
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-        let consts = vec![
-            Some((-10).to_i64().unwrap()),
-            Some((-1).to_i64().unwrap()),
-            Some(0.to_i64().unwrap()),
-            Some(1.to_i64().unwrap()),
-            Some(2.to_i64().unwrap()),
-            Some(5.to_i64().unwrap()),
-            Some(100.to_i64().unwrap()),
-        ];
-        let cvecs = self_product(&consts, vars.len());
-
-        egraph.analysis.cvec_len = cvecs[0].len();
-
-        for (i, v) in vars.iter().enumerate() {
-            let id = egraph.add(Pred::Var(Symbol::from(v.clone())));
-            let cvec = cvecs[i].clone();
-            egraph[id].data.cvec = cvec;
-        }
-    }
-
-    fn to_var(&self) -> Option<Symbol> {
-        if let Pred::Var(sym) = self {
-            Some(*sym)
-        } else {
-            None
-        }
-    }
-
-    fn mk_var(sym: Symbol) -> Self {
-        Pred::Var(sym)
-    }
-
-    fn is_constant(&self) -> bool {
-        matches!(self, Pred::Lit(_))
-    }
-
-    fn mk_constant(c: Self::Constant) -> Self {
-        Pred::Lit(c)
-    }
-
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult {
-        let mut cfg = z3::Config::new();
-        cfg.set_timeout_msec(1000);
-        let ctx = z3::Context::new(&cfg);
-        let solver = z3::Solver::new(&ctx);
-        let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-        let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-        solver.assert(&lexpr._eq(&rexpr).not());
-        match solver.check() {
-            z3::SatResult::Unsat => ValidationResult::Valid,
-            z3::SatResult::Unknown => ValidationResult::Unknown,
-            z3::SatResult::Sat => ValidationResult::Invalid,
-        }
-    }
-}
-
-fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Pred]) -> z3::ast::Int<'a> {
-    let mut buf: Vec<z3::ast::Int> = vec![];
-    let zero = z3::ast::Int::from_i64(ctx, 0);
-    let one = z3::ast::Int::from_i64(ctx, 1);
-    for node in expr.as_ref().iter() {
-        match node {
-            Pred::Lit(c) => buf.push(z3::ast::Int::from_i64(ctx, c.to_i64().unwrap())),
-            Pred::Lt([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::lt(l, r), &one, &zero))
-            }
-            Pred::Leq([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), &one, &zero))
-            }
-            Pred::Eq([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::_eq(l, r), &one, &zero))
-            }
-            Pred::Neq([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::_eq(l, r), &zero, &one))
-            }
-            Pred::Implies([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
-                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &z3::ast::Bool::implies(&l_not_z, &r_not_z),
-                    &one,
-                    &zero,
-                ))
-            }
-            Pred::Not(x) => {
-                let l = &buf[usize::from(*x)];
-                buf.push(z3::ast::Bool::ite(&l._eq(&zero), &one, &zero))
-            }
-            Pred::Neg(x) => buf.push(z3::ast::Int::unary_minus(&buf[usize::from(*x)])),
-            Pred::And([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
-                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &z3::ast::Bool::and(ctx, &[&l_not_z, &r_not_z]),
-                    &one,
-                    &zero,
-                ))
-            }
-            Pred::Or([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
-                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &z3::ast::Bool::or(ctx, &[&l_not_z, &r_not_z]),
-                    &one,
-                    &zero,
-                ))
-            }
-            Pred::Xor([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                let l_not_z = z3::ast::Bool::not(&l._eq(&zero));
-                let r_not_z = z3::ast::Bool::not(&r._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &z3::ast::Bool::xor(&l_not_z, &r_not_z),
-                    &one,
-                    &zero,
-                ))
-            }
-            Pred::Add([x, y]) => buf.push(z3::ast::Int::add(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Pred::Sub([x, y]) => buf.push(z3::ast::Int::sub(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Pred::Mul([x, y]) => buf.push(z3::ast::Int::mul(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Pred::Div([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(
-                    &r._eq(&zero),
-                    &zero,
-                    &z3::ast::Int::div(l, r),
-                ))
-            }
-            Pred::Min([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), l, r))
-            }
-            Pred::Max([x, y]) => {
-                let l = &buf[usize::from(*x)];
-                let r = &buf[usize::from(*y)];
-                buf.push(z3::ast::Bool::ite(&z3::ast::Int::le(l, r), r, l))
-            }
-            Pred::Select([x, y, z]) => {
-                let cond = z3::ast::Bool::not(&buf[usize::from(*x)]._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &cond,
-                    &buf[usize::from(*y)],
-                    &buf[usize::from(*z)],
-                ))
-            }
-            Pred::Var(v) => buf.push(z3::ast::Int::new_const(ctx, v.to_string())),
-        }
-    }
-    buf.pop().unwrap()
-}
+use ::enumo::halide::Pred;
 
 #[cfg(test)]
 #[path = "./recipes/halide.rs"]
@@ -291,10 +10,8 @@ mod test {
     use std::time::{Duration, Instant};
 
     use enumo::{
-        enumo::{Filter, Metric, Ruleset, Workload},
-        logger,
-        recipe_utils::{recursive_rules, run_workload, Lang},
-        Limits,
+        enumo::{Ruleset, Scheduler},
+        logger, EGraph, Limits, SynthAnalysis, SynthLanguage,
     };
 
     #[test]
@@ -314,8 +31,9 @@ mod test {
         // real	0m2.707s
         // user	0m2.681s
         // sys	0m0.028s
-        let baseline: Ruleset<Pred> = Ruleset::from_file("baseline/halide.rules");
-        let oopsla_halide: Ruleset<Pred> = Ruleset::from_file("baseline/oopsla-halide.rules");
+        let baseline: Ruleset<Pred> = Ruleset::from_file("baseline/halide.rules").unwrap();
+        let oopsla_halide: Ruleset<Pred> =
+            Ruleset::from_file("baseline/oopsla-halide.rules").unwrap();
         let oopsla_duration = Duration::from_secs_f32(3.354);
 
         logger::write_baseline(&all_rules, "halide", &baseline, "halide", duration);
@@ -328,4 +46,38 @@ mod test {
             oopsla_duration,
         );
     }
+
+    #[test]
+    fn import_halide_rules() {
+        let source = "rewrite(-(-x), x) || rewrite(x - y < x, 0 < y) || \
+                      rewrite(min(x, c0) < c1, true, c0 < c1) || rewrite(x << 1, x * 2)";
+        let import = Ruleset::<Pred>::from_halide(source);
+        assert_eq!(
+            import.rules.to_str_vec(),
+            vec![
+                "(- (- ?a)) ==> ?a",
+                "(< (- ?a ?b) ?a) ==> (< 0 ?b)",
+                "(< (min ?a ?b) ?c) ==> 1 if (< ?b ?c)",
+            ]
+        );
+        assert_eq!(import.untranslatable.len(), 1);
+    }
+
+    #[test]
+    fn imported_guard_fires() {
+        let import = Ruleset::<Pred>::from_halide("rewrite(min(x, c0) < c1, true, c0 < c1)");
+        let rule = import.rules.0.values().next().unwrap();
+        assert!(rule.is_conditional());
+        assert!(rule.is_valid());
+
+        let mut egraph: EGraph<Pred, SynthAnalysis> = Default::default();
+        Pred::initialize_vars(&mut egraph, &["x".into()]);
+        let fires = egraph.add_expr(&"(< (min x 3) 5)".parse().unwrap());
+        let blocked = egraph.add_expr(&"(< (min x 5) 3)".parse().unwrap());
+        let out = Scheduler::Simple(Limits::deriving()).run(&egraph, &import.rules);
+
+        let one = out.lookup_expr(&"1".parse().unwrap());
+        assert_eq!(one, Some(out.find(fires)));
+        assert_ne!(one, Some(out.find(blocked)));
+    }
 }
@@ -163,6 +163,7 @@ egg::define_language! {
 
 impl SynthLanguage for CaddyAndFRep {
     type Constant = Constant;
+    const NAME: &'static str = "maxmin";
 
     fn is_fast_forwarding() -> bool {
         true
@@ -443,7 +444,7 @@ impl SynthLanguage for CaddyAndFRep {
         CaddyAndFRep::Lit(c)
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
         ValidationResult::Valid
     }
 }
@@ -479,6 +480,8 @@ mod tests {
             iter: 3,
             node: 10000000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
 
         let eg_init = atoms3.to_egraph();
@@ -509,6 +512,8 @@ mod tests {
             iter: 3,
             node: 1000000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
         let nat_rules = get_nat_rules();
 
@@ -1,490 +1,133 @@
-use egg::Rewrite;
-use num::{
-    rational::Ratio, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Signed, ToPrimitive, Zero,
-};
 use ::enumo::{
     enumo::{Rule, Ruleset, Scheduler, Workload},
+    rational::{mk_rat, Constant, Math},
     *,
 };
-use std::{ops::*, time::Instant};
+use num::Signed;
+use std::time::Instant;
 use symbolic_expressions::parser::parse_str;
 use symbolic_expressions::Sexp;
-use z3::ast::Ast;
 #[path = "./recipes/rational_best.rs"]
 pub mod rational_best;
 #[path = "./recipes/rational_replicate.rs"]
 pub mod rational_replicate;
 
-/// define `Constant` for rationals.
-pub type Constant = Ratio<i64>;
-
-fn mk_rat(n: i64, d: i64) -> Constant {
-    if d.is_zero() {
-        panic!("mk_rat: denominator is zero!");
-    }
-    Ratio::new(n, d)
-}
-
-egg::define_language! {
-  pub enum Math {
-    "+" = Add([Id; 2]),
-    "-" = Sub([Id; 2]),
-    "*" = Mul([Id; 2]),
-    "/" = Div([Id; 2]),
-    "~" = Neg(Id),
-    "fabs" = Abs(Id),
-    "if" = If([Id; 3]),
-    Lit(Constant),
-    Var(egg::Symbol),
-  }
-}
-
-impl SynthLanguage for Math {
-    type Constant = Constant;
-
-    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
-    where
-        F: FnMut(&'a Id) -> &'a CVec<Self>,
-    {
-        match self {
-            Math::Add([x, y]) => map!(get_cvec, x, y => x.checked_add(y)),
-            Math::Sub([x, y]) => map!(get_cvec, x, y => x.checked_sub(y)),
-            Math::Mul([x, y]) => map!(get_cvec, x, y => x.checked_mul(y)),
-            Math::Div([x, y]) => map!(get_cvec, x, y => {
-                x.checked_div(y)
-            }),
-            Math::Neg(x) => map!(get_cvec, x => Some(-x)),
-            Math::Abs(a) => map!(get_cvec, a => Some(a.abs())),
-            Math::Lit(c) => vec![Some(c.clone()); cvec_len],
-            Math::Var(_) => vec![],
-            Math::If([x, y, z]) => get_cvec(x)
-                .iter()
-                .zip(get_cvec(y).iter())
-                .zip(get_cvec(z).iter())
-                .map(|tup| {
-                    let ((x, y), z) = tup;
-                    if let Some(cond) = x {
-                        if !cond.is_zero() {
-                            *y
-                        } else {
-                            *z
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>(),
+fn all_denominators(sexp: Sexp) -> HashSet<String> {
+    let mut res = HashSet::<String>::default();
+    if let Sexp::List(list) = sexp {
+        if list[0] == Sexp::String("/".to_string()) {
+            res.insert(list[2].to_string());
         }
-    }
 
-    fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
-    where
-        F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
-    {
-        let mut get_const = |x: &'a Id| {
-            let ival = get_interval(x);
-            if ival.low == ival.high {
-                ival.low.clone()
-            } else {
-                None
-            }
-        };
-        match self {
-            Math::Lit(n) => Some(n.clone()),
-            Math::Var(_) => None,
-            Math::Neg(x) => get_const(x).map(|c| -c),
-            Math::Abs(a) => get_const(a).map(|c| c.abs()),
-            Math::Add([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_add(&y),
-                _ => None,
-            },
-            Math::Sub([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_sub(&y),
-                _ => None,
-            },
-            Math::Mul([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_mul(&y),
-                _ => None,
-            },
-            Math::Div([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_div(&y),
-                _ => None,
-            },
-            Math::If([x, y, z]) => {
-                if let Some(x) = get_const(x) {
-                    if !x.is_zero() {
-                        get_const(y)
-                    } else {
-                        get_const(z)
-                    }
-                } else {
-                    None
-                }
-            }
-        }
-        .map(|c| Interval::new(Some(c.clone()), Some(c)))
-        .unwrap_or_default()
-    }
-
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-        let consts = vec![
-            Some(mk_rat(-1, 1)),
-            Some(mk_rat(0, 1)),
-            Some(mk_rat(1, 1)),
-            Some(mk_rat(2, 1)),
-            Some(mk_rat(-3, 1)),
-        ];
-        let cvecs = self_product(&consts, vars.len());
-
-        egraph.analysis.cvec_len = cvecs[0].len();
-
-        for (i, v) in vars.iter().enumerate() {
-            let id = egraph.add(Math::Var(Symbol::from(v.clone())));
-            let cvec = cvecs[i].clone();
-            egraph[id].data.cvec = cvec;
+        for s in list {
+            res.extend(all_denominators(s));
         }
     }
 
-    fn mk_var(sym: egg::Symbol) -> Self {
-        Math::Var(sym)
-    }
-
-    fn to_var(&self) -> Option<Symbol> {
-        match self {
-            Math::Var(v) => Some(*v),
-            _ => None,
-        }
-    }
-
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult {
-        // TODO if you drop variables, it's unsound because
-        // we may have lost an error
-        /*if lhs.vars().into_iter().collect::<HashSet<Var>>()
-            != rhs.vars().into_iter().collect::<HashSet<Var>>()
-        {
-            return ValidationResult::Invalid;
-        }*/
-
-        let mut cfg = z3::Config::new();
-        cfg.set_timeout_msec(1000);
-        let ctx = z3::Context::new(&cfg);
-        let solver = z3::Solver::new(&ctx);
-        let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-        let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-        let lhs_denom = Self::error_conditions(
-            &ctx,
-            Self::pat_to_sexp(lhs),
-            z3::ast::Bool::from_bool(&ctx, true),
-        );
-        let rhs_denom = Self::error_conditions(
-            &ctx,
-            Self::pat_to_sexp(rhs),
-            z3::ast::Bool::from_bool(&ctx, true),
-        );
-
-        let mut assert_equal = lexpr._eq(&rexpr);
-
-        for condition in lhs_denom.iter().chain(rhs_denom.iter()) {
-            assert_equal = condition.not().implies(&assert_equal);
-        }
-
-        let rhs_errors =
-            z3::ast::Bool::or(&ctx, &rhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
-        let lhs_errors =
-            z3::ast::Bool::or(&ctx, &lhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
-        let error_preserved = rhs_errors.iff(&lhs_errors);
-        let assertion = z3::ast::Bool::and(&ctx, &[&assert_equal, &error_preserved]);
-
-        solver.assert(&assertion.clone().not());
-        let res = Self::z3_res_to_validationresult(solver.check());
-        /*if let ValidationResult::Valid = res {
-            eprintln!("verifying {} => {}", lhs, rhs);
-        eprintln!("assertion: {}", assertion);
-        }*/
-        res
-    }
-
-    fn is_constant(&self) -> bool {
-        matches!(self, Math::Lit(_))
-    }
-
-    fn mk_constant(c: Self::Constant) -> Self {
-        Math::Lit(c)
-    }
+    res
 }
 
-impl Math {
-    fn _one_of_errors(ctx: &z3::Context, denoms: HashSet<String>) -> z3::ast::Bool {
-        let zero_z3 = z3::ast::Real::from_real(&ctx, 0, 1);
-
-        let mut one_of_rhs_errors = z3::ast::Bool::from_bool(ctx, false);
-        for d in denoms {
-            let expr = egg_to_z3(
-                ctx,
-                Self::instantiate(&d.to_string().parse::<Pattern<Math>>().unwrap()).as_ref(),
-            );
-            one_of_rhs_errors = z3::ast::Bool::or(ctx, &[&one_of_rhs_errors, &expr._eq(&zero_z3)]);
-        }
-        one_of_rhs_errors
-    }
-
-    fn z3_res_to_validationresult(res: z3::SatResult) -> ValidationResult {
-        match res {
-            z3::SatResult::Unsat => ValidationResult::Valid,
-            z3::SatResult::Sat => ValidationResult::Invalid,
-            z3::SatResult::Unknown => ValidationResult::Unknown,
+fn add_condition(rule: Rule<Math>) -> Option<Rule<Math>> {
+    let lhs_sexp = parse_str(&rule.lhs.to_string()).unwrap();
+    let rhs_sexp = parse_str(&rule.rhs.to_string()).unwrap();
+
+    let lhs_denoms = all_denominators(lhs_sexp.clone());
+    let rhs_denoms = all_denominators(rhs_sexp.clone());
+    let intersection: HashSet<String> = lhs_denoms.intersection(&rhs_denoms).cloned().collect();
+    let all_denoms: Vec<String> = lhs_denoms
+        .union(&rhs_denoms)
+        .cloned()
+        .collect::<HashSet<String>>()
+        .difference(&intersection)
+        .cloned()
+        .collect();
+
+    if all_denoms.is_empty() {
+        None
+    } else {
+        // The rule is guarded by the product of the denominators that only
+        // appear on one side, which is nonzero iff all of them are.
+        let mut iterator = all_denoms.iter();
+        let mut condition: Sexp = parse_str(iterator.next().unwrap()).unwrap();
+        for denom in iterator {
+            condition = Sexp::List(vec![
+                Sexp::String("*".to_string()),
+                condition,
+                parse_str(denom).unwrap(),
+            ]);
         }
-    }
+        let cond = condition.to_string().parse::<Pattern<Math>>().unwrap();
 
-    fn pat_to_sexp(pat: &Pattern<Math>) -> Sexp {
-        parse_str(&pat.to_string()).unwrap()
+        Rule::new_conditional(&rule.lhs, &rule.rhs, Some(&cond))
     }
+}
 
-    fn all_denominators(sexp: Sexp) -> HashSet<String> {
-        let mut res = HashSet::<String>::default();
-        match sexp {
-            Sexp::List(list) => {
-                if list[0] == Sexp::String("/".to_string()) {
-                    res.insert(list[2].to_string());
-                }
+fn run_workload_conditional(
+    workload: Workload,
+    prior: Ruleset<Math>,
+    limits: Limits,
+    fast_match: bool,
+) -> Ruleset<Math> {
+    let t = Instant::now();
+
+    println!("Compressing workload with {} prior rules", prior.len());
+    let egraph = workload.to_egraph::<Math>();
+    let compressed = Scheduler::Compress(limits).run(&egraph, &prior);
+
+    let mut candidates = if fast_match {
+        Ruleset::fast_cvec_match(&compressed)
+    } else {
+        Ruleset::cvec_match(&compressed)
+    };
 
-                for s in list {
-                    res.extend(Self::all_denominators(s));
+    let num_prior = prior.len();
+    let minimized = candidates.minimize(prior.clone(), Scheduler::Compress(limits));
+    let chosen = minimized.chosen;
+    let invalid = minimized.invalid.union(&minimized.skipped_reverses);
+
+    println!(
+        "Found {} valid and {} invalid rules",
+        chosen.len(),
+        invalid.len()
+    );
+    // here's the conditional stuff
+    let mut with_condition = Ruleset::<Math>(
+        invalid
+            .0
+            .iter()
+            .filter_map(|r| {
+                if let Some(rewritten) = add_condition(r.1.clone()) {
+                    Some((rewritten.name.clone(), rewritten))
+                } else {
+                    None
                 }
-            }
-            _ => (),
-        }
-
-        res
-    }
-
-    fn add_condition(rule: Rule<Math>) -> Option<Rule<Math>> {
-        let lhs_sexp = parse_str(&rule.lhs.to_string()).unwrap();
-        let rhs_sexp = parse_str(&rule.rhs.to_string()).unwrap();
-
-        let lhs_denoms = Self::all_denominators(lhs_sexp.clone());
-        let rhs_denoms = Self::all_denominators(rhs_sexp.clone());
-        let intersection: HashSet<String> = lhs_denoms.intersection(&rhs_denoms).cloned().collect();
-        let all_denoms: Vec<String> = lhs_denoms
-            .union(&rhs_denoms)
-            .cloned()
-            .collect::<HashSet<String>>()
-            .difference(&intersection)
-            .cloned()
-            .collect();
-
-        if all_denoms.is_empty() {
-            None
-        } else {
-            let mut iterator = all_denoms.iter();
-            let mut condition: Sexp = parse_str(iterator.next().unwrap()).unwrap();
-
-            // TODO doesn't handle multiple denominators
-            if let Some(_) = iterator.next() {
-                return None;
-            }
-            for denom in iterator {
-                condition = Sexp::List(vec![
-                    Sexp::String("and".to_string()),
-                    condition,
-                    parse_str(denom).unwrap(),
-                ]);
-            }
-            let rhs = Sexp::List(vec![
-                Sexp::String("if".to_string()),
-                condition,
-                rhs_sexp,
-                lhs_sexp,
-            ])
-            .to_string()
-            .parse::<Pattern<Math>>()
-            .unwrap();
-
-            let name = format!("{} ==> {}", rule.lhs, rhs);
-            let rewrite = Rewrite::new(name.clone(), rule.lhs.clone(), rhs.clone()).unwrap();
-            Some(Rule {
-                lhs: rule.lhs,
-                rhs,
-                name: name.into(),
-                rewrite,
             })
-        }
-    }
-
-    /// Given an expression, returns a vector
-    /// of conditions for when the expression
-    /// divides by zero.
-    ///
-    /// For example, the expression
-    /// (/ 1 x) errors when (== x 0)
-    ///
-    /// The path variable stores the path conditions for reaching this expression.
-    ///
-    /// For example,
-    /// In (if x y z), the expression y
-    /// has condition (!= x 0)
-    fn error_conditions<'a>(
-        ctx: &'a z3::Context,
-        sexp: Sexp,
-        path: z3::ast::Bool<'a>,
-    ) -> Vec<z3::ast::Bool<'a>> {
-        let mut res = Vec::<z3::ast::Bool<'a>>::default();
-        match sexp {
-            Sexp::List(list) => {
-                if list[0] == Sexp::String("/".to_string()) {
-                    let denom = list[2].to_string();
-                    let expr = egg_to_z3(
-                        &ctx,
-                        Self::instantiate(&denom.to_string().parse::<Pattern<Math>>().unwrap())
-                            .as_ref(),
-                    );
-                    let is_zero = expr._eq(&z3::ast::Real::from_real(ctx, 0, 1));
-
-                    res.push(z3::ast::Bool::and(ctx, &[&is_zero, &path]));
-                }
-
-                if list[0] == Sexp::String("if".to_string()) {
-                    let cond_real = egg_to_z3(
-                        &ctx,
-                        Self::instantiate(&list[1].to_string().parse::<Pattern<Math>>().unwrap())
-                            .as_ref(),
-                    );
-                    let zero = z3::ast::Real::from_real(ctx, 0, 1);
-                    let new_path_pos = z3::ast::Bool::and(
-                        &ctx,
-                        &[&path, &z3::ast::Bool::not(&cond_real._eq(&zero))],
-                    );
-                    let new_path_neg = z3::ast::Bool::and(&ctx, &[&path, &cond_real._eq(&zero)]);
-                    res.extend(Self::error_conditions(ctx, list[2].clone(), new_path_pos));
-                    res.extend(Self::error_conditions(ctx, list[3].clone(), new_path_neg));
-                } else {
-                    for s in list {
-                        res.extend(Self::error_conditions(ctx, s, path.clone()));
-                    }
-                };
-            }
-            Sexp::String(_) => (),
-            Sexp::Empty => (),
-        }
-
-        res
-    }
-
-    fn run_workload_conditional(
-        workload: Workload,
-        prior: Ruleset<Self>,
-        limits: Limits,
-        fast_match: bool,
-    ) -> Ruleset<Self> {
-        let t = Instant::now();
-
-        println!("Compressing workload with {} prior rules", prior.len());
-        let egraph = workload.to_egraph::<Self>();
-        let mut compressed = Scheduler::Compress(limits).run(&egraph, &prior);
-
-        let mut candidates = if fast_match {
-            Ruleset::fast_cvec_match(&compressed)
-        } else {
-            Ruleset::cvec_match(&mut compressed)
-        };
-
-        let num_prior = prior.len();
-        let (chosen, invalid) = candidates.minimize(prior.clone(), Scheduler::Compress(limits));
-
-        println!(
-            "Found {} valid and {} invalid rules",
-            chosen.len(),
-            invalid.len()
-        );
-        // here's the conditional stuff
-        let mut with_condition = Ruleset::<Math>(
-            invalid
-                .0
-                .iter()
-                .filter_map(|r| {
-                    if let Some(rewritten) = Self::add_condition(r.1.clone()) {
-                        Some((rewritten.name.clone(), rewritten))
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
-        );
+            .collect(),
+    );
 
-        println!(
-            "Instrumented {} rules with conditions",
-            with_condition.len()
-        );
+    println!(
+        "Instrumented {} rules with conditions",
+        with_condition.len()
+    );
 
-        let chosen_conditional = with_condition
-            .minimize(prior.union(&chosen), Scheduler::Compress(limits))
-            .0;
+    let chosen_conditional = with_condition
+        .minimize(prior.union(&chosen), Scheduler::Compress(limits))
+        .chosen;
 
-        let result = chosen.union(&chosen_conditional);
+    let result = chosen.union(&chosen_conditional);
 
-        let time = t.elapsed().as_secs_f64();
-        println!(
-            "Learned {} bidirectional rewrites ({} total rewrites) in {} using {} prior rewrites",
-            result.bidir_len(),
-            result.len(),
-            time,
-            num_prior
-        );
-
-        result.pretty_print();
+    let time = t.elapsed().as_secs_f64();
+    println!(
+        "Learned {} bidirectional rewrites ({} total rewrites) in {} using {} prior rewrites",
+        result.bidir_len(),
+        result.len(),
+        time,
+        num_prior
+    );
 
-        result
-    }
-}
+    println!("{}", result);
 
-fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Math]) -> z3::ast::Real<'a> {
-    let mut buf: Vec<z3::ast::Real> = vec![];
-    for node in expr.as_ref().iter() {
-        match node {
-            Math::Add([x, y]) => buf.push(z3::ast::Real::add(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Math::Sub([x, y]) => buf.push(z3::ast::Real::sub(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Math::Mul([x, y]) => buf.push(z3::ast::Real::mul(
-                ctx,
-                &[&buf[usize::from(*x)], &buf[usize::from(*y)]],
-            )),
-            Math::Div([x, y]) => buf.push(z3::ast::Real::div(
-                &buf[usize::from(*x)],
-                &buf[usize::from(*y)],
-            )),
-            Math::Neg(x) => buf.push(z3::ast::Real::unary_minus(&buf[usize::from(*x)])),
-            Math::Abs(a) => {
-                let inner = &buf[usize::from(*a)].clone();
-                let zero = z3::ast::Real::from_real(ctx, 0, 1);
-                buf.push(z3::ast::Bool::ite(
-                    &z3::ast::Real::le(inner, &zero),
-                    &z3::ast::Real::unary_minus(inner),
-                    &inner,
-                ));
-            }
-            Math::Lit(c) => buf.push(z3::ast::Real::from_real(
-                ctx,
-                (c.numer()).to_i32().unwrap(),
-                (c.denom()).to_i32().unwrap(),
-            )),
-            Math::Var(v) => buf.push(z3::ast::Real::new_const(ctx, v.to_string())),
-            Math::If([x, y, z]) => {
-                let zero = z3::ast::Real::from_real(ctx, 0, 1);
-                let cond = z3::ast::Bool::not(&buf[usize::from(*x)]._eq(&zero));
-                buf.push(z3::ast::Bool::ite(
-                    &cond,
-                    &buf[usize::from(*y)],
-                    &buf[usize::from(*z)],
-                ))
-            }
-        }
-    }
-    buf.pop().unwrap()
+    result
 }
 
 // Interval helpers
@@ -525,14 +168,14 @@ fn sign(interval: &Interval<Constant>) -> Sign {
 }
 
 fn neg(interval: &Interval<Constant>) -> Interval<Constant> {
-    let low = interval.low.clone();
-    let high = interval.high.clone();
+    let low = interval.low;
+    let high = interval.high;
     Interval::new(high.map(|x| -x), low.map(|x| -x))
 }
 
 fn _abs(interval: &Interval<Constant>) -> Interval<Constant> {
-    let low = interval.low.clone();
-    let high = interval.high.clone();
+    let low = interval.low;
+    let high = interval.high;
 
     match (low, high) {
         (None, None) => Interval::new(None, None),
@@ -557,12 +200,10 @@ fn _abs(interval: &Interval<Constant>) -> Interval<Constant> {
                 } else {
                     Interval::new(Some(-x), Some(y))
                 }
+            } else if y.is_negative() {
+                Interval::new(Some(x), Some(-y))
             } else {
-                if y.is_negative() {
-                    Interval::new(Some(x), Some(-y))
-                } else {
-                    Interval::new(Some(x), Some(y))
-                }
+                Interval::new(Some(x), Some(y))
             }
         }
     }
@@ -595,22 +236,22 @@ fn mul(a: &Interval<Constant>, b: &Interval<Constant>) -> Interval<Constant> {
         }
 
         (Sign::Positive, Sign::ContainsZero) => {
-            Interval::new(mul_opts(a.high.clone(), b.low), mul_opts(a.high, b.high))
+            Interval::new(mul_opts(a.high, b.low), mul_opts(a.high, b.high))
         }
         (Sign::ContainsZero, Sign::Positive) => {
-            Interval::new(mul_opts(a.low, b.high.clone()), mul_opts(a.high, b.high))
+            Interval::new(mul_opts(a.low, b.high), mul_opts(a.high, b.high))
         }
 
         (Sign::Negative, Sign::ContainsZero) => {
-            Interval::new(mul_opts(a.low.clone(), b.high), mul_opts(a.low, b.low))
+            Interval::new(mul_opts(a.low, b.high), mul_opts(a.low, b.low))
         }
         (Sign::ContainsZero, Sign::Negative) => {
-            Interval::new(mul_opts(a.high, b.low.clone()), mul_opts(a.low, b.low))
+            Interval::new(mul_opts(a.high, b.low), mul_opts(a.low, b.low))
         }
 
         (Sign::ContainsZero, Sign::ContainsZero) => {
-            let al_bh = mul_opts(a.low.clone(), b.high.clone());
-            let ah_bl = mul_opts(a.high.clone(), b.low.clone());
+            let al_bh = mul_opts(a.low, b.high);
+            let ah_bl = mul_opts(a.high, b.low);
             let min = al_bh.zip(ah_bl).map(|(x, y)| x.min(y));
 
             let ah_bh = mul_opts(a.high, b.high);
@@ -800,8 +441,8 @@ pub mod test {
             return;
         }
 
-        let ruler1: Ruleset<Math> = Ruleset::from_file("baseline/rational.rules");
-        let herbie: Ruleset<Math> = Ruleset::from_file("baseline/herbie-rational.rules");
+        let ruler1: Ruleset<Math> = Ruleset::from_file("baseline/rational.rules").unwrap();
+        let herbie: Ruleset<Math> = Ruleset::from_file("baseline/herbie-rational.rules").unwrap();
 
         let start = Instant::now();
         let replicate_rules = replicate_ruler1_recipe();
@@ -832,7 +473,7 @@ pub mod test {
                 .0
                 .iter()
                 .filter_map(|r| {
-                    if r.1.rhs.to_string().starts_with("(if") {
+                    if r.1.is_conditional() || r.1.rhs.to_string().starts_with("(if") {
                         None
                     } else {
                         Some(r.0.to_string().clone())
@@ -892,7 +533,7 @@ pub mod test {
             Limits::minimize(),
             false,
         );
-        guarded_rules.to_file("guard.rules");
+        guarded_rules.to_file("guard.rules").unwrap();
         assert!(guarded_rules
             .0
             .contains_key("(if ?a 0 (/ 0 ?a)) ==> (/ 0 ?a)"));
@@ -912,10 +553,48 @@ pub mod test {
             iter: 3,
             node: 300000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
         let test = Workload::new(&["(if a b b)", "b"]);
         let test_rules: Ruleset<Math> =
             run_workload(test, Ruleset::default(), limits, limits, false);
         assert_eq!(test_rules.len(), 1);
     }
+
+    #[test]
+    fn guard_needs_exact_value() {
+        let (rule, _) =
+            Rule::<Math>::from_string("(+ ?a ?b) ==> (+ ?b ?a) if (+ ?a 4)").unwrap();
+        let mut rules = Ruleset::default();
+        rules.add(rule);
+
+        let mut egraph: EGraph<Math, SynthAnalysis> = Default::default();
+        Math::initialize_vars(&mut egraph, &["x".into(), "y".into()]);
+        for term in ["(+ x y)", "(+ 3 y)", "(+ -4 y)"] {
+            egraph.add_expr(&term.parse().unwrap());
+        }
+        let out = Scheduler::Simple(Limits::deriving()).run(&egraph, &rules);
+
+        // (+ x 4) is nonzero on every sample of x, but not for x = -4
+        assert!(out.lookup_expr(&"(+ y x)".parse().unwrap()).is_none());
+        assert!(out.lookup_expr(&"(+ y 3)".parse().unwrap()).is_some());
+        assert!(out.lookup_expr(&"(+ y -4)".parse().unwrap()).is_none());
+        // The guards are evaluated, not added to the e-graph
+        assert!(out.lookup_expr(&"(+ x 4)".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn counterexample_values() {
+        let lhs: Pattern<Math> = "(/ ?a ?a)".parse().unwrap();
+        let rhs: Pattern<Math> = "1".parse().unwrap();
+        let cex = match Math::validate(&lhs, &rhs) {
+            ValidationResult::Invalid(Some(cex)) => cex,
+            res => panic!("expected a counterexample, got {:?}", res),
+        };
+        assert_eq!(cex.get("a".into()), Some(&mk_rat(0, 1)));
+        assert_eq!(cex.lhs, None);
+        assert_eq!(cex.rhs, Some(mk_rat(1, 1)));
+        assert_eq!(cex.to_string(), "a = 0: lhs = undefined, rhs = 1");
+    }
 }
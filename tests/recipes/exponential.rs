@@ -15,6 +15,8 @@ fn limits() -> Limits {
         iter: 3,
         node: 2_000_000,
         match_: 200_000,
+        time: None,
+        max_egraph_nodes: None,
     }
 }
 
@@ -118,6 +118,8 @@ pub fn halide_rules() -> Ruleset<Pred> {
             iter: 1,
             node: 100_000,
             match_: 100_000,
+            time: None,
+            max_egraph_nodes: None,
         },
         true,
     );
@@ -132,6 +134,8 @@ pub fn halide_rules() -> Ruleset<Pred> {
             iter: 1,
             node: 100_000,
             match_: 100_000,
+            time: None,
+            max_egraph_nodes: None,
         },
         true,
     );
@@ -31,7 +31,7 @@ pub fn best_enumo_recipe() -> Ruleset<Math> {
 
     let factor_div = Workload::new(["(/ v v)"]).plug("v", &factor_term);
 
-    let factor_rules = Math::run_workload_conditional(factor_div, rules.clone(), limits, false);
+    let factor_rules = run_workload_conditional(factor_div, rules.clone(), limits, false);
     // don't include rules that already contain division
     let factor_rules_filtered = Ruleset(
         factor_rules
@@ -11,8 +11,11 @@ pub fn trig_rules() -> Ruleset<Trig> {
         iter: 3,
         node: 2000000,
         match_: 200_000,
+        time: None,
+        max_egraph_nodes: None,
     };
-    let mut prior: Ruleset<Trig> = Ruleset::from_file("scripts/oopsla21/trig/complex.rules");
+    let mut prior: Ruleset<Trig> =
+        Ruleset::from_file("scripts/oopsla21/trig/complex.rules").unwrap();
     prior.extend(prior_rules());
 
     let no_trig_2x = Filter::Invert(Box::new(Filter::Or(vec![
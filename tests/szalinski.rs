@@ -6,7 +6,6 @@ use ::enumo::{
     *,
 };
 use std::hash::Hash;
-use std::usize;
 
 pub type Constant = i64;
 
@@ -143,6 +142,7 @@ fn is_caddy(node: &CF) -> bool {
 
 impl SynthLanguage for CF {
     type Constant = Constant;
+    const NAME: &'static str = "szalinski";
 
     fn is_fast_forwarding() -> bool {
         true
@@ -198,7 +198,7 @@ impl SynthLanguage for CF {
         CF::Literal(c)
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
         ValidationResult::Valid
     }
 }
@@ -271,6 +271,8 @@ mod tests {
             iter: 4,
             node: 10_000_000,
             match_: 1_000_000,
+            time: None,
+            max_egraph_nodes: None,
         };
 
         for i in 2..4 {
@@ -281,7 +283,7 @@ mod tests {
             learned_rules.extend(chosen);
         }
 
-        learned_rules.pretty_print();
+        println!("{}", learned_rules);
         export_print(&learned_rules);
 
         let expected: Ruleset<CF> = Ruleset::new(&[
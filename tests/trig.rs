@@ -147,6 +147,7 @@ egg::define_language! {
 
 impl SynthLanguage for Trig {
     type Constant = Real;
+    const NAME: &'static str = "trig";
 
     fn is_fast_forwarding() -> bool {
         true
@@ -306,7 +307,7 @@ impl SynthLanguage for Trig {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
         ValidationResult::Valid
     }
 }
@@ -355,7 +356,7 @@ mod test {
             return;
         }
 
-        let herbie: Ruleset<Trig> = Ruleset::from_file("baseline/herbie-trig.rules");
+        let herbie: Ruleset<Trig> = Ruleset::from_file("baseline/herbie-trig.rules").unwrap();
 
         let start = Instant::now();
         let rules = trig_rules();
@@ -366,13 +367,16 @@ mod test {
 
     #[test]
     fn simple() {
-        let complex: Ruleset<Trig> = Ruleset::from_file("scripts/oopsla21/trig/complex.rules");
+        let complex: Ruleset<Trig> =
+            Ruleset::from_file("scripts/oopsla21/trig/complex.rules").unwrap();
         assert_eq!(complex.len(), 57);
 
         let limits = Limits {
             iter: 3,
             node: 2000000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
 
         let terms = Workload::new([
@@ -431,7 +435,7 @@ mod test {
         candidates.extend(Ruleset::extract_candidates(&g2, &g3));
 
         // let (sound, _) = candidates.partition(|r| r.is_valid());
-        let (sound, _) = candidates.minimize(minimize.rules, minimize.scheduler);
+        let sound = candidates.minimize(minimize.rules, minimize.scheduler).chosen;
 
         logger::write_ff_phase(phase1, phase2, phase3, start.elapsed(), &sound);
 
@@ -449,8 +453,10 @@ mod test {
             iter: 3,
             node: 300000,
             match_: 200_000,
+            time: None,
+            max_egraph_nodes: None,
         };
-        let mut all = Ruleset::from_file("scripts/oopsla21/trig/complex.rules");
+        let mut all = Ruleset::from_file("scripts/oopsla21/trig/complex.rules").unwrap();
         all.extend(prior_rules());
         all.extend(Trig::get_exploratory_rules());
         let mut all_but_exploratory = all.clone();